@@ -1,7 +1,9 @@
-use intcode;
+use intcode::patch::PatchSet;
+use intcode::symbolic::Solver;
 
 
-const GRAV_PROG: &'static str = include_str!("grav-prog.txt");
+const GRAV_PROG: &str = include_str!("grav-prog.txt");
+const PATCHES: &str = include_str!("patches.txt");
 
 
 pub fn part1() {
@@ -9,9 +11,13 @@ pub fn part1() {
     let mut prog = intcode::parse_prog(GRAV_PROG)
         .unwrap();
 
-    // Restore to state just before spontaneous combustion
-    prog[1] = 12;
-    prog[2] = 2;
+    let patches: PatchSet = PATCHES.parse()
+        .unwrap();
+
+    patches.get("alarm-1202")
+        .unwrap()
+        .apply(&mut prog)
+        .unwrap_or_else(|err| panic!("{}", err));
 
     intcode::eval(&mut prog)
         .unwrap();
@@ -21,23 +27,16 @@ pub fn part1() {
 
 pub fn part2() {
 
-    'outer: for i in 0..100 {
-        for j in 0..100 {
-
-            let mut prog = intcode::parse_prog(GRAV_PROG)
-                .unwrap();
-
-            // Set noun and verb
-            prog[1] = i;
-            prog[2] = j;
+    let prog = intcode::parse_prog(GRAV_PROG)
+        .unwrap();
 
-            intcode::eval(&mut prog)
-                .unwrap();
+    // Solve for noun and verb rather than trying every combination
+    let res = Solver::new(&prog)
+        .var(1, 0..=99)
+        .var(2, 0..=99)
+        .solve(0, 19690720)
+        .unwrap()
+        .expect("no noun and verb produce the target");
 
-            if prog[0] == 19690720 {
-                println!("{}", 100 * i + j);
-                break 'outer;
-            }
-        }
-    }
+    println!("{}", 100 * res[0] + res[1]);
 }
@@ -1,7 +1,6 @@
-use std::io::{self, BufRead};
 use std::convert::TryFrom;
 
-use intcode;
+use intcode::{Error, InputError, IoHandler};
 
 enum Operation {
     Add,
@@ -14,13 +13,18 @@ enum Operation {
     Equal,
 }
 
-fn param_is_immediate(op: isize, param: usize) -> bool {
+fn param_mode(op: isize, param: usize) -> char {
 
     let op = format!("{:0>10}", op); // limits opcodes to 10 digits
 
     let flag_idx = op.len() - 3 - param;
 
-    &op[flag_idx..(flag_idx+1)] == "1"
+    op.as_bytes()[flag_idx] as char
+}
+
+fn param_is_immediate(op: isize, param: usize) -> bool {
+
+    param_mode(op, param) == '1'
 }
 
 // TODO: use this everywhere
@@ -39,7 +43,9 @@ fn load_param(prog: &[isize], ip: usize, param: usize) -> isize {
 
 impl Operation {
 
-    fn eval(&self, prog: &mut [isize], ip: usize) -> Option<usize> {
+    fn eval<H>(&self, prog: &mut [isize], ip: usize, io: &mut H) -> Result<Option<usize>, Error>
+    where H: IoHandler
+    {
 
         let op = prog[ip];
 
@@ -77,11 +83,7 @@ impl Operation {
 
             Self::Input => {
 
-                let mut input = String::new();
-                io::stdin().read_line(&mut input)
-                    .unwrap();
-                let val: isize = input.trim().parse()
-                    .unwrap();
+                let val = io.input()?;
 
                 let dest = usize::try_from(prog[ip + 1]).unwrap();
                 prog[dest] = val;
@@ -96,7 +98,7 @@ impl Operation {
                     prog[addr]
                 };
 
-                println!("{}", val);
+                io.output(val);
             },
 
             Self::JumpTrue => {
@@ -106,7 +108,7 @@ impl Operation {
                 if val != 0 {
                     let dest = usize::try_from(load_param(prog, ip, 1))
                         .unwrap();
-                    return Some(dest);
+                    return Ok(Some(dest));
                 }
             },
 
@@ -117,7 +119,7 @@ impl Operation {
                 if val == 0 {
                     let dest = usize::try_from(load_param(prog, ip, 1))
                         .unwrap();
-                    return Some(dest);
+                    return Ok(Some(dest));
                 }
             },
 
@@ -142,7 +144,32 @@ impl Operation {
             },
         }
 
-        None
+        Ok(None)
+    }
+
+    /// Index of the parameter written to, if any
+    fn dest(&self) -> Option<usize> {
+
+        match self {
+            Self::Add | Self::Multiply | Self::Less | Self::Equal => Some(2),
+            Self::Input => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Checks parameter modes against the day 5 rules: position or immediate
+    /// mode, but never immediate mode for writes
+    fn check_modes(&self, op: isize) -> Result<(), Error> {
+
+        for param in 0..self.size() - 1 {
+            match param_mode(op, param) {
+                '0' => { },
+                '1' if self.dest() != Some(param) => { },
+                _ => return Err(Error::Mode),
+            }
+        }
+
+        Ok(())
     }
 
     fn size(&self) -> usize {
@@ -166,7 +193,9 @@ fn parse_op(op: isize) -> isize {
     op[(op.len() - 2)..].parse().unwrap()
 }
 
-fn eval(prog: &mut [isize]) {
+fn eval<H>(prog: &mut [isize], io: &mut H) -> Result<(), Error>
+where H: IoHandler
+{
 
     let mut i = 0;
     loop {
@@ -181,11 +210,13 @@ fn eval(prog: &mut [isize]) {
             6 => Operation::JumpFalse,
             7 => Operation::Less,
             8 => Operation::Equal,
-            99 => return,
-            _ => panic!("unknown opcode {}", op),
+            99 => return Ok(()),
+            _ => return Err(Error::Opcode),
         };
 
-        if let Some(new_ip) = op.eval(prog, i) {
+        op.check_modes(prog[i])?;
+
+        if let Some(new_ip) = op.eval(prog, i, io)? {
             i = new_ip;
         } else {
             i += op.size();
@@ -193,6 +224,38 @@ fn eval(prog: &mut [isize]) {
     }
 }
 
+/// I/O handler which feeds a fixed input sequence and records output
+struct VecIo<'a> {
+    input: std::slice::Iter<'a, isize>,
+    output: Vec<isize>,
+}
+
+impl<'a> IoHandler for VecIo<'a> {
+
+    fn input(&mut self) -> Result<isize, InputError> {
+        self.input.next()
+            .cloned()
+            .ok_or(InputError::Unavailable)
+    }
+
+    fn output(&mut self, val: isize) {
+        self.output.push(val);
+    }
+}
+
+/// Runs a program on this day's interpreter with the given input
+pub fn run(prog: &mut [isize], input: &[isize]) -> Result<Vec<isize>, intcode::Error> {
+
+    let mut io = VecIo {
+        input: input.iter(),
+        output: Vec::new(),
+    };
+
+    eval(prog, &mut io)?;
+
+    Ok(io.output)
+}
+
 pub fn part1() {
 
     let mut prog = [
@@ -223,4 +286,39 @@ mod test {
     fn part2_case0() {
         unimplemented!();
     }
+
+    #[test]
+    fn matches_intcode_crate() {
+
+        use intcode::conformance::run_computer;
+        use intcode::fuzz::{self, Features};
+
+        let found = fuzz::differential(
+            &[("intcode", &run_computer), ("day5", &run)],
+            Features { relative: false },
+            0,
+            500,
+        );
+
+        assert!(found.is_empty(), "{:#?}", found.first());
+    }
+
+    #[test]
+    fn matches_day5_revision() {
+
+        use intcode::conformance::{self, run_revision};
+        use intcode::isa::Revision;
+
+        // Later cases must be rejected in the same way, not just cleanly
+        for case in conformance::cases() {
+            let day5 = case.check(run)
+                .map_err(|f| f.to_string());
+            let computer = case.check(run_revision(Revision::Day5))
+                .map_err(|f| f.to_string());
+            assert_eq!(day5, computer, "{}", case.name);
+        }
+
+        let failures = conformance::check_revision(Revision::Day5, run);
+        assert!(failures.is_empty(), "{:#?}", failures);
+    }
 }
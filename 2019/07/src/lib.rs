@@ -1,150 +1,131 @@
-use std::sync::mpsc::{self, *};
-use std::thread;
-
 use itertools::Itertools;
 
-use intcode::{self, Computer, IoHandler};
-
-const AMP_PROG: &'static str = include_str!("amp-prog.txt");
-
+use intcode::Computer;
+use intcode::aio::{self, ChannelIo, Executor};
+use intcode::sweep::{self, Candidate, Sweep};
+use intcode::timeline::{Timeline, Traced};
 
-fn run_amp(phase: isize, input: isize) -> isize {
+const AMP_PROG: &str = include_str!("amp-prog.txt");
 
-    struct AmpIo<'a> {
-        phase: isize,
-        input: isize,
-        counter: isize,
-        output: &'a mut Option<isize>,
-    }
-    impl<'a> IoHandler for AmpIo<'a> {
-        fn input(&mut self) -> isize {
-            match self.counter {
-                0 => {
-                    self.counter += 1;
-                    self.phase
-                },
-                _ => self.input,
-            }
-        }
-        fn output(&mut self, val: isize) {
-            *self.output = Some(val);
-        }
-    }
 
-    let mut output = None;
-    let io = AmpIo {
-        phase,
-        input,
-        counter: 0,
-        output: &mut output,
-    };
+fn run_amp(sweep: &Sweep, phase: isize, input: isize) -> isize {
 
-    let mut prog = intcode::parse_prog(AMP_PROG)
-        .unwrap();
-    Computer::new(io)
-        .eval(&mut prog)
+    let outcome = sweep.run(&Candidate::input(vec![phase, input]))
         .unwrap();
 
-    output.unwrap()
+    *outcome.output.last()
+        .unwrap()
 }
 
 fn run_amps() {
 
-    let mut max: Option<isize> = None;
+    let prog = intcode::parse_prog(AMP_PROG)
+        .unwrap();
+    let sweep = Sweep::new(&prog);
+
+    let perms: Vec<Vec<isize>> = (0..5).permutations(5)
+        .collect();
 
-    for phases in (0..5).permutations(5) {
+    let signals = sweep::all(&perms, |phases| {
         let mut last_input = 0;
-        for phase in phases {
-            last_input = run_amp(phase, last_input);
+        for &phase in phases {
+            last_input = run_amp(&sweep, phase, last_input);
         }
+        Ok(Some(last_input))
+    }).unwrap();
 
-        if max.is_none() || max.unwrap() < last_input {
-            max = Some(last_input);
-        }
-    }
+    let max = signals.into_iter()
+        .map(|(_, signal)| signal)
+        .max();
 
     println!("{:?}", max);
 }
 
-fn run_amp_threaded(phase: isize, input: Receiver<isize>) -> Receiver<isize> {
+/// Runs the amps in a feedback loop with the given phase settings, returning
+/// the last signal sent to the thrusters
+///
+/// When `timeline` is given, each amp is recorded on its own track.
+fn run_feedback(prog: &[isize], phases: &[isize], timeline: Option<&Timeline>) -> isize {
+
+    let (first_tx, first_rx) = aio::channel();
+    first_tx.send(phases[0])
+        .unwrap();
+    first_tx.send(0)
+        .unwrap();
+
+    let mut last_out = None;
 
-    let (output, rx) = mpsc::channel();
+    {
+        let mut exec = Executor::new();
 
-    // TODO
+        // Amp i reads from the channel written by amp i - 1
+        let mut input = first_rx;
+        for i in 0..phases.len() {
 
-    struct AmpIo {
-        phase: isize,
-        input: Receiver<isize>,
-        counter: isize,
-        output: Sender<isize>,
-    }
-    impl IoHandler for AmpIo {
-        fn input(&mut self) -> isize {
-            match self.counter {
-                0 => {
-                    self.counter += 1;
-                    self.phase
-                },
-                _ => self.input.recv().unwrap(),
+            let (output, next) = aio::channel();
+            if let Some(&phase) = phases.get(i + 1) {
+                output.send(phase)
+                    .unwrap();
             }
-        }
-        fn output(&mut self, val: isize) {
-            self.output.send(val).unwrap();
-        }
-    }
 
-    let io = AmpIo {
-        phase,
-        input,
-        counter: 0,
-        output,
-    };
+            let mut mem = prog.to_vec();
+            let io = ChannelIo { input, output };
+            let name = format!("amp {}", (b'A' + i as u8) as char);
+
+            match timeline.map(|timeline| timeline.track(&name)) {
+                Some(track) => exec.spawn(async move {
+                    Computer::new(Traced::new(io, track))
+                        .eval_async(&mut mem)
+                        .await
+                        .unwrap();
+                }),
+                None => exec.spawn(async move {
+                    Computer::new(io)
+                        .eval_async(&mut mem)
+                        .await
+                        .unwrap();
+                }),
+            }
 
-    thread::spawn(move ||{
-        let mut prog = intcode::parse_prog(AMP_PROG)
-            .unwrap();
-        Computer::new(io)
-            .eval(&mut prog)
-            .unwrap();
-    });
+            input = next;
+        }
 
-    // TODO end
+        // Feed the last amp's output back into the first
+        let last_out = &mut last_out;
+        exec.spawn(async move {
+            while let Some(out) = input.recv().await {
+                *last_out = Some(out);
+                let _ = first_tx.send(out);
+            }
+        });
+
+        exec.run();
+    }
 
-    rx
+    last_out.unwrap()
 }
 
 fn run_amps_feedback() {
 
-    let mut max: Option<isize> = None;
-
-    for phases in (5..10).permutations(5) {
-
-        let (tx, mut rx) = mpsc::channel();
+    let prog = intcode::parse_prog(AMP_PROG)
+        .unwrap();
 
-        for phase in phases {
-            rx = run_amp_threaded(phase, rx);
-        }
+    let (max, phases) = (5..10).permutations(5)
+        .map(|phases| (run_feedback(&prog, &phases, None), phases))
+        .max()
+        .unwrap();
 
-        tx.send(0)
+    // Set INTCODE_TRACE to a path to record a timeline of the winning run
+    if let Some(path) = std::env::var_os("INTCODE_TRACE") {
+        let timeline = Timeline::new();
+        run_feedback(&prog, &phases, Some(&timeline));
+        timeline.save(path)
             .unwrap();
-
-        let mut last_out = None;
-        while let Ok(out) = rx.recv() {
-            last_out = Some(out);
-            if tx.send(out).is_err() {
-                break;
-            }
-        }
-
-        if max.is_none() || max.unwrap() < last_out.unwrap() {
-            max = last_out;
-        }
     }
 
-    println!("{:?}", max);
+    println!("{:?}", Some(max));
 }
 
-
 pub fn part1() {
     run_amps();
 }
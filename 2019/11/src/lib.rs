@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
-use intcode::{Computer, IoHandler};
+use intcode::{Computer, InputError};
+use intcode::frame::{Framed, Protocol};
 
 
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
@@ -9,16 +10,10 @@ struct Point {
     y: isize,
 }
 
-enum HullBotState {
-    Painting,
-    Turning,
-}
-
 struct HullBot {
     loc: Point,
     dx: isize,
     dy: isize,
-    state: HullBotState,
     hull: HashMap<Point, isize>,
 }
 
@@ -29,56 +24,48 @@ impl HullBot {
             loc: Point { x: 0, y: 0 },
             dx: 0,
             dy: 1,
-            state: HullBotState::Painting,
             hull: HashMap::new(),
         }
     }
 }
 
-impl IoHandler for &mut HullBot {
+impl Protocol for HullBot {
 
-    fn input(&mut self) -> isize {
+    /// Color to paint, then direction to turn
+    type Frame = (isize, isize);
 
-        *self.hull.get(&self.loc)
-            .unwrap_or(&0)
-    }
+    /// Color of the current panel
+    type Input = isize;
 
-    fn output(&mut self, val: isize) {
+    fn input(&mut self) -> Result<isize, InputError> {
 
-        match self.state {
+        Ok(*self.hull.get(&self.loc)
+            .unwrap_or(&0))
+    }
 
-            HullBotState::Painting => {
+    fn frame(&mut self, (color, turn): (isize, isize)) {
 
-                self.hull.insert(self.loc, val);
+        self.hull.insert(self.loc, color);
 
-                self.state = HullBotState::Turning;
-            },
+        let dx = self.dx;
+        let dy = self.dy;
 
-            HullBotState::Turning => {
-
-                let dx = self.dx;
-                let dy = self.dy;
-
-                match val {
-                    0 => {
-                        self.dx = -1 * dy;
-                        self.dy = dx;
-                    },
-                    1 => {
-                        self.dx = dy;
-                        self.dy = -1 * dx;
-                    },
-                    _ => panic!("unexpected rotation direction {}", val),
-                }
-
-                self.loc = Point {
-                    x: self.loc.x + self.dx,
-                    y: self.loc.y + self.dy,
-                };
-
-                self.state = HullBotState::Painting;
+        match turn {
+            0 => {
+                self.dx = -1 * dy;
+                self.dy = dx;
+            },
+            1 => {
+                self.dx = dy;
+                self.dy = -1 * dx;
             },
+            _ => panic!("unexpected rotation direction {}", turn),
         }
+
+        self.loc = Point {
+            x: self.loc.x + self.dx,
+            y: self.loc.y + self.dy,
+        };
     }
 }
 
@@ -91,7 +78,7 @@ pub fn part1() {
 
     let mut hull_bot = HullBot::new();
 
-    Computer::new(&mut hull_bot)
+    Computer::new(Framed::new(&mut hull_bot))
         .eval(&mut hull_paint)
         .unwrap();
 
@@ -108,7 +95,7 @@ pub fn part2() {
     // Start on a white panel
     hull_bot.hull.insert(Point { x: 0, y: 0 }, 1);
 
-    Computer::new(&mut hull_bot)
+    Computer::new(Framed::new(&mut hull_bot))
         .eval(&mut hull_paint)
         .unwrap();
 
@@ -2,7 +2,9 @@
 use std::collections::HashMap;
 use std::io;
 
-use intcode::{Computer, IoHandler};
+use intcode::{Computer, InputError};
+use intcode::frame::{Frame, Framed, Protocol};
+use intcode::patch::Patch;
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq)]
 struct Point {
@@ -10,18 +12,25 @@ struct Point {
     y: isize,
 }
 
-enum GameOutputState {
-    XCoord,
-    YCoord {
-        x: isize,
-    },
-    TileId {
-        loc: Point,
-    },
+/// Tile drawn at a location, or the score if the location is (-1, 0)
+struct Draw {
+    loc: Point,
+    id: isize,
+}
+
+impl Frame for Draw {
+
+    const LEN: usize = 3;
+
+    fn decode(vals: &[isize]) -> Self {
+        Self {
+            loc: Point { x: vals[0], y: vals[1] },
+            id: vals[2],
+        }
+    }
 }
 
 struct GameHandler {
-    state: GameOutputState,
     tiles: HashMap<Point, isize>,
     ball_cur: Option<Point>,
     ball_prev: Option<Point>,
@@ -73,9 +82,12 @@ impl GameHandler {
     }
 }
 
-impl IoHandler for &mut GameHandler {
+impl Protocol for GameHandler {
 
-    fn input(&mut self) -> isize {
+    type Frame = Draw;
+    type Input = isize;
+
+    fn input(&mut self) -> Result<isize, InputError> {
 
         self.render();
 
@@ -104,57 +116,32 @@ impl IoHandler for &mut GameHandler {
             println!("next move? (p/l/r/n)");
             let mut line = String::new();
             io::stdin().read_line(&mut line)
-                .unwrap();
+                .map_err(|_| InputError::Unavailable)?;
 
             match line.trim() {
-                "p" | "" => return prediction,
-                "n" => return 0,
-                "l" => return -1,
-                "r" => return 1,
+                "p" | "" => return Ok(prediction),
+                "n" => return Ok(0),
+                "l" => return Ok(-1),
+                "r" => return Ok(1),
                 _ => println!("unrecognized input!"),
             }
         }
     }
 
-    fn output(&mut self, val: isize) {
-
-        match &self.state {
-
-            GameOutputState::XCoord => {
-                self.state = GameOutputState::YCoord {
-                    x: val,
-                };
-            },
-
-            GameOutputState::YCoord { x } => {
-                self.state = GameOutputState::TileId {
-                    loc: Point {
-                        x: *x,
-                        y: val,
-                    },
-                };
-            },
-
-            GameOutputState::TileId { loc } => {
-
-                let loc = *loc;
-
-                if loc.x == -1 && loc.y == 0 {
-                    println!("Score: {}", val);
-                } else {
-                    self.tiles.insert(loc, val);
-                    if val == 3 {
-                        self.paddle_cur = Some(loc);
-                    } else if val == 4 {
-                        if self.ball_cur.is_some() {
-                            self.ball_prev = self.ball_cur;
-                        }
-                        self.ball_cur = Some(loc);
-                    }
-                }
+    fn frame(&mut self, Draw { loc, id }: Draw) {
 
-                self.state = GameOutputState::XCoord;
-            },
+        if loc.x == -1 && loc.y == 0 {
+            println!("Score: {}", id);
+        } else {
+            self.tiles.insert(loc, id);
+            if id == 3 {
+                self.paddle_cur = Some(loc);
+            } else if id == 4 {
+                if self.ball_cur.is_some() {
+                    self.ball_prev = self.ball_cur;
+                }
+                self.ball_cur = Some(loc);
+            }
         }
     }
 }
@@ -166,14 +153,13 @@ pub fn part1() {
     let mut game = intcode::parse_prog(GAME)
         .unwrap();
     let mut handler = GameHandler {
-        state: GameOutputState::XCoord,
         tiles: HashMap::new(),
         ball_cur: None,
         ball_prev: None,
         paddle_cur: None,
     };
 
-    Computer::new(&mut handler)
+    Computer::new(Framed::new(&mut handler))
         .eval(&mut game)
         .unwrap();
 
@@ -192,17 +178,56 @@ pub fn part2() {
     let mut game = intcode::parse_prog(GAME)
         .unwrap();
     let mut handler = GameHandler {
-        state: GameOutputState::XCoord,
         tiles: HashMap::new(),
         ball_cur: None,
         ball_prev: None,
         paddle_cur: None,
     };
 
-    // Jimmy the quarter slot
-    game[0] = 2;
+    Patch::new("free-play")
+        .set(0, 1, 2)
+        .and_then(|p| p.apply(&mut game))
+        .unwrap_or_else(|err| panic!("{}", err));
 
-    Computer::new(&mut handler)
+    Computer::new(Framed::new(&mut handler))
         .eval(&mut game)
         .unwrap();
 }
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use intcode::layout::Layout;
+    use intcode::program::Program;
+
+    #[test]
+    fn level_layout_is_data() {
+
+        let prog: Program = GAME.parse()
+            .unwrap();
+        let mut handler = GameHandler {
+            tiles: HashMap::new(),
+            ball_cur: None,
+            ball_prev: None,
+            paddle_cur: None,
+        };
+
+        let layout = Layout::trace(prog.cells(), Framed::new(&mut handler))
+            .unwrap();
+
+        // The screen is the largest table, with one tile id per cell
+        let screen = layout.tables()
+            .into_iter()
+            .max_by_key(|r| r.range.len())
+            .unwrap()
+            .extract::<u8>(prog.cells())
+            .unwrap();
+
+        assert_eq!(screen.len(), handler.tiles.len());
+        assert_eq!(
+            screen.iter().filter(|&&t| t == 2).count(),
+            handler.tiles.values().filter(|&&t| t == 2).count(),
+        );
+    }
+}
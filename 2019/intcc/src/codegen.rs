@@ -0,0 +1,517 @@
+//! Translation of the syntax tree into intcode
+//!
+//! Every function has a frame on a stack which starts just past the end of
+//! the program and grows upwards. While a function runs, the relative base
+//! points at its frame, which holds:
+//!
+//! - slot 0: return address
+//! - slots 1 to n: parameters
+//! - remaining slots: local variables and temporaries
+//!
+//! To make a call, the caller fills in the frame directly above its own,
+//! moves the relative base up by the size of its frame, and jumps. The callee
+//! leaves its result in slot 1 and jumps back through slot 0, after which the
+//! caller moves the relative base back down.
+
+use std::collections::HashMap;
+
+use intcode::isa;
+
+use crate::{Error, ErrorKind, Pos};
+use crate::parser::{BinOp, Expr, Function, Stmt, UnOp};
+
+
+/// Functions provided by the compiler
+const BUILTINS: [&str; 2] = ["input", "output"];
+
+
+/// Source of a value, or destination of a result
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+
+    /// Immediate value
+    Imm(isize),
+
+    /// Slot in the current frame
+    Slot(isize),
+
+    /// Slot in the frame of a function being called
+    Callee(isize),
+
+    /// Address of a label, as an immediate value
+    Label(usize),
+
+    /// Size of the current frame times a factor, as an immediate value
+    Frame(isize),
+}
+
+
+/// Element of generated code
+#[derive(Debug)]
+enum Item {
+
+    /// Instruction with its opcode and parameters
+    Op(isize, Vec<Operand>),
+
+    /// Marks the address of a label
+    Label(usize),
+}
+
+
+/// State of code generation
+#[derive(Default)]
+struct Codegen {
+
+    /// Code generated so far
+    items: Vec<Item>,
+
+    /// Number of labels allocated
+    labels: usize,
+
+    /// Entry label and arity of every user-defined function
+    funcs: HashMap<String, (usize, usize)>,
+
+    /// Variables in scope, innermost scope last
+    scopes: Vec<HashMap<String, isize>>,
+
+    /// Highest slot of the current frame in use
+    top: isize,
+
+    /// Highest slot of the current frame ever used
+    max: isize,
+}
+
+impl Codegen {
+
+    fn label(&mut self) -> usize {
+
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    fn place(&mut self, label: usize) {
+
+        self.items.push(Item::Label(label));
+    }
+
+    fn emit(&mut self, opcode: isize, args: &[Operand]) {
+
+        self.items.push(Item::Op(opcode, args.to_vec()));
+    }
+
+    /// Reserves a new slot in the current frame
+    fn alloc(&mut self) -> isize {
+
+        self.top += 1;
+        self.max = self.max.max(self.top);
+        self.top
+    }
+
+    fn lookup(&self, name: &str, pos: Pos) -> Result<isize, Error> {
+
+        self.scopes.iter()
+            .rev()
+            .find_map(|s| s.get(name))
+            .cloned()
+            .ok_or_else(|| Error {
+                pos,
+                kind: ErrorKind::UndefinedVariable(name.to_owned()),
+            })
+    }
+
+    fn function(&mut self, func: &Function) -> Result<(), Error> {
+
+        let start = self.items.len();
+        self.place(self.funcs[&func.name].0);
+
+        let mut params = HashMap::new();
+        for (i, name) in func.params.iter().enumerate() {
+            if params.insert(name.clone(), i as isize + 1).is_some() {
+                return Err(Error {
+                    pos: func.pos,
+                    kind: ErrorKind::DuplicateParam(name.clone()),
+                });
+            }
+        }
+
+        self.scopes = vec![params];
+        self.top = func.params.len() as isize;
+        self.max = self.top;
+
+        self.block(&func.body)?;
+        self.ret(Operand::Imm(0));
+
+        // Now that the frame size is known, resolve references to it
+        let frame = self.max + 1;
+        for item in &mut self.items[start..] {
+            if let Item::Op(_, args) = item {
+                for arg in args {
+                    *arg = match *arg {
+                        Operand::Callee(slot) => Operand::Slot(frame + slot),
+                        Operand::Frame(factor) => Operand::Imm(frame * factor),
+                        other => other,
+                    };
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<(), Error> {
+
+        let mark = self.top;
+        self.scopes.push(HashMap::new());
+
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+
+        self.scopes.pop();
+        self.top = mark;
+
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+
+        match stmt {
+
+            Stmt::Let(name, e) => {
+                let slot = self.alloc();
+                let mark = self.top;
+                self.expr_into(e, Operand::Slot(slot))?;
+                self.top = mark;
+                self.scopes.last_mut()
+                    .unwrap()
+                    .insert(name.clone(), slot);
+            },
+
+            Stmt::Assign(name, e, pos) => {
+                let slot = self.lookup(name, *pos)?;
+                let mark = self.top;
+                self.expr_into(e, Operand::Slot(slot))?;
+                self.top = mark;
+            },
+
+            Stmt::If(cond, then, otherwise) => {
+                let else_label = self.label();
+                let mark = self.top;
+                let c = self.operand(cond)?;
+                self.emit(6, &[c, Operand::Label(else_label)]);
+                self.top = mark;
+
+                self.block(then)?;
+
+                if otherwise.is_empty() {
+                    self.place(else_label);
+                } else {
+                    let end = self.label();
+                    self.emit(5, &[Operand::Imm(1), Operand::Label(end)]);
+                    self.place(else_label);
+                    self.block(otherwise)?;
+                    self.place(end);
+                }
+            },
+
+            Stmt::While(cond, body) => {
+                let start = self.label();
+                let end = self.label();
+                self.place(start);
+                let mark = self.top;
+                let c = self.operand(cond)?;
+                self.emit(6, &[c, Operand::Label(end)]);
+                self.top = mark;
+
+                self.block(body)?;
+                self.emit(5, &[Operand::Imm(1), Operand::Label(start)]);
+                self.place(end);
+            },
+
+            Stmt::Return(e) => {
+                let mark = self.top;
+                let val = match e {
+                    Some(e) => self.operand(e)?,
+                    None => Operand::Imm(0),
+                };
+                self.ret(val);
+                self.top = mark;
+            },
+
+            Stmt::Expr(e) => {
+                let mark = self.top;
+                match e {
+                    Expr::Call(name, args, pos) => self.call(name, args, *pos, None)?,
+                    e => { self.operand(e)?; },
+                }
+                self.top = mark;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Returns from the current function with `val` as its result
+    fn ret(&mut self, val: Operand) {
+
+        self.emit(1, &[val, Operand::Imm(0), Operand::Slot(1)]);
+        self.emit(5, &[Operand::Imm(1), Operand::Slot(0)]);
+    }
+
+    /// Evaluates an expression, returning where its value can be found
+    ///
+    /// Temporaries are allocated as needed, and stay in use until the caller
+    /// releases them.
+    fn operand(&mut self, e: &Expr) -> Result<Operand, Error> {
+
+        Ok(match e {
+            Expr::Num(n) => Operand::Imm(*n),
+            Expr::Var(name, pos) => Operand::Slot(self.lookup(name, *pos)?),
+            e => {
+                let slot = self.alloc();
+                self.expr_into(e, Operand::Slot(slot))?;
+                Operand::Slot(slot)
+            },
+        })
+    }
+
+    /// Evaluates an expression, storing its value to `dst`
+    fn expr_into(&mut self, e: &Expr, dst: Operand) -> Result<(), Error> {
+
+        let mark = self.top;
+
+        match e {
+
+            Expr::Num(_) | Expr::Var(..) => {
+                let val = self.operand(e)?;
+                self.emit(1, &[val, Operand::Imm(0), dst]);
+            },
+
+            Expr::Call(name, args, pos) => self.call(name, args, *pos, Some(dst))?,
+
+            Expr::Unary(op, e) => {
+                let a = self.operand(e)?;
+                match op {
+                    UnOp::Neg => self.emit(2, &[a, Operand::Imm(-1), dst]),
+                    UnOp::Not => self.emit(8, &[a, Operand::Imm(0), dst]),
+                }
+            },
+
+            Expr::Binary(op @ BinOp::And, lhs, rhs) | Expr::Binary(op @ BinOp::Or, lhs, rhs) => {
+
+                // Skip the right hand side once the result is known
+                let (skip, short) = match op {
+                    BinOp::And => (6, 0),
+                    _ => (5, 1),
+                };
+                let short_label = self.label();
+                let end = self.label();
+
+                let a = self.operand(lhs)?;
+                self.emit(skip, &[a, Operand::Label(short_label)]);
+                let b = self.operand(rhs)?;
+                self.emit(skip, &[b, Operand::Label(short_label)]);
+                self.emit(1, &[Operand::Imm(1 - short), Operand::Imm(0), dst]);
+                self.emit(5, &[Operand::Imm(1), Operand::Label(end)]);
+                self.place(short_label);
+                self.emit(1, &[Operand::Imm(short), Operand::Imm(0), dst]);
+                self.place(end);
+            },
+
+            Expr::Binary(op, lhs, rhs) => {
+
+                let a = self.operand(lhs)?;
+                let b = self.operand(rhs)?;
+
+                match op {
+                    BinOp::Add => self.emit(1, &[a, b, dst]),
+                    BinOp::Sub => {
+                        let neg_b = match b {
+                            Operand::Imm(n) if n != isize::MIN => Operand::Imm(-n),
+                            b => {
+                                let slot = Operand::Slot(self.alloc());
+                                self.emit(2, &[b, Operand::Imm(-1), slot]);
+                                slot
+                            },
+                        };
+                        self.emit(1, &[a, neg_b, dst]);
+                    },
+                    BinOp::Mul => self.emit(2, &[a, b, dst]),
+                    BinOp::Lt => self.emit(7, &[a, b, dst]),
+                    BinOp::Gt => self.emit(7, &[b, a, dst]),
+                    BinOp::Eq => self.emit(8, &[a, b, dst]),
+                    BinOp::Le | BinOp::Ge | BinOp::Ne => {
+                        match op {
+                            BinOp::Le => self.emit(7, &[b, a, dst]),
+                            BinOp::Ge => self.emit(7, &[a, b, dst]),
+                            _ => self.emit(8, &[a, b, dst]),
+                        }
+                        self.emit(8, &[dst, Operand::Imm(0), dst]);
+                    },
+                    BinOp::And | BinOp::Or => unreachable!(),
+                }
+            },
+        }
+
+        self.top = mark;
+
+        Ok(())
+    }
+
+    /// Calls a function, storing its result to `dst` if given
+    fn call(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        pos: Pos,
+        dst: Option<Operand>,
+    ) -> Result<(), Error> {
+
+        let (label, arity) = match name {
+            "input" => (None, 0),
+            "output" => (None, 1),
+            _ => {
+                let &(label, arity) = self.funcs.get(name)
+                    .ok_or_else(|| Error {
+                        pos,
+                        kind: ErrorKind::UndefinedFunction(name.to_owned()),
+                    })?;
+                (Some(label), arity)
+            },
+        };
+
+        if args.len() != arity {
+            return Err(Error {
+                pos,
+                kind: ErrorKind::Arity {
+                    name: name.to_owned(),
+                    expected: arity,
+                    found: args.len(),
+                },
+            });
+        }
+
+        let vals = args.iter()
+            .map(|a| self.operand(a))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let label = match label {
+            Some(label) => label,
+            None if name == "input" => {
+                let dst = dst.unwrap_or_else(|| Operand::Slot(self.alloc()));
+                self.emit(3, &[dst]);
+                return Ok(());
+            },
+            None => {
+                self.emit(4, &[vals[0]]);
+                if let Some(dst) = dst {
+                    self.emit(1, &[Operand::Imm(0), Operand::Imm(0), dst]);
+                }
+                return Ok(());
+            },
+        };
+
+        let ret = self.label();
+
+        for (i, &val) in vals.iter().enumerate() {
+            self.emit(1, &[val, Operand::Imm(0), Operand::Callee(i as isize + 1)]);
+        }
+        self.emit(1, &[Operand::Label(ret), Operand::Imm(0), Operand::Callee(0)]);
+        self.emit(9, &[Operand::Frame(1)]);
+        self.emit(5, &[Operand::Imm(1), Operand::Label(label)]);
+        self.place(ret);
+        self.emit(9, &[Operand::Frame(-1)]);
+
+        if let Some(dst) = dst {
+            self.emit(1, &[Operand::Callee(1), Operand::Imm(0), dst]);
+        }
+
+        Ok(())
+    }
+
+    /// Resolves labels and encodes the generated code
+    fn assemble(&self) -> Vec<isize> {
+
+        let mut addrs = vec![0; self.labels];
+        let mut addr = 0;
+
+        for item in &self.items {
+            match item {
+                Item::Op(_, args) => addr += 1 + args.len() as isize,
+                Item::Label(label) => addrs[*label] = addr,
+            }
+        }
+
+        let mut cells = Vec::with_capacity(addr as usize);
+
+        for item in &self.items {
+            if let Item::Op(opcode, args) = item {
+
+                let modes: Vec<isize> = args.iter()
+                    .map(|a| match a {
+                        Operand::Slot(_) => 2,
+                        _ => 1,
+                    })
+                    .collect();
+                cells.push(isa::encode(*opcode, &modes));
+
+                cells.extend(args.iter().map(|a| match *a {
+                    Operand::Imm(v) | Operand::Slot(v) => v,
+                    Operand::Label(label) => addrs[label],
+                    Operand::Callee(_) | Operand::Frame(_) => unreachable!(),
+                }));
+            }
+        }
+
+        cells
+    }
+}
+
+
+/// Generates a program from a list of functions
+///
+/// Execution starts by calling `main`, and halts once it returns.
+pub fn generate(funcs: &[Function]) -> Result<Vec<isize>, Error> {
+
+    let mut gen = Codegen::default();
+
+    for func in funcs {
+
+        let kind = if BUILTINS.contains(&func.name.as_str()) {
+            Some(ErrorKind::Reserved(func.name.clone()))
+        } else if gen.funcs.contains_key(&func.name) {
+            Some(ErrorKind::DuplicateFunction(func.name.clone()))
+        } else if func.name == "main" && !func.params.is_empty() {
+            Some(ErrorKind::MainParams)
+        } else {
+            None
+        };
+
+        if let Some(kind) = kind {
+            return Err(Error { pos: func.pos, kind });
+        }
+
+        let label = gen.label();
+        gen.funcs.insert(func.name.clone(), (label, func.params.len()));
+    }
+
+    let main = gen.funcs.get("main")
+        .ok_or(Error { pos: Pos { line: 1, col: 1 }, kind: ErrorKind::MissingMain })?
+        .0;
+    let stack = gen.label();
+    let halt = gen.label();
+
+    gen.emit(9, &[Operand::Label(stack)]);
+    gen.emit(1, &[Operand::Label(halt), Operand::Imm(0), Operand::Slot(0)]);
+    gen.emit(5, &[Operand::Imm(1), Operand::Label(main)]);
+    gen.place(halt);
+    gen.emit(99, &[]);
+
+    for func in funcs {
+        gen.function(func)?;
+    }
+
+    gen.place(stack);
+
+    Ok(gen.assemble())
+}
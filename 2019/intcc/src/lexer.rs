@@ -0,0 +1,203 @@
+//! Splitting of source text into tokens
+
+use crate::{Error, ErrorKind, Pos};
+
+
+/// Smallest meaningful unit of source text
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Token {
+    Num(isize),
+    Ident(String),
+    Fn,
+    Let,
+    If,
+    Else,
+    While,
+    Return,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    Semi,
+    Assign,
+    Plus,
+    Minus,
+    Star,
+    Not,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+    Eof,
+}
+
+
+/// Splits `src` into tokens, each paired with its position
+///
+/// The last token is always `Eof`. Comments start with `//` and run to the end
+/// of the line.
+pub fn tokenize(src: &str) -> Result<Vec<(Pos, Token)>, Error> {
+
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    let mut pos = Pos { line: 1, col: 1 };
+
+    while let Some(&c) = chars.peek() {
+
+        let start = pos;
+        let mut bump = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+            let c = chars.next();
+            if c == Some('\n') {
+                pos.line += 1;
+                pos.col = 1;
+            } else {
+                pos.col += 1;
+            }
+            c
+        };
+
+        if c.is_whitespace() {
+            bump(&mut chars);
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let mut text = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_alphanumeric()) {
+                text.push(d);
+                bump(&mut chars);
+            }
+            let val = text.parse()
+                .map_err(|_| Error { pos: start, kind: ErrorKind::BadNumber(text) })?;
+            tokens.push((start, Token::Num(val)));
+            continue;
+        }
+
+        if c.is_ascii_alphabetic() || c == '_' {
+            let mut text = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_alphanumeric() || **d == '_') {
+                text.push(d);
+                bump(&mut chars);
+            }
+            tokens.push((start, match text.as_str() {
+                "fn" => Token::Fn,
+                "let" => Token::Let,
+                "if" => Token::If,
+                "else" => Token::Else,
+                "while" => Token::While,
+                "return" => Token::Return,
+                _ => Token::Ident(text),
+            }));
+            continue;
+        }
+
+        bump(&mut chars);
+        let next = chars.peek().cloned();
+
+        let (token, wide) = match (c, next) {
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    bump(&mut chars);
+                }
+                continue;
+            },
+            ('<', Some('=')) => (Token::Le, true),
+            ('>', Some('=')) => (Token::Ge, true),
+            ('=', Some('=')) => (Token::Eq, true),
+            ('!', Some('=')) => (Token::Ne, true),
+            ('&', Some('&')) => (Token::And, true),
+            ('|', Some('|')) => (Token::Or, true),
+            ('(', _) => (Token::LParen, false),
+            (')', _) => (Token::RParen, false),
+            ('{', _) => (Token::LBrace, false),
+            ('}', _) => (Token::RBrace, false),
+            (',', _) => (Token::Comma, false),
+            (';', _) => (Token::Semi, false),
+            ('=', _) => (Token::Assign, false),
+            ('+', _) => (Token::Plus, false),
+            ('-', _) => (Token::Minus, false),
+            ('*', _) => (Token::Star, false),
+            ('!', _) => (Token::Not, false),
+            ('<', _) => (Token::Lt, false),
+            ('>', _) => (Token::Gt, false),
+            _ => return Err(Error { pos: start, kind: ErrorKind::UnexpectedChar(c) }),
+        };
+
+        if wide {
+            bump(&mut chars);
+        }
+
+        tokens.push((start, token));
+    }
+
+    tokens.push((pos, Token::Eof));
+
+    Ok(tokens)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<Token> {
+
+        tokenize(src)
+            .unwrap()
+            .into_iter()
+            .map(|(_, t)| t)
+            .collect()
+    }
+
+    #[test]
+    fn tokens() {
+
+        assert_eq!(kinds("let x1 = 42 <= y; // done"), vec![
+            Token::Let,
+            Token::Ident("x1".to_owned()),
+            Token::Assign,
+            Token::Num(42),
+            Token::Le,
+            Token::Ident("y".to_owned()),
+            Token::Semi,
+            Token::Eof,
+        ]);
+        assert_eq!(kinds("!a&&b||c!=d"), vec![
+            Token::Not,
+            Token::Ident("a".to_owned()),
+            Token::And,
+            Token::Ident("b".to_owned()),
+            Token::Or,
+            Token::Ident("c".to_owned()),
+            Token::Ne,
+            Token::Ident("d".to_owned()),
+            Token::Eof,
+        ]);
+    }
+
+    #[test]
+    fn positions() {
+
+        let tokens = tokenize("fn\n  main")
+            .unwrap();
+
+        assert_eq!(tokens[1].0, Pos { line: 2, col: 3 });
+    }
+
+    #[test]
+    fn errors() {
+
+        let err = tokenize("x = 1 # 2")
+            .unwrap_err();
+        assert_eq!(err, Error { pos: Pos { line: 1, col: 7 }, kind: ErrorKind::UnexpectedChar('#') });
+
+        let err = tokenize("x = 12ab")
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::BadNumber("12ab".to_owned()));
+    }
+}
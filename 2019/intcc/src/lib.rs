@@ -0,0 +1,466 @@
+//! Compiler from a small structured language to intcode
+//!
+//! A program is a list of functions, and runs by calling `main`:
+//!
+//! ```text
+//! // Outputs the first n Fibonacci numbers
+//! fn main() {
+//!     let n = input();
+//!     let i = 0;
+//!     while i < n {
+//!         output(fib(i));
+//!         i = i + 1;
+//!     }
+//! }
+//!
+//! fn fib(n) {
+//!     if n < 2 {
+//!         return n;
+//!     }
+//!     return fib(n - 1) + fib(n - 2);
+//! }
+//! ```
+//!
+//! All values are integers. Supported operators are `+`, `-`, `*`, unary `-`,
+//! comparisons (`<`, `<=`, `>`, `>=`, `==`, `!=`) and logical operators (`!`,
+//! and short-circuiting `&&` and `||`), with the usual precedence. Conditions
+//! treat any nonzero value as true, and comparisons produce 0 or 1.
+//!
+//! Variables are declared with `let` and are scoped to the enclosing block.
+//! Functions may recurse, and return 0 unless they `return` something else.
+//! The built-in `input()` reads a value, and `output(x)` writes one.
+
+use std::fmt;
+
+use intcode::program::Program;
+
+mod codegen;
+mod lexer;
+mod parser;
+
+pub use lexer::Token;
+
+
+/// Line and column (both starting from 1) within source text
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for Pos {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+
+/// Reason a program could not be compiled
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ErrorKind {
+
+    /// Character which cannot start any token
+    UnexpectedChar(char),
+
+    /// Malformed or out of range integer literal
+    BadNumber(String),
+
+    /// Parser found something other than what it expected
+    Expected {
+        expected: &'static str,
+        found: Token,
+    },
+
+    /// Variable used without being declared
+    UndefinedVariable(String),
+
+    /// Call to a function which does not exist
+    UndefinedFunction(String),
+
+    /// Call with the wrong number of arguments
+    Arity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+
+    /// Function defined more than once
+    DuplicateFunction(String),
+
+    /// Parameter name used more than once in a function
+    DuplicateParam(String),
+
+    /// Function defined with the name of a built-in
+    Reserved(String),
+
+    /// Program has no `main` function
+    MissingMain,
+
+    /// `main` is defined with parameters
+    MainParams,
+}
+
+
+/// Error encountered while compiling a program
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Error {
+
+    /// Where in the source the error was found
+    pub pos: Pos,
+
+    /// What went wrong
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for Error {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        write!(f, "{}: ", self.pos)?;
+
+        match &self.kind {
+            ErrorKind::UnexpectedChar(c) => write!(f, "unexpected character {:?}", c),
+            ErrorKind::BadNumber(text) => write!(f, "invalid number {:?}", text),
+            ErrorKind::Expected { expected, found } => write!(
+                f,
+                "expected {}, found {:?}",
+                expected,
+                found,
+            ),
+            ErrorKind::UndefinedVariable(name) => write!(f, "undefined variable {}", name),
+            ErrorKind::UndefinedFunction(name) => write!(f, "undefined function {}", name),
+            ErrorKind::Arity { name, expected, found } => write!(
+                f,
+                "{} takes {} arguments, but {} were given",
+                name,
+                expected,
+                found,
+            ),
+            ErrorKind::DuplicateFunction(name) => write!(f, "function {} is already defined", name),
+            ErrorKind::DuplicateParam(name) => write!(f, "parameter {} is repeated", name),
+            ErrorKind::Reserved(name) => write!(f, "{} is a built-in function", name),
+            ErrorKind::MissingMain => write!(f, "no main function"),
+            ErrorKind::MainParams => write!(f, "main cannot take parameters"),
+        }
+    }
+}
+
+
+/// Compiles source text into an intcode program
+pub fn compile(src: &str) -> Result<Program, Error> {
+
+    let tokens = lexer::tokenize(src)?;
+    let funcs = parser::parse(tokens)?;
+
+    Ok(codegen::generate(&funcs)?.into())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use intcode::{Computer, InputError, IoHandler};
+    use intcode::lint::{self, Severity};
+
+    struct VecIo<'a> {
+        input: std::slice::Iter<'a, isize>,
+        output: &'a mut Vec<isize>,
+    }
+
+    impl<'a> IoHandler for VecIo<'a> {
+
+        fn input(&mut self) -> Result<isize, InputError> {
+            self.input.next()
+                .cloned()
+                .ok_or(InputError::Unavailable)
+        }
+
+        fn output(&mut self, val: isize) {
+            self.output.push(val);
+        }
+    }
+
+    /// Compiles and runs a program, returning its output
+    fn run(src: &str, input: &[isize]) -> Vec<isize> {
+
+        let prog = compile(src)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let errors: Vec<_> = lint::lint(prog.cells())
+            .into_iter()
+            .filter(|f| f.severity == Severity::Error)
+            .collect();
+        assert_eq!(errors, vec![]);
+
+        let mut output = Vec::new();
+        let io = VecIo {
+            input: input.iter(),
+            output: &mut output,
+        };
+
+        Computer::new(io)
+            .eval(&mut prog.memory())
+            .unwrap();
+
+        output
+    }
+
+    fn compile_err(src: &str) -> ErrorKind {
+
+        compile(src)
+            .unwrap_err()
+            .kind
+    }
+
+    #[test]
+    fn arithmetic() {
+
+        let src = "
+            fn main() {
+                let a = input();
+                let b = input();
+                output(a + b);
+                output(a - b);
+                output(a * b);
+                output(-a);
+                output(a - -3 * (b + 1));
+                output(2 - 3 - 4);
+            }
+        ";
+
+        assert_eq!(run(src, &[7, 5]), vec![12, 2, 35, -7, 25, -5]);
+    }
+
+    #[test]
+    fn comparisons() {
+
+        let src = "
+            fn main() {
+                let a = input();
+                let b = input();
+                output(a < b);
+                output(a <= b);
+                output(a > b);
+                output(a >= b);
+                output(a == b);
+                output(a != b);
+                output(!a);
+                output(!!a);
+            }
+        ";
+
+        assert_eq!(run(src, &[1, 2]), vec![1, 1, 0, 0, 0, 1, 0, 1]);
+        assert_eq!(run(src, &[2, 2]), vec![0, 1, 0, 1, 1, 0, 0, 1]);
+        assert_eq!(run(src, &[0, -1]), vec![0, 0, 1, 1, 0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn short_circuit() {
+
+        let src = "
+            fn main() {
+                output(zero() && one());
+                output(one() && zero());
+                output(one() || zero());
+                output(zero() || 5);
+            }
+
+            fn zero() {
+                output(100);
+                return 0;
+            }
+
+            fn one() {
+                output(101);
+                return 1;
+            }
+        ";
+
+        assert_eq!(run(src, &[]), vec![
+            100, 0,
+            101, 100, 0,
+            101, 1,
+            100, 1,
+        ]);
+    }
+
+    #[test]
+    fn control_flow() {
+
+        let src = "
+            fn main() {
+                let n = input();
+                while n != 0 {
+                    if n < 0 {
+                        output(-1);
+                    } else if n < 10 {
+                        output(1);
+                    } else {
+                        output(10);
+                    }
+                    n = input();
+                }
+            }
+        ";
+
+        assert_eq!(run(src, &[5, -3, 42, 9, 0]), vec![1, -1, 10, 1]);
+    }
+
+    #[test]
+    fn scoping() {
+
+        let src = "
+            fn main() {
+                let x = 1;
+                if 1 {
+                    let x = x + 10;
+                    output(x);
+                    x = 20;
+                    output(x);
+                }
+                output(x);
+                x = 2;
+                output(x);
+            }
+        ";
+
+        assert_eq!(run(src, &[]), vec![11, 20, 1, 2]);
+    }
+
+    #[test]
+    fn recursion() {
+
+        let src = "
+            fn main() {
+                let n = input();
+                let i = 0;
+                while i < n {
+                    output(fib(i));
+                    i = i + 1;
+                }
+                output(fact(10));
+                output(is_even(7));
+                output(sum(200));
+            }
+
+            fn fib(n) {
+                if n < 2 {
+                    return n;
+                }
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            fn fact(n) {
+                if n == 0 {
+                    return 1;
+                }
+                return n * fact(n - 1);
+            }
+
+            fn is_even(n) {
+                if n == 0 {
+                    return 1;
+                }
+                return is_odd(n - 1);
+            }
+
+            fn is_odd(n) {
+                if n == 0 {
+                    return 0;
+                }
+                return is_even(n - 1);
+            }
+
+            fn sum(n) {
+                if n == 0 {
+                    return 0;
+                }
+                return n + sum(n - 1);
+            }
+        ";
+
+        assert_eq!(
+            run(src, &[10]),
+            vec![0, 1, 1, 2, 3, 5, 8, 13, 21, 34, 3628800, 0, 20100],
+        );
+    }
+
+    #[test]
+    fn calls() {
+
+        let src = "
+            fn main() {
+                output(max3(input(), input(), input()));
+                output(add(add(1, 2), add(3, add(4, 5))));
+                output(nothing());
+                noisy(7);
+            }
+
+            fn max3(a, b, c) {
+                return max(max(a, b), c);
+            }
+
+            fn max(a, b) {
+                if a > b {
+                    return a;
+                }
+                return b;
+            }
+
+            fn add(a, b) {
+                return a + b;
+            }
+
+            fn nothing() {
+            }
+
+            fn noisy(x) {
+                output(x);
+                return;
+            }
+        ";
+
+        assert_eq!(run(src, &[4, 9, 2]), vec![9, 15, 0, 7]);
+    }
+
+    #[test]
+    fn errors() {
+
+        assert_eq!(
+            compile_err("fn main() { output(x); }"),
+            ErrorKind::UndefinedVariable("x".to_owned()),
+        );
+        assert_eq!(
+            compile_err("fn main() { if 1 { let x = 1; } output(x); }"),
+            ErrorKind::UndefinedVariable("x".to_owned()),
+        );
+        assert_eq!(
+            compile_err("fn main() { f(); }"),
+            ErrorKind::UndefinedFunction("f".to_owned()),
+        );
+        assert_eq!(
+            compile_err("fn main() { output(1, 2); }"),
+            ErrorKind::Arity { name: "output".to_owned(), expected: 1, found: 2 },
+        );
+        assert_eq!(
+            compile_err("fn main() { } fn main() { }"),
+            ErrorKind::DuplicateFunction("main".to_owned()),
+        );
+        assert_eq!(
+            compile_err("fn main() { } fn f(a, a) { }"),
+            ErrorKind::DuplicateParam("a".to_owned()),
+        );
+        assert_eq!(compile_err("fn input() { }"), ErrorKind::Reserved("input".to_owned()));
+        assert_eq!(compile_err("fn f() { }"), ErrorKind::MissingMain);
+        assert_eq!(compile_err("fn main(x) { }"), ErrorKind::MainParams);
+
+        assert_eq!(
+            compile("fn main() {\n  output(y);\n}")
+                .unwrap_err()
+                .to_string(),
+            "2:10: undefined variable y",
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use std::env;
+use std::fs;
+use std::process;
+
+
+fn main() {
+
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| {
+            eprintln!("usage: intcc <source>");
+            process::exit(2);
+        });
+
+    let src = fs::read_to_string(&path)
+        .unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(2);
+        });
+
+    match intcc::compile(&src) {
+        Ok(prog) => println!("{}", prog),
+        Err(err) => {
+            eprintln!("{}:{}", path, err);
+            process::exit(1);
+        },
+    }
+}
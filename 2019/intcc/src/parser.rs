@@ -0,0 +1,404 @@
+//! Parsing of tokens into a syntax tree
+
+use crate::{Error, ErrorKind, Pos};
+use crate::lexer::Token;
+
+
+/// Binary operator
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+
+/// Unary operator
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+
+/// Expression, which always evaluates to a single integer
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Num(isize),
+    Var(String, Pos),
+    Call(String, Vec<Expr>, Pos),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+
+/// Statement within a function body
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Stmt {
+    Let(String, Expr),
+    Assign(String, Expr, Pos),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+
+/// Function definition
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Function {
+
+    /// Name the function is called by
+    pub name: String,
+
+    /// Names of the parameters, in order
+    pub params: Vec<String>,
+
+    /// Statements making up the body
+    pub body: Vec<Stmt>,
+
+    /// Position of the function's name
+    pub pos: Pos,
+}
+
+
+/// Recursive descent parser over a list of tokens
+struct Parser {
+    tokens: Vec<(Pos, Token)>,
+    idx: usize,
+}
+
+impl Parser {
+
+    fn peek(&self) -> &Token {
+
+        &self.tokens[self.idx].1
+    }
+
+    fn pos(&self) -> Pos {
+
+        self.tokens[self.idx].0
+    }
+
+    fn next(&mut self) -> Token {
+
+        let token = self.tokens[self.idx].1.clone();
+        if token != Token::Eof {
+            self.idx += 1;
+        }
+        token
+    }
+
+    /// Consumes the next token if it is `token`
+    fn eat(&mut self, token: &Token) -> bool {
+
+        let found = self.peek() == token;
+        if found {
+            self.idx += 1;
+        }
+        found
+    }
+
+    fn error(&self, expected: &'static str) -> Error {
+
+        Error {
+            pos: self.pos(),
+            kind: ErrorKind::Expected {
+                expected,
+                found: self.peek().clone(),
+            },
+        }
+    }
+
+    fn expect(&mut self, token: Token, expected: &'static str) -> Result<(), Error> {
+
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            Err(self.error(expected))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, Error> {
+
+        match self.peek() {
+            Token::Ident(_) => match self.next() {
+                Token::Ident(name) => Ok(name),
+                _ => unreachable!(),
+            },
+            _ => Err(self.error("identifier")),
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, Error> {
+
+        self.expect(Token::Fn, "`fn`")?;
+        let pos = self.pos();
+        let name = self.ident()?;
+        self.expect(Token::LParen, "`(`")?;
+
+        let mut params = Vec::new();
+        if !self.eat(&Token::RParen) {
+            loop {
+                params.push(self.ident()?);
+                if self.eat(&Token::RParen) {
+                    break;
+                }
+                self.expect(Token::Comma, "`,` or `)`")?;
+            }
+        }
+
+        let body = self.block()?;
+
+        Ok(Function { name, params, body, pos })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, Error> {
+
+        self.expect(Token::LBrace, "`{`")?;
+
+        let mut stmts = Vec::new();
+        while !self.eat(&Token::RBrace) {
+            stmts.push(self.stmt()?);
+        }
+
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, Error> {
+
+        let stmt = match self.peek() {
+            Token::Let => {
+                self.next();
+                let name = self.ident()?;
+                self.expect(Token::Assign, "`=`")?;
+                Stmt::Let(name, self.expr()?)
+            },
+            Token::If => {
+                self.next();
+                return self.if_rest();
+            },
+            Token::While => {
+                self.next();
+                let cond = self.expr()?;
+                return Ok(Stmt::While(cond, self.block()?));
+            },
+            Token::Return => {
+                self.next();
+                if *self.peek() == Token::Semi {
+                    Stmt::Return(None)
+                } else {
+                    Stmt::Return(Some(self.expr()?))
+                }
+            },
+            Token::Ident(_) if self.tokens[self.idx + 1].1 == Token::Assign => {
+                let pos = self.pos();
+                let name = self.ident()?;
+                self.next();
+                Stmt::Assign(name, self.expr()?, pos)
+            },
+            _ => Stmt::Expr(self.expr()?),
+        };
+
+        self.expect(Token::Semi, "`;`")?;
+
+        Ok(stmt)
+    }
+
+    /// Parses the remainder of an `if` statement after the keyword
+    fn if_rest(&mut self) -> Result<Stmt, Error> {
+
+        let cond = self.expr()?;
+        let then = self.block()?;
+
+        let otherwise = if !self.eat(&Token::Else) {
+            Vec::new()
+        } else if self.eat(&Token::If) {
+            vec![self.if_rest()?]
+        } else {
+            self.block()?
+        };
+
+        Ok(Stmt::If(cond, then, otherwise))
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+
+        self.binary(0)
+    }
+
+    /// Parses a chain of binary operators binding at least as tightly as
+    /// `level`
+    fn binary(&mut self, level: usize) -> Result<Expr, Error> {
+
+        const LEVELS: &[&[(Token, BinOp)]] = &[
+            &[(Token::Or, BinOp::Or)],
+            &[(Token::And, BinOp::And)],
+            &[(Token::Eq, BinOp::Eq), (Token::Ne, BinOp::Ne)],
+            &[
+                (Token::Lt, BinOp::Lt),
+                (Token::Le, BinOp::Le),
+                (Token::Gt, BinOp::Gt),
+                (Token::Ge, BinOp::Ge),
+            ],
+            &[(Token::Plus, BinOp::Add), (Token::Minus, BinOp::Sub)],
+            &[(Token::Star, BinOp::Mul)],
+        ];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut lhs = self.binary(level + 1)?;
+
+        while let Some(&(_, op)) = LEVELS[level].iter().find(|(t, _)| t == self.peek()) {
+            self.next();
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+
+        let op = match self.peek() {
+            Token::Minus => UnOp::Neg,
+            Token::Not => UnOp::Not,
+            _ => return self.primary(),
+        };
+        self.next();
+
+        Ok(match (op, self.unary()?) {
+            (UnOp::Neg, Expr::Num(n)) => Expr::Num(-n),
+            (op, e) => Expr::Unary(op, Box::new(e)),
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+
+        let pos = self.pos();
+
+        match self.peek() {
+            Token::Num(_) => match self.next() {
+                Token::Num(n) => Ok(Expr::Num(n)),
+                _ => unreachable!(),
+            },
+            Token::Ident(_) => {
+                let name = self.ident()?;
+                if !self.eat(&Token::LParen) {
+                    return Ok(Expr::Var(name, pos));
+                }
+                let mut args = Vec::new();
+                if !self.eat(&Token::RParen) {
+                    loop {
+                        args.push(self.expr()?);
+                        if self.eat(&Token::RParen) {
+                            break;
+                        }
+                        self.expect(Token::Comma, "`,` or `)`")?;
+                    }
+                }
+                Ok(Expr::Call(name, args, pos))
+            },
+            Token::LParen => {
+                self.next();
+                let e = self.expr()?;
+                self.expect(Token::RParen, "`)`")?;
+                Ok(e)
+            },
+            _ => Err(self.error("expression")),
+        }
+    }
+}
+
+
+/// Parses a whole source file into a list of functions
+pub fn parse(tokens: Vec<(Pos, Token)>) -> Result<Vec<Function>, Error> {
+
+    let mut parser = Parser { tokens, idx: 0 };
+    let mut funcs = Vec::new();
+
+    while *parser.peek() != Token::Eof {
+        funcs.push(parser.function()?);
+    }
+
+    Ok(funcs)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    fn parse_src(src: &str) -> Result<Vec<Function>, Error> {
+
+        parse(tokenize(src)?)
+    }
+
+    fn var(name: &str, line: usize, col: usize) -> Box<Expr> {
+
+        Box::new(Expr::Var(name.to_owned(), Pos { line, col }))
+    }
+
+    #[test]
+    fn precedence() {
+
+        let funcs = parse_src("fn f(a, b) { return a + b * 2 < -3 || !a; }")
+            .unwrap();
+
+        assert_eq!(funcs[0].params, vec!["a", "b"]);
+        assert_eq!(funcs[0].body, vec![Stmt::Return(Some(Expr::Binary(
+            BinOp::Or,
+            Box::new(Expr::Binary(
+                BinOp::Lt,
+                Box::new(Expr::Binary(
+                    BinOp::Add,
+                    var("a", 1, 21),
+                    Box::new(Expr::Binary(BinOp::Mul, var("b", 1, 25), Box::new(Expr::Num(2)))),
+                )),
+                Box::new(Expr::Num(-3)),
+            )),
+            Box::new(Expr::Unary(UnOp::Not, var("a", 1, 40))),
+        )))]);
+    }
+
+    #[test]
+    fn else_if() {
+
+        let funcs = parse_src("fn f() { if 1 { } else if 2 { x = 3; } else { } }")
+            .unwrap();
+
+        assert_eq!(funcs[0].body, vec![Stmt::If(
+            Expr::Num(1),
+            vec![],
+            vec![Stmt::If(
+                Expr::Num(2),
+                vec![Stmt::Assign("x".to_owned(), Expr::Num(3), Pos { line: 1, col: 31 })],
+                vec![],
+            )],
+        )]);
+    }
+
+    #[test]
+    fn errors() {
+
+        let err = parse_src("fn f() { let = 1; }")
+            .unwrap_err();
+        assert_eq!(err.pos, Pos { line: 1, col: 14 });
+        assert_eq!(err.kind, ErrorKind::Expected { expected: "identifier", found: Token::Assign });
+
+        let err = parse_src("fn f() { return 1 }")
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Expected { expected: "`;`", found: Token::RBrace });
+    }
+}
@@ -0,0 +1,426 @@
+//! Asynchronous I/O for intcode computers
+//!
+//! A computer whose I/O handler implements `AsyncIoHandler` can be run with
+//! `Computer::eval_async`, which yields to the executor whenever the program
+//! waits on input or output. Many machines can then share a single thread.
+//!
+//! No external runtime is required: `block_on` and `Executor` provide a small
+//! executor, and `channel` connects machines to each other.
+//!
+//! The futures returned by `eval_async` are not `Send`, since a `Computer`
+//! owns its extension handlers and memory-mapped devices as plain boxed trait
+//! objects. They must be polled on the thread which created them, which is
+//! all `block_on` and `Executor` do; multi-threaded runtimes will reject them.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use crate::InputError;
+
+
+/// Defines how to handle I/O operations asynchronously
+pub trait AsyncIoHandler {
+
+    /// Retrieves a single integer as input
+    fn input(&mut self) -> impl Future<Output = Result<isize, InputError>>;
+
+    /// Outputs a single integer
+    fn output(&mut self, val: isize) -> impl Future<Output = ()>;
+}
+
+
+/// Waker which flags a task as ready and unparks the executor thread
+struct Signal {
+    ready: AtomicBool,
+    thread: Thread,
+}
+
+impl Wake for Signal {
+
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.store(true, Ordering::SeqCst);
+        self.thread.unpark();
+    }
+}
+
+impl Signal {
+
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            ready: AtomicBool::new(true),
+            thread: thread::current(),
+        })
+    }
+
+    /// Clears the ready flag, returning whether it was set
+    fn take(&self) -> bool {
+        self.ready.swap(false, Ordering::SeqCst)
+    }
+}
+
+
+/// Runs a future to completion on the current thread
+pub fn block_on<F>(fut: F) -> F::Output
+where F: Future
+{
+    let mut fut = Box::pin(fut);
+    let signal = Signal::new();
+    let waker = Waker::from(signal.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if signal.take() {
+            if let Poll::Ready(val) = fut.as_mut().poll(&mut cx) {
+                return val;
+            }
+        } else {
+            thread::park();
+        }
+    }
+}
+
+
+/// Task spawned on an `Executor`
+struct Task<'a> {
+    fut: Pin<Box<dyn Future<Output = ()> + 'a>>,
+    signal: Arc<Signal>,
+}
+
+
+/// Single-threaded executor which runs many futures concurrently
+///
+/// Tasks may be woken by each other (e.g. through channels) or from other
+/// threads.
+#[derive(Default)]
+pub struct Executor<'a> {
+    tasks: Vec<Task<'a>>,
+}
+
+impl<'a> Executor<'a> {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a future to be run by `run`
+    pub fn spawn<F>(&mut self, fut: F)
+    where F: Future<Output = ()> + 'a
+    {
+        self.tasks.push(Task {
+            fut: Box::pin(fut),
+            signal: Signal::new(),
+        });
+    }
+
+    /// Runs every spawned future to completion
+    ///
+    /// Must be called from the thread which spawned the futures. While every
+    /// remaining task is waiting, the thread is parked until one is woken.
+    pub fn run(&mut self) {
+
+        while !self.tasks.is_empty() {
+
+            let mut polled = false;
+
+            self.tasks.retain_mut(|task| {
+
+                if !task.signal.take() {
+                    return true;
+                }
+
+                polled = true;
+
+                let waker = Waker::from(task.signal.clone());
+                let mut cx = Context::from_waker(&waker);
+
+                task.fut.as_mut().poll(&mut cx).is_pending()
+            });
+
+            if !polled {
+                thread::park();
+            }
+        }
+    }
+}
+
+
+/// State shared by both ends of a channel
+struct Shared<T> {
+    queue: VecDeque<T>,
+    waker: Option<Waker>,
+    senders: usize,
+    receiver: bool,
+}
+
+
+/// Sending half of a channel
+pub struct Sender<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> Sender<T> {
+
+    /// Queues a value, failing if the receiver has been dropped
+    pub fn send(&self, val: T) -> Result<(), T> {
+
+        let mut shared = self.shared.lock().unwrap();
+
+        if !shared.receiver {
+            return Err(val);
+        }
+
+        shared.queue.push_back(val);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().unwrap().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.senders -= 1;
+        if shared.senders == 0 {
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+
+/// Receiving half of a channel
+pub struct Receiver<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> Receiver<T> {
+
+    /// Waits for the next value
+    ///
+    /// Resolves to `None` once every sender has been dropped and the queue is
+    /// empty.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv {
+            rx: self,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.receiver = false;
+        shared.queue.clear();
+    }
+}
+
+
+/// Future returned by `Receiver::recv`
+pub struct Recv<'a, T> {
+    rx: &'a mut Receiver<T>,
+}
+
+impl<'a, T> Future for Recv<'a, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+
+        let mut shared = self.rx.shared.lock().unwrap();
+
+        if let Some(val) = shared.queue.pop_front() {
+            return Poll::Ready(Some(val));
+        }
+
+        if shared.senders == 0 {
+            return Poll::Ready(None);
+        }
+
+        shared.waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+
+/// Creates an unbounded channel which can be awaited on
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+
+    let shared = Arc::new(Mutex::new(Shared {
+        queue: VecDeque::new(),
+        waker: None,
+        senders: 1,
+        receiver: true,
+    }));
+
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+
+/// I/O handler which reads input from and writes output to channels
+///
+/// Input is unavailable once every sender of the input channel has been
+/// dropped. Output sent after the receiving end has been dropped is discarded.
+pub struct ChannelIo {
+    pub input: Receiver<isize>,
+    pub output: Sender<isize>,
+}
+
+impl AsyncIoHandler for ChannelIo {
+
+    async fn input(&mut self) -> Result<isize, InputError> {
+
+        self.input.recv()
+            .await
+            .ok_or(InputError::Unavailable)
+    }
+
+    async fn output(&mut self, val: isize) {
+
+        let _ = self.output.send(val);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::{Computer, Error};
+
+    #[test]
+    fn echo() {
+
+        let (tx_in, rx_in) = channel();
+        let (tx_out, mut rx_out) = channel();
+
+        let mut prog = [3,0,4,0,99];
+        let mut computer = Computer::new(ChannelIo {
+            input: rx_in,
+            output: tx_out,
+        });
+
+        tx_in.send(42)
+            .unwrap();
+
+        block_on(computer.eval_async(&mut prog))
+            .unwrap();
+
+        drop(computer);
+        assert_eq!(block_on(rx_out.recv()), Some(42));
+        assert_eq!(block_on(rx_out.recv()), None);
+    }
+
+    #[test]
+    fn ping_pong() {
+
+        // Reads a value, outputs it plus one, and repeats until it reaches 10
+        let prog = vec![
+            3,20,
+            1001,20,1,20,
+            4,20,
+            1007,20,10,21,
+            1005,21,0,
+            99,
+        ];
+
+        let (a_tx, a_rx) = channel();
+        let (b_tx, b_rx) = channel();
+        let (res_tx, mut res_rx) = channel();
+
+        a_tx.send(0)
+            .unwrap();
+
+        let mut exec = Executor::new();
+
+        for (name, input, output) in [("a", a_rx, b_tx), ("b", b_rx, a_tx)] {
+
+            let mut mem = prog.clone();
+            mem.resize(22, 0);
+            let res = res_tx.clone();
+
+            exec.spawn(async move {
+                Computer::new(ChannelIo { input, output })
+                    .eval_async(&mut mem)
+                    .await
+                    .unwrap();
+                res.send((name, mem[20]))
+                    .unwrap();
+            });
+        }
+
+        drop(res_tx);
+        exec.run();
+
+        let mut results = vec![];
+        while let Some(res) = block_on(res_rx.recv()) {
+            results.push(res);
+        }
+
+        assert_eq!(results, vec![("b", 10), ("a", 11)]);
+    }
+
+    #[test]
+    fn jump_out_of_bounds() {
+
+        let (_tx_in, rx_in) = channel();
+        let (tx_out, _rx_out) = channel();
+
+        let mut prog = [1106,0,50];
+        let res = block_on(Computer::new(ChannelIo { input: rx_in, output: tx_out })
+            .eval_async(&mut prog));
+
+        assert_eq!(res, Err(Error::Address));
+    }
+
+    #[test]
+    fn wake_from_thread() {
+
+        let (tx_in, rx_in) = channel();
+        let (tx_out, mut rx_out) = channel();
+
+        let mut exec = Executor::new();
+
+        exec.spawn(async move {
+            let mut mem = [3,0,4,0,99];
+            Computer::new(ChannelIo { input: rx_in, output: tx_out })
+                .eval_async(&mut mem)
+                .await
+                .unwrap();
+        });
+
+        // Every task is waiting when input arrives
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            tx_in.send(42)
+                .unwrap();
+        });
+
+        exec.run();
+        sender.join()
+            .unwrap();
+
+        assert_eq!(block_on(rx_out.recv()), Some(42));
+    }
+}
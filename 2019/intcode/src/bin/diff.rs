@@ -0,0 +1,38 @@
+use std::env;
+use std::process;
+
+use intcode::diff::Diff;
+use intcode::layout::Layout;
+use intcode::program::Program;
+
+
+fn main() {
+
+    let paths: Vec<String> = env::args()
+        .skip(1)
+        .collect();
+
+    if paths.len() != 2 {
+        eprintln!("usage: intcode_diff <old> <new>");
+        process::exit(2);
+    }
+
+    let load = |path: &String| Program::load(path)
+        .unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(2);
+        });
+
+    let old = load(&paths[0]);
+    let new = load(&paths[1]);
+
+    // Only a static layout is available without knowing the program's input
+    let diff = Diff::new(old.cells(), new.cells())
+        .annotate(&Layout::analyze(old.cells()));
+
+    print!("{}", diff);
+
+    if !diff.is_empty() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,45 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use intcode::image::Image;
+
+
+fn main() {
+
+    let paths: Vec<String> = env::args()
+        .skip(1)
+        .collect();
+
+    if paths.len() != 2 {
+        eprintln!("usage: intcode_image <program> <image>");
+        process::exit(2);
+    }
+
+    let mut image = Image::load(&paths[0])
+        .unwrap_or_else(|err| {
+            eprintln!("{}: {}", paths[0], err);
+            process::exit(2);
+        });
+
+    // Converting an image again keeps its original name
+    if image.name.is_none() {
+        image.name = Path::new(&paths[0])
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+    }
+
+    image.save(&paths[1])
+        .unwrap_or_else(|err| {
+            eprintln!("{}: {}", paths[1], err);
+            process::exit(2);
+        });
+
+    let before = fs::metadata(&paths[0])
+        .map(|m| m.len())
+        .unwrap_or(0);
+    let after = image.to_bytes().len();
+
+    println!("{} cells, {} -> {} bytes", image.cells.len(), before, after);
+}
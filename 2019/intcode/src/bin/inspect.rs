@@ -0,0 +1,70 @@
+use std::collections::VecDeque;
+use std::env;
+use std::io;
+use std::process;
+
+use intcode::{InputError, IoHandler};
+use intcode::inspect::Inspector;
+use intcode::program::Program;
+
+
+/// Takes input from the command line, then from standard input once that runs
+/// out; outputs only appear in the inspector's history
+struct ArgsIo {
+    input: VecDeque<isize>,
+}
+
+impl IoHandler for ArgsIo {
+
+    fn input(&mut self) -> Result<isize, InputError> {
+
+        if let Some(val) = self.input.pop_front() {
+            return Ok(val);
+        }
+
+        loop {
+            eprint!("input: ");
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => return Err(InputError::Unavailable),
+                Ok(_) => { },
+            }
+            match line.trim().parse() {
+                Ok(val) => return Ok(val),
+                Err(_) => eprintln!("not an integer: {:?}", line.trim()),
+            }
+        }
+    }
+
+    fn output(&mut self, _: isize) { }
+}
+
+
+fn main() {
+
+    let mut args = env::args()
+        .skip(1);
+
+    let path = args.next()
+        .unwrap_or_else(|| {
+            eprintln!("usage: intcode_inspect <program> [input...]");
+            process::exit(2);
+        });
+
+    let input = args.map(|arg| arg.parse())
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|err| {
+            eprintln!("bad input: {}", err);
+            process::exit(2);
+        });
+
+    let prog = Program::load(&path)
+        .unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(2);
+        });
+
+    Inspector::new(prog.cells(), ArgsIo { input })
+        .interact()
+        .expect("failed to access terminal");
+}
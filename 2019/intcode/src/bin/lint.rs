@@ -0,0 +1,32 @@
+use std::env;
+use std::process;
+
+use intcode::lint::{self, Severity};
+use intcode::program::Program;
+
+
+fn main() {
+
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| {
+            eprintln!("usage: intcode_lint <program>");
+            process::exit(2);
+        });
+
+    let prog = Program::load(&path)
+        .unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            process::exit(2);
+        });
+
+    let findings = lint::lint(prog.cells());
+
+    for finding in &findings {
+        println!("{}:{}", path, finding);
+    }
+
+    if findings.iter().any(|f| f.severity == Severity::Error) {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,43 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+
+use intcode::repl::{Repl, HELP};
+
+
+fn main() {
+
+    let mut repl = Repl::new();
+
+    // Optionally start with a program loaded
+    if let Some(path) = env::args().nth(1) {
+        match repl.exec(&format!(":load {}", path)) {
+            Ok(text) => print!("{}", text),
+            Err(err) => eprintln!("error: {}", err),
+        }
+    } else {
+        print!("{}", HELP);
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print!("> ");
+        io::stdout().flush()
+            .unwrap();
+
+        let line = match lines.next() {
+            Some(line) => line.expect("failed to read input"),
+            None => break,
+        };
+
+        if line.trim() == ":quit" {
+            break;
+        }
+
+        match repl.exec(&line) {
+            Ok(text) => print!("{}", text),
+            Err(err) => println!("error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,557 @@
+//! Table-driven conformance cases for intcode interpreters
+//!
+//! The cases in *conformance.txt* cover the example programs published
+//! alongside the 2019 intcode puzzles. Any interpreter can be checked against
+//! them by wrapping it in a closure which runs a program with given input.
+//! The day 7 amplifier circuits need several copies of a program running at
+//! once, so they are checked separately with an asynchronous runner (see
+//! `Case::check_circuits`).
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+
+use crate::{Computer, Error};
+use crate::aio::{self, ChannelIo, Executor};
+use crate::isa::Revision;
+use crate::program::Program;
+use crate::sweep::{Candidate, Sweep};
+
+
+/// Built-in conformance cases
+const CASES: &str = include_str!("conformance.txt");
+
+
+/// Single run of a conformance program
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Run {
+
+    /// Values supplied to input instructions
+    pub input: Vec<isize>,
+
+    /// Values the program is expected to output
+    pub output: Vec<isize>,
+}
+
+
+/// Copies of a conformance program connected in series, like the day 7
+/// amplifiers
+///
+/// Each copy is given its phase setting as its first input, and the first copy
+/// is then given 0. Every value a copy outputs is passed on as input to the
+/// next one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Circuit {
+
+    /// Phase setting of each copy, in order
+    pub phases: Vec<isize>,
+
+    /// Whether output of the last copy is also fed back into the first
+    pub feedback: bool,
+
+    /// Last value the last copy is expected to output
+    pub signal: isize,
+}
+
+
+/// Example program together with its expected behavior
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Case {
+
+    /// Name of the case
+    pub name: String,
+
+    /// Program under test
+    pub prog: Program,
+
+    /// Runs of the program, each starting from fresh memory
+    pub runs: Vec<Run>,
+
+    /// Circuits built from copies of the program
+    pub circuits: Vec<Circuit>,
+
+    /// Expected prefix of memory after each run
+    pub mem: Option<Vec<isize>>,
+
+    /// Earliest revision of the instruction set the program runs under
+    pub rev: Revision,
+}
+
+
+/// Way in which an interpreter failed a case
+#[derive(Debug)]
+pub enum Failure {
+
+    /// Interpreter returned an error
+    Error {
+        case: String,
+        run: usize,
+        err: Error,
+    },
+
+    /// Output did not match
+    Output {
+        case: String,
+        run: usize,
+        expected: Vec<isize>,
+        actual: Vec<isize>,
+    },
+
+    /// Final memory did not match
+    Memory {
+        case: String,
+        run: usize,
+        addr: usize,
+        expected: isize,
+        actual: Option<isize>,
+    },
+}
+
+impl fmt::Display for Failure {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            Self::Error { case, run, err } => write!(
+                f,
+                "{} (run {}): error {:?}",
+                case,
+                run,
+                err,
+            ),
+            Self::Output { case, run, expected, actual } => write!(
+                f,
+                "{} (run {}): expected output {:?}, got {:?}",
+                case,
+                run,
+                expected,
+                actual,
+            ),
+            Self::Memory { case, run, addr, expected, actual } => write!(
+                f,
+                "{} (run {}): expected [{}] = {}, got {:?}",
+                case,
+                run,
+                addr,
+                expected,
+                actual,
+            ),
+        }
+    }
+}
+
+
+/// Error encountered while parsing conformance cases
+#[derive(Debug, Eq, PartialEq)]
+pub struct CaseParseError {
+
+    /// Line number (starting from 1) of the offending line
+    pub line: usize,
+}
+
+
+fn parse_list(s: &str) -> Option<Vec<isize>> {
+
+    s.split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse().ok())
+        .collect()
+}
+
+
+impl Case {
+
+    /// Checks an interpreter against this case
+    ///
+    /// `run` is given fresh working memory and the input for a single run, and
+    /// returns the values output by the program. Circuits are not checked (see
+    /// `check_circuits`).
+    pub fn check<F>(&self, mut run: F) -> Result<(), Failure>
+    where F: FnMut(&mut [isize], &[isize]) -> Result<Vec<isize>, Error>
+    {
+        let default_run = [Run { input: vec![], output: vec![] }];
+        let runs = match (&self.runs[..], &self.circuits[..]) {
+            ([], []) => &default_run[..],
+            (runs, _) => runs,
+        };
+
+        for (i, r) in runs.iter().enumerate() {
+
+            let mut mem = self.prog.memory();
+
+            let output = run(&mut mem, &r.input)
+                .map_err(|err| Failure::Error {
+                    case: self.name.clone(),
+                    run: i,
+                    err,
+                })?;
+
+            if output != r.output {
+                return Err(Failure::Output {
+                    case: self.name.clone(),
+                    run: i,
+                    expected: r.output.clone(),
+                    actual: output,
+                });
+            }
+
+            for (addr, &expected) in self.mem.iter().flatten().enumerate() {
+                if mem.get(addr) != Some(&expected) {
+                    return Err(Failure::Memory {
+                        case: self.name.clone(),
+                        run: i,
+                        addr,
+                        expected,
+                        actual: mem.get(addr).cloned(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks an asynchronous interpreter against the circuits of this case
+    ///
+    /// `run` is given fresh working memory and channels to perform I/O with,
+    /// and returns a future which runs the program until it halts. The copies
+    /// making up a circuit run as separate tasks on an `Executor`. Failures
+    /// report the index of the circuit as the run.
+    pub fn check_circuits<F, Fut>(&self, mut run: F) -> Result<(), Failure>
+    where
+        F: FnMut(Vec<isize>, ChannelIo) -> Fut,
+        Fut: Future<Output = Result<(), Error>>,
+    {
+        for (i, circuit) in self.circuits.iter().enumerate() {
+
+            let err = RefCell::new(None);
+            let signal = RefCell::new(None);
+
+            let (txs, rxs): (Vec<_>, Vec<_>) = circuit.phases.iter()
+                .map(|_| aio::channel())
+                .unzip();
+
+            for (tx, &phase) in txs.iter().zip(&circuit.phases) {
+                let _ = tx.send(phase);
+            }
+            if let Some(tx) = txs.first() {
+                let _ = tx.send(0);
+            }
+
+            // Output of the last copy goes through a tap which records it
+            let (tap_tx, mut tap_rx) = aio::channel();
+            let mut txs = txs.into_iter();
+            let first = txs.next();
+            let outputs = txs.chain(Some(tap_tx));
+
+            let mut exec = Executor::new();
+
+            for (input, output) in rxs.into_iter().zip(outputs) {
+                let fut = run(self.prog.memory(), ChannelIo { input, output });
+                let err = &err;
+                exec.spawn(async move {
+                    if let Err(e) = fut.await {
+                        err.borrow_mut().get_or_insert(e);
+                    }
+                });
+            }
+
+            {
+                let feedback = first.filter(|_| circuit.feedback);
+                let signal = &signal;
+                exec.spawn(async move {
+                    while let Some(val) = tap_rx.recv().await {
+                        *signal.borrow_mut() = Some(val);
+                        if let Some(tx) = &feedback {
+                            let _ = tx.send(val);
+                        }
+                    }
+                });
+            }
+
+            exec.run();
+            drop(exec);
+
+            if let Some(err) = err.into_inner() {
+                return Err(Failure::Error {
+                    case: self.name.clone(),
+                    run: i,
+                    err,
+                });
+            }
+
+            let signal = signal.into_inner();
+            if signal != Some(circuit.signal) {
+                return Err(Failure::Output {
+                    case: self.name.clone(),
+                    run: i,
+                    expected: vec![circuit.signal],
+                    actual: signal.into_iter().collect(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Parses conformance cases from text
+///
+/// See *conformance.txt* for a description of the format.
+pub fn parse(src: &str) -> Result<Vec<Case>, CaseParseError> {
+
+    let mut cases: Vec<Case> = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+
+        let err = || CaseParseError { line: i + 1 };
+
+        let line = match line.find('#') {
+            Some(idx) => &line[..idx],
+            None => line,
+        }.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            cases.push(Case {
+                name: line[1..line.len() - 1].trim().to_owned(),
+                prog: Program::default(),
+                runs: Vec::new(),
+                circuits: Vec::new(),
+                mem: None,
+                rev: Revision::default(),
+            });
+            continue;
+        }
+
+        let case = cases.last_mut()
+            .ok_or_else(err)?;
+
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next()
+            .unwrap()
+            .trim();
+        let val = parts.next()
+            .ok_or_else(err)?;
+
+        match key {
+            "prog" => {
+                case.prog = val.parse()
+                    .map_err(|_| err())?;
+            },
+            "mem" => {
+                case.mem = Some(parse_list(val).ok_or_else(err)?);
+            },
+            "rev" => {
+                case.rev = Revision::by_name(val.trim())
+                    .ok_or_else(err)?;
+            },
+            "io" => {
+                let mut io = val.splitn(2, "->");
+                let input = parse_list(io.next().unwrap())
+                    .ok_or_else(err)?;
+                let output = io.next()
+                    .and_then(parse_list)
+                    .ok_or_else(err)?;
+                case.runs.push(Run { input, output });
+            },
+            "chain" | "loop" => {
+                let mut io = val.splitn(2, "->");
+                let phases = parse_list(io.next().unwrap())
+                    .ok_or_else(err)?;
+                let signal = io.next()
+                    .and_then(|s| s.trim().parse().ok())
+                    .ok_or_else(err)?;
+                case.circuits.push(Circuit {
+                    phases,
+                    feedback: key == "loop",
+                    signal,
+                });
+            },
+            _ => return Err(err()),
+        }
+    }
+
+    Ok(cases)
+}
+
+/// Returns the built-in conformance cases
+pub fn cases() -> Vec<Case> {
+
+    parse(CASES)
+        .expect("built-in conformance cases are malformed")
+}
+
+/// Runs a program on the shared `Computer`, for use with `Case::check`
+pub fn run_computer(mem: &mut [isize], input: &[isize]) -> Result<Vec<isize>, Error> {
+
+    run_revision(Revision::default())(mem, input)
+}
+
+/// Runs a program on the shared `Computer` asynchronously, for use with
+/// `Case::check_circuits`
+pub async fn run_computer_async(
+    rev: Revision,
+    mut mem: Vec<isize>,
+    io: ChannelIo,
+) -> Result<(), Error> {
+
+    let mut computer = Computer::new(io);
+    computer.set_revision(rev);
+
+    computer.eval_async(&mut mem)
+        .await
+}
+
+/// Returns a runner like `run_computer`, limited to a revision of the
+/// instruction set
+pub fn run_revision(rev: Revision) -> impl Fn(&mut [isize], &[isize]) -> Result<Vec<isize>, Error> {
+
+    move |mem, input| {
+        let outcome = Sweep::new(mem)
+            .revision(rev)
+            .run(&Candidate::input(input.to_vec()))?;
+
+        mem.copy_from_slice(&outcome.mem);
+
+        Ok(outcome.output)
+    }
+}
+
+/// Checks that an interpreter follows the rules of a revision
+///
+/// `run` should be limited to `rev`. Cases needing at most `rev` must pass.
+/// Later cases must either pass, since their programs may happen to avoid
+/// anything new, or fail cleanly with `Error::Opcode` or `Error::Mode`.
+pub fn check_revision<F>(rev: Revision, mut run: F) -> Vec<Failure>
+where F: FnMut(&mut [isize], &[isize]) -> Result<Vec<isize>, Error>
+{
+    cases().iter()
+        .filter_map(|case| case.check(&mut run).err().map(|f| (case, f)))
+        .filter(|(case, f)| case.rev <= rev || !matches!(
+            f,
+            Failure::Error { err: Error::Opcode, .. } | Failure::Error { err: Error::Mode, .. },
+        ))
+        .map(|(_, f)| f)
+        .collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn computer_conforms() {
+
+        let failures: Vec<String> = cases().iter()
+            .filter_map(|case| case.check(run_computer).err())
+            .map(|f| f.to_string())
+            .collect();
+
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn computer_circuits() {
+
+        let failures: Vec<String> = cases().iter()
+            .filter_map(|case| {
+                let run = |mem, io| run_computer_async(case.rev, mem, io);
+                case.check_circuits(run).err()
+            })
+            .map(|f| f.to_string())
+            .collect();
+
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+
+        // Day 2 programs lack I/O, so cannot take part in a circuit
+        let res = parse("[a]\nrev = day2\nprog = 3,0,4,0,99\nloop = 1 -> 1")
+            .unwrap()[0]
+            .check_circuits(|mem, io| run_computer_async(Revision::Day2, mem, io));
+        assert!(matches!(res, Err(Failure::Error { err: Error::Opcode, .. })));
+    }
+
+    #[test]
+    fn revisions() {
+
+        for &rev in &Revision::ALL {
+            let failures: Vec<String> = check_revision(rev, run_revision(rev))
+                .iter()
+                .map(|f| f.to_string())
+                .collect();
+            assert!(failures.is_empty(), "{}:\n{}", rev.name(), failures.join("\n"));
+        }
+
+        // Earlier revisions reject later cases cleanly, rather than passing
+        // by accident
+        let echo = cases().into_iter()
+            .find(|c| c.name == "day5-echo")
+            .unwrap();
+        assert!(matches!(
+            echo.check(run_revision(Revision::Day2)),
+            Err(Failure::Error { err: Error::Opcode, .. }),
+        ));
+
+        let quine = cases().into_iter()
+            .find(|c| c.name == "day9-quine")
+            .unwrap();
+        assert!(quine.check(run_revision(Revision::Day5)).is_err());
+    }
+
+    #[test]
+    fn covers_every_day() {
+
+        let cases = cases();
+
+        for day in &["day2-", "day5-", "day7-", "day9-"] {
+            assert!(cases.iter().any(|c| c.name.starts_with(day)));
+        }
+
+        for &feedback in &[false, true] {
+            assert!(cases.iter()
+                .flat_map(|c| &c.circuits)
+                .any(|c| c.feedback == feedback));
+        }
+    }
+
+    #[test]
+    fn detects_wrong_output() {
+
+        let cases = parse("[neg]\nprog = 3,0,4,0,99\nio = 5 -> 5")
+            .unwrap();
+
+        let res = cases[0].check(|mem, input| {
+            let mut output = run_computer(mem, input)?;
+            output[0] = -output[0];
+            Ok(output)
+        });
+
+        assert!(matches!(res, Err(Failure::Output { .. })));
+
+        // Each copy outputs its phase plus its input, so the chain gives 3
+        let cases = parse("[sum]\nprog = 3,11,3,12,1,11,12,11,4,11,99\nchain = 1,2 -> 5")
+            .unwrap();
+
+        let res = cases[0].check_circuits(|mem, io| run_computer_async(Revision::Day9, mem, io));
+
+        assert!(matches!(
+            res,
+            Err(Failure::Output { ref expected, ref actual, .. }) if expected == &[5] && actual == &[3],
+        ));
+    }
+
+    #[test]
+    fn parse_errors() {
+
+        assert_eq!(parse("prog = 99").unwrap_err().line, 1);
+        assert_eq!(parse("[a]\n\nfoo = 1").unwrap_err().line, 3);
+        assert_eq!(parse("[a]\nio = 1, x -> 2").unwrap_err().line, 2);
+        assert_eq!(parse("[a]\nloop = 1, 2").unwrap_err().line, 2);
+    }
+}
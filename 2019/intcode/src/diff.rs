@@ -0,0 +1,238 @@
+//! Comparison of intcode memory images
+//!
+//! Images may be the final memories of two runs, or snapshots taken at two
+//! points of a single run (e.g. with `Session::mem`). Changed cells are grouped
+//! into ranges which, given a `Layout`, are labelled as code or data.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::layout::{Kind, Layout};
+
+
+/// Run of consecutive changed cells
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Change {
+
+    /// Addresses of the cells
+    pub range: Range<usize>,
+
+    /// Values before the change
+    pub old: Vec<isize>,
+
+    /// Values after the change
+    pub new: Vec<isize>,
+
+    /// What the cells are used for, if known
+    pub kind: Option<Kind>,
+}
+
+impl fmt::Display for Change {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        let join = |vals: &[isize]| vals.iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        write!(f, "{}..{}", self.range.start, self.range.end)?;
+
+        match self.kind {
+            Some(Kind::Code) => write!(f, " code")?,
+            Some(Kind::Data) => write!(f, " data")?,
+            Some(Kind::Unknown) => write!(f, " unknown")?,
+            None => { },
+        }
+
+        write!(f, ": {} -> {}", join(&self.old), join(&self.new))
+    }
+}
+
+
+/// Differences between two memory images
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Diff {
+    changes: Vec<Change>,
+}
+
+impl Diff {
+
+    /// Compares two images
+    ///
+    /// Where one image is shorter, its missing cells are taken to be zero,
+    /// matching the padding of working memory.
+    pub fn new(old: &[isize], new: &[isize]) -> Self {
+
+        let len = old.len().max(new.len());
+        let cell = |mem: &[isize], addr| mem.get(addr).cloned().unwrap_or(0);
+
+        let mut changes: Vec<Change> = Vec::new();
+
+        for addr in (0..len).filter(|&a| cell(old, a) != cell(new, a)) {
+            match changes.last_mut() {
+                Some(c) if c.range.end == addr => {
+                    c.range.end += 1;
+                    c.old.push(cell(old, addr));
+                    c.new.push(cell(new, addr));
+                },
+                _ => changes.push(Change {
+                    range: addr..addr + 1,
+                    old: vec![cell(old, addr)],
+                    new: vec![cell(new, addr)],
+                    kind: None,
+                }),
+            }
+        }
+
+        Self { changes }
+    }
+
+    /// Labels changes as code or data, splitting any which span both
+    pub fn annotate(self, layout: &Layout) -> Self {
+
+        let mut changes: Vec<Change> = Vec::new();
+
+        for change in self.changes {
+            for (i, addr) in change.range.clone().enumerate() {
+                let kind = Some(layout.kind(addr));
+                match changes.last_mut() {
+                    Some(c) if c.range.end == addr && c.kind == kind => {
+                        c.range.end += 1;
+                        c.old.push(change.old[i]);
+                        c.new.push(change.new[i]);
+                    },
+                    _ => changes.push(Change {
+                        range: addr..addr + 1,
+                        old: vec![change.old[i]],
+                        new: vec![change.new[i]],
+                        kind,
+                    }),
+                }
+            }
+        }
+
+        Self { changes }
+    }
+
+    pub fn changes(&self) -> &[Change] {
+
+        &self.changes
+    }
+
+    /// Whether the images are identical
+    pub fn is_empty(&self) -> bool {
+
+        self.changes.is_empty()
+    }
+
+    /// Addresses of every changed cell
+    pub fn addrs(&self) -> impl Iterator<Item = usize> + '_ {
+
+        self.changes.iter()
+            .flat_map(|c| c.range.clone())
+    }
+}
+
+impl fmt::Display for Diff {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::patch::Patch;
+    use crate::program::Program;
+
+    const GRAV_PROG: &str = include_str!("grav-prog.txt");
+
+    struct NoIo;
+
+    impl crate::IoHandler for NoIo {
+
+        fn input(&mut self) -> Result<isize, crate::InputError> {
+            Err(crate::InputError::Unavailable)
+        }
+
+        fn output(&mut self, _: isize) {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn grouping() {
+
+        let diff = Diff::new(&[1, 2, 3, 4, 5], &[1, 9, 9, 4, 5, 0, 7]);
+
+        assert_eq!(diff.to_string(), "1..3: 2 3 -> 9 9\n6..7: 0 -> 7\n");
+        assert_eq!(diff.addrs().collect::<Vec<_>>(), vec![1, 2, 6]);
+        assert!(Diff::new(&[1, 0], &[1]).is_empty());
+    }
+
+    #[test]
+    fn annotated() {
+
+        // Adds the cells at 9 and 10, storing the result at 0
+        let prog = [1,9,10,0,99,0,0,0,0,3,4];
+        let layout = Layout::analyze(&prog);
+
+        let diff = Diff::new(&prog, &[7,9,10,0,99,0,0,0,0,3,5])
+            .annotate(&layout);
+
+        assert_eq!(diff.to_string(), "0..1 code: 1 -> 7\n10..11 data: 4 -> 5\n");
+
+        let diff = Diff::new(&prog, &[1,9,10,0,99,0,0,0,1,2,4])
+            .annotate(&layout);
+
+        assert_eq!(diff.to_string(), "8..9 unknown: 0 -> 1\n9..10 data: 3 -> 2\n");
+    }
+
+    #[test]
+    fn day2_noun() {
+
+        let prog: Program = GRAV_PROG.parse()
+            .unwrap();
+
+        let run = |noun| {
+            let mut mem = prog.memory();
+            Patch::new("noun")
+                .set(1, 0, noun)
+                .and_then(|p| p.set(2, 0, 2))
+                .and_then(|p| p.apply(&mut mem))
+                .unwrap();
+            let layout = Layout::trace(&mem[..prog.len()], NoIo)
+                .unwrap();
+            crate::eval(&mut mem)
+                .unwrap();
+            (mem, layout)
+        };
+
+        let (a, layout) = run(12);
+        let (b, _) = run(13);
+
+        let diff = Diff::new(&a, &b)
+            .annotate(&layout);
+
+        // The noun itself, and the result which depends on it
+        assert_eq!(diff.changes()[0].range, 0..2);
+        assert_eq!(diff.changes()[0].old, vec![10566835, 12]);
+        assert_eq!(diff.changes()[0].new, vec![11396275, 13]);
+
+        // Every intermediate sum and product is written over the last cell of
+        // an instruction, so those cells depend on the noun too
+        let scratch: Vec<_> = diff.addrs()
+            .skip(2)
+            .collect();
+        assert_eq!(scratch, (19..164).step_by(4).collect::<Vec<_>>());
+        assert!(diff.changes().iter().all(|c| c.kind == Some(Kind::Code)));
+    }
+}
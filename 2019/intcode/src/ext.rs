@@ -0,0 +1,107 @@
+//! Custom opcodes which extend the built-in intcode instruction set
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::Error;
+
+
+/// Role of a single instruction parameter
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Param {
+
+    /// Parameter is loaded before the handler runs
+    Read,
+
+    /// Parameter is stored to after the handler runs
+    Write,
+}
+
+
+/// State visible to the handler of a custom opcode
+pub struct Invocation<'a> {
+
+    /// Address of the instruction being executed
+    pub(crate) ip: usize,
+
+    /// Relative base
+    pub(crate) rb: isize,
+
+    /// Main memory
+    pub(crate) mem: &'a [isize],
+
+    /// Loaded values of read parameters (write parameters hold 0)
+    pub(crate) args: Vec<isize>,
+
+    /// Values to store to write parameters
+    pub(crate) results: Vec<Option<isize>>,
+
+    /// Address to continue execution from, if not the next instruction
+    pub(crate) jump: Option<usize>,
+}
+
+impl<'a> Invocation<'a> {
+
+    /// Value of the read parameter at `idx`
+    ///
+    /// Panics if `idx` is not a read parameter.
+    pub fn get(&self, idx: usize) -> isize {
+
+        assert!(self.results[idx].is_none(), "parameter {} is not readable", idx);
+        self.args[idx]
+    }
+
+    /// Stores `val` to the write parameter at `idx`
+    ///
+    /// Panics if `idx` is not a write parameter.
+    pub fn set(&mut self, idx: usize, val: isize) {
+
+        let res = self.results[idx].as_mut()
+            .unwrap_or_else(|| panic!("parameter {} is not writable", idx));
+        *res = val;
+    }
+
+    /// Continues execution at `addr` instead of the next instruction
+    pub fn jump(&mut self, addr: usize) {
+
+        self.jump = Some(addr);
+    }
+
+    /// Address of the instruction being executed
+    pub fn ip(&self) -> usize {
+
+        self.ip
+    }
+
+    /// Current relative base
+    pub fn rb(&self) -> isize {
+
+        self.rb
+    }
+
+    /// Contents of main memory
+    pub fn mem(&self) -> &[isize] {
+
+        self.mem
+    }
+}
+
+
+/// Handler invoked when a custom opcode is executed
+pub type Handler = Box<dyn FnMut(&mut Invocation) -> Result<(), Error>>;
+
+
+/// Definition of a custom opcode
+pub(crate) struct Extension {
+
+    /// Role of each parameter, in order
+    pub(crate) params: Vec<Param>,
+
+    /// Implementation of the opcode
+    pub(crate) handler: Handler,
+}
+
+
+/// Custom opcodes registered on a computer
+pub(crate) type Registry = BTreeMap<isize, Extension>;
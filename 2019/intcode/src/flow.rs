@@ -0,0 +1,147 @@
+//! Static walk of the control flow reachable from address 0
+//!
+//! Both outcomes of a conditional jump are followed unless its condition is
+//! an immediate value. Jumps whose target is an immediate value are followed;
+//! jumps through memory cannot be resolved statically, so they are only
+//! noted. Instructions are decoded as written, even if the program rewrites
+//! them before they run.
+
+use std::convert::TryFrom;
+
+use crate::ext::Param;
+use crate::isa::{self, Instr};
+
+
+/// What the walk found at a reachable address
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Node {
+
+    /// Built-in instruction, which is followed only if its parameters are
+    /// valid (see `params_valid`)
+    Instr(&'static Instr),
+
+    /// Cell is not a built-in opcode
+    Unknown,
+
+    /// Instruction extends past the end of the program
+    Truncated,
+
+    /// Address just past the end of the program
+    End,
+}
+
+
+/// Reachable part of a program
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Flow {
+
+    /// Reachable addresses and what was found there, in the order visited
+    pub(crate) nodes: Vec<(usize, Node)>,
+
+    /// Transfers of control between reachable addresses, as `(from, to)`
+    pub(crate) edges: Vec<(usize, usize)>,
+
+    /// Jumps whose immediate target is outside of the program, as
+    /// `(addr, target)`
+    pub(crate) bad_targets: Vec<(usize, isize)>,
+
+    /// Whether any reachable jump goes through memory
+    pub(crate) computed_jumps: bool,
+}
+
+impl Flow {
+
+    /// Walks the control flow of `prog` from address 0
+    pub(crate) fn walk(prog: &[isize]) -> Self {
+
+        let mut flow = Self::default();
+
+        if prog.is_empty() {
+            return flow;
+        }
+
+        let mut visited = vec![false; prog.len() + 1];
+        let mut pending = vec![0];
+
+        while let Some(addr) = pending.pop() {
+
+            if visited[addr] {
+                continue;
+            }
+            visited[addr] = true;
+
+            if addr == prog.len() {
+                flow.nodes.push((addr, Node::End));
+                continue;
+            }
+
+            let op = prog[addr];
+            let instr = match isa::lookup(isa::opcode(op)) {
+                Some(instr) if op > 0 => instr,
+                _ => {
+                    flow.nodes.push((addr, Node::Unknown));
+                    continue;
+                },
+            };
+
+            let next = addr + instr.size();
+            if next > prog.len() {
+                flow.nodes.push((addr, Node::Truncated));
+                continue;
+            }
+
+            flow.nodes.push((addr, Node::Instr(instr)));
+
+            let args = &prog[addr + 1..next];
+            if !params_valid(op, instr, args) || instr.opcode == 99 {
+                continue;
+            }
+
+            let mut succs = Vec::new();
+
+            // Condition is fixed if given as an immediate value
+            let taken = match (instr.is_jump(), isa::mode(op, 0)) {
+                (false, _) => Some(false),
+                (true, 1) => Some((args[0] != 0) == (instr.opcode == 5)),
+                (true, _) => None,
+            };
+
+            if taken != Some(false) {
+                if isa::mode(op, 1) == 1 {
+                    match usize::try_from(args[1]) {
+                        Ok(target) if target < prog.len() => succs.push(target),
+                        _ => flow.bad_targets.push((addr, args[1])),
+                    }
+                } else {
+                    flow.computed_jumps = true;
+                }
+            }
+
+            if taken != Some(true) {
+                succs.push(next);
+            }
+
+            for succ in succs {
+                flow.edges.push((addr, succ));
+                pending.push(succ);
+            }
+        }
+
+        flow
+    }
+}
+
+
+/// Whether every parameter of an instruction has a mode which is valid for
+/// its role, and position mode parameters refer to non-negative addresses
+pub(crate) fn params_valid(op: isize, instr: &Instr, args: &[isize]) -> bool {
+
+    instr.params.iter()
+        .zip(args)
+        .enumerate()
+        .all(|(i, (&role, &arg))| match (isa::mode(op, i), role) {
+            (0, _) => arg >= 0,
+            (1, Param::Read) | (2, _) => true,
+            _ => false,
+        })
+}
@@ -0,0 +1,441 @@
+//! Framing for protocols built from groups of values
+//!
+//! Many programs output fixed-size groups of values, such as `(x, y, tile)`
+//! triples, and some expect several values per input. A `Protocol` deals only
+//! in whole frames and inputs; wrapping it in `Framed` produces an I/O handler
+//! which does the grouping and splitting.
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::{InputError, IoHandler};
+
+
+/// Value decoded from a fixed number of consecutive outputs
+pub trait Frame: Sized {
+
+    /// Number of values making up one frame, which must be at least 1
+    const LEN: usize;
+
+    /// Builds a frame from exactly `LEN` values
+    fn decode(vals: &[isize]) -> Self;
+}
+
+impl Frame for isize {
+
+    const LEN: usize = 1;
+
+    fn decode(vals: &[isize]) -> Self {
+        vals[0]
+    }
+}
+
+impl Frame for (isize, isize) {
+
+    const LEN: usize = 2;
+
+    fn decode(vals: &[isize]) -> Self {
+        (vals[0], vals[1])
+    }
+}
+
+impl Frame for (isize, isize, isize) {
+
+    const LEN: usize = 3;
+
+    fn decode(vals: &[isize]) -> Self {
+        (vals[0], vals[1], vals[2])
+    }
+}
+
+impl<const N: usize> Frame for [isize; N] {
+
+    const LEN: usize = N;
+
+    fn decode(vals: &[isize]) -> Self {
+        let mut frame = [0; N];
+        frame.copy_from_slice(vals);
+        frame
+    }
+}
+
+
+/// Value encoded as one or more consecutive inputs
+pub trait Encode {
+
+    /// Appends the values making up `self`
+    fn encode(&self, vals: &mut Vec<isize>);
+}
+
+impl Encode for isize {
+
+    fn encode(&self, vals: &mut Vec<isize>) {
+        vals.push(*self);
+    }
+}
+
+impl Encode for (isize, isize) {
+
+    fn encode(&self, vals: &mut Vec<isize>) {
+        vals.extend(&[self.0, self.1]);
+    }
+}
+
+impl Encode for [isize] {
+
+    fn encode(&self, vals: &mut Vec<isize>) {
+        vals.extend(self);
+    }
+}
+
+impl<const N: usize> Encode for [isize; N] {
+
+    fn encode(&self, vals: &mut Vec<isize>) {
+        vals.extend(self);
+    }
+}
+
+impl Encode for Vec<isize> {
+
+    fn encode(&self, vals: &mut Vec<isize>) {
+        vals.extend(self);
+    }
+}
+
+/// Text is encoded one character code at a time
+impl Encode for str {
+
+    fn encode(&self, vals: &mut Vec<isize>) {
+        vals.extend(self.chars().map(|c| c as isize));
+    }
+}
+
+impl Encode for String {
+
+    fn encode(&self, vals: &mut Vec<isize>) {
+        self.as_str().encode(vals);
+    }
+}
+
+
+/// Groups values into frames
+#[derive(Clone, Debug)]
+pub struct Decoder<F> {
+    buf: Vec<isize>,
+    frame: PhantomData<F>,
+}
+
+impl<F> Default for Decoder<F> {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            frame: PhantomData,
+        }
+    }
+}
+
+impl<F> Decoder<F>
+where F: Frame
+{
+
+    /// Adds a value, returning the frame it completes (if any)
+    ///
+    /// Fails to compile for frames with a `LEN` of 0.
+    pub fn push(&mut self, val: isize) -> Option<F> {
+
+        const { assert!(F::LEN > 0, "frames must be made up of at least one value") };
+
+        self.buf.push(val);
+
+        if self.buf.len() < F::LEN {
+            return None;
+        }
+
+        let frame = F::decode(&self.buf);
+        self.buf.clear();
+
+        Some(frame)
+    }
+
+    /// Values received since the last complete frame
+    pub fn partial(&self) -> &[isize] {
+
+        &self.buf
+    }
+}
+
+
+/// Splits inputs into single values
+#[derive(Clone, Debug, Default)]
+pub struct Encoder {
+    queue: VecDeque<isize>,
+}
+
+impl Encoder {
+
+    /// Queues every value making up `input`
+    pub fn push<E>(&mut self, input: &E)
+    where E: Encode + ?Sized
+    {
+        let mut vals = Vec::new();
+        input.encode(&mut vals);
+        self.queue.extend(vals);
+    }
+
+    /// Takes the next queued value
+    pub fn pop(&mut self) -> Option<isize> {
+
+        self.queue.pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+
+        self.queue.is_empty()
+    }
+}
+
+
+/// Exchange of whole frames and inputs with a program
+pub trait Protocol {
+
+    /// Group of values output by the program
+    type Frame: Frame;
+
+    /// Input the program expects, possibly made up of several values
+    type Input: Encode;
+
+    /// Handles a complete frame of output
+    fn frame(&mut self, frame: Self::Frame);
+
+    /// Retrieves the next input, once every value of the previous one has
+    /// been consumed
+    ///
+    /// Inputs which encode to no values are skipped, and another is requested.
+    fn input(&mut self) -> Result<Self::Input, InputError>;
+}
+
+impl<P> Protocol for &mut P
+where P: Protocol
+{
+    type Frame = P::Frame;
+    type Input = P::Input;
+
+    fn frame(&mut self, frame: Self::Frame) {
+        (**self).frame(frame)
+    }
+
+    fn input(&mut self) -> Result<Self::Input, InputError> {
+        (**self).input()
+    }
+}
+
+
+/// I/O handler speaking a `Protocol`
+pub struct Framed<P>
+where P: Protocol
+{
+    protocol: P,
+    decoder: Decoder<P::Frame>,
+    encoder: Encoder,
+}
+
+impl<P> Framed<P>
+where P: Protocol
+{
+
+    pub fn new(protocol: P) -> Self {
+        Self {
+            protocol,
+            decoder: Decoder::default(),
+            encoder: Encoder::default(),
+        }
+    }
+
+    pub fn protocol(&self) -> &P {
+
+        &self.protocol
+    }
+
+    pub fn into_inner(self) -> P {
+
+        self.protocol
+    }
+
+    /// Outputs received since the last complete frame
+    ///
+    /// Anything left here after the program halts was never framed.
+    pub fn partial(&self) -> &[isize] {
+
+        self.decoder.partial()
+    }
+}
+
+impl<P> IoHandler for Framed<P>
+where P: Protocol
+{
+
+    fn input(&mut self) -> Result<isize, InputError> {
+
+        // Inputs may encode to nothing (e.g. empty text), so keep asking
+        while self.encoder.is_empty() {
+            let input = self.protocol.input()?;
+            self.encoder.push(&input);
+        }
+
+        self.encoder.pop()
+            .ok_or(InputError::Unavailable)
+    }
+
+    fn output(&mut self, val: isize) {
+
+        if let Some(frame) = self.decoder.push(val) {
+            self.protocol.frame(frame);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Computer;
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct Pixel {
+        x: isize,
+        y: isize,
+        lit: bool,
+    }
+
+    impl Frame for Pixel {
+
+        const LEN: usize = 3;
+
+        fn decode(vals: &[isize]) -> Self {
+            Self { x: vals[0], y: vals[1], lit: vals[2] != 0 }
+        }
+    }
+
+    #[test]
+    fn decoder() {
+
+        let mut pairs = Decoder::<(isize, isize)>::default();
+        assert_eq!(pairs.push(1), None);
+        assert_eq!(pairs.partial(), &[1]);
+        assert_eq!(pairs.push(2), Some((1, 2)));
+        assert_eq!(pairs.partial(), &[]);
+
+        let mut pixels = Decoder::<Pixel>::default();
+        let frames: Vec<_> = [3, 4, 1, 5, 6, 0, 7]
+            .iter()
+            .filter_map(|&v| pixels.push(v))
+            .collect();
+        assert_eq!(frames, vec![
+            Pixel { x: 3, y: 4, lit: true },
+            Pixel { x: 5, y: 6, lit: false },
+        ]);
+
+        let mut quads = Decoder::<[isize; 4]>::default();
+        assert_eq!((1..=4).filter_map(|v| quads.push(v)).next(), Some([1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn encoder() {
+
+        let mut enc = Encoder::default();
+        enc.push(&(1, 2));
+        enc.push("ab\n");
+        enc.push(&7);
+
+        let vals: Vec<_> = std::iter::from_fn(|| enc.pop()).collect();
+        assert_eq!(vals, vec![1, 2, 97, 98, 10, 7]);
+    }
+
+    /// Reads points and outputs each one mirrored in both axes, along with the
+    /// sum of its coordinates
+    struct Mirror {
+        points: Vec<(isize, isize)>,
+        seen: Vec<(isize, isize, isize)>,
+    }
+
+    impl Protocol for Mirror {
+
+        type Frame = (isize, isize, isize);
+        type Input = (isize, isize);
+
+        fn frame(&mut self, frame: Self::Frame) {
+            self.seen.push(frame);
+        }
+
+        fn input(&mut self) -> Result<Self::Input, InputError> {
+            self.points.pop()
+                .ok_or(InputError::Unavailable)
+        }
+    }
+
+    #[test]
+    fn framed() {
+
+        // Loops: x = in, y = in, out -x, out -y, out x + y
+        let mut prog = vec![
+            3,100, 3,101,
+            1002,100,-1,102, 1002,101,-1,103, 1,100,101,104,
+            4,102, 4,103, 4,104,
+            1105,1,0,
+        ];
+        prog.resize(105, 0);
+
+        let mut mirror = Mirror {
+            points: vec![(5, 6), (1, -2)],
+            seen: Vec::new(),
+        };
+
+        let mut computer = Computer::new(Framed::new(&mut mirror));
+        let res = computer.eval(&mut prog);
+
+        // Runs out of points while waiting for the next x
+        assert_eq!(res, Err(crate::Error::Input(InputError::Unavailable)));
+        assert_eq!(computer.ip(), 0);
+        assert!(computer.io().partial().is_empty());
+
+        drop(computer);
+        assert_eq!(mirror.seen, vec![(-1, 2, -1), (-5, -6, 11)]);
+    }
+
+    /// Sends lines of text, echoing back every value
+    struct Lines(Vec<&'static str>, Vec<isize>);
+
+    impl Protocol for Lines {
+
+        type Frame = isize;
+        type Input = String;
+
+        fn frame(&mut self, frame: Self::Frame) {
+            self.1.push(frame);
+        }
+
+        fn input(&mut self) -> Result<Self::Input, InputError> {
+            match self.0.is_empty() {
+                true => Err(InputError::Unavailable),
+                false => Ok(self.0.remove(0).to_owned()),
+            }
+        }
+    }
+
+    #[test]
+    fn empty_input() {
+
+        // Echoes two values
+        let mut prog = vec![3,0,4,0,3,0,4,0,99];
+
+        let mut lines = Lines(vec!["", "a", "", "", "b"], Vec::new());
+        Computer::new(Framed::new(&mut lines))
+            .eval(&mut prog)
+            .unwrap();
+
+        assert_eq!(lines.1, vec![97, 98]);
+        assert!(lines.0.is_empty());
+    }
+}
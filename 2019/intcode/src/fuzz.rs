@@ -0,0 +1,636 @@
+//! Differential fuzzing of intcode interpreters
+//!
+//! Random programs are generated which are well-formed by construction: every
+//! address they touch is in bounds, every loop runs a bounded number of times,
+//! and arithmetic cannot overflow. Any difference in behavior between two
+//! interpreters running such a program is therefore a bug in one of them.
+//!
+//! Generated programs use a fixed memory layout:
+//!
+//! | Addresses     | Contents                                          |
+//! |---------------|---------------------------------------------------|
+//! | `0..512`      | Code, laid out sequentially and ending with `99`  |
+//! | `512..768`    | Constants (jump targets, relative base deltas)    |
+//! | `768..800`    | Loop counters and flags                           |
+//! | `800..832`    | Scratch data read and written by instructions     |
+
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::Error;
+use crate::isa;
+
+
+const CONST_BASE: usize = 512;
+const COUNTER_BASE: usize = 768;
+const DATA_BASE: usize = 800;
+const DATA_LEN: usize = 32;
+
+/// Size of memory given to generated programs
+pub const MEM_LEN: usize = DATA_BASE + DATA_LEN;
+
+/// Maximum number of add/mul instructions executed by a generated program
+const GROWTH_BUDGET: usize = 30;
+
+/// Maximum nesting depth of generated loops
+const MAX_DEPTH: usize = 2;
+
+
+/// Runs a program on some interpreter, given its memory and input
+pub type Interpreter<'a> = &'a dyn Fn(&mut [isize], &[isize]) -> Result<Vec<isize>, Error>;
+
+
+/// Instruction set features used by generated programs
+#[derive(Clone, Copy, Debug)]
+pub struct Features {
+
+    /// Use relative mode and opcode 9 (introduced on day 9)
+    pub relative: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            relative: true,
+        }
+    }
+}
+
+
+/// Small, deterministic PRNG (xorshift64*)
+struct Rng(u64);
+
+impl Rng {
+
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Random value in `lo..=hi`
+    fn range(&mut self, lo: isize, hi: isize) -> isize {
+        lo + (self.next() % (hi - lo + 1) as u64) as isize
+    }
+
+    fn chance(&mut self, n: u64) -> bool {
+        self.next().is_multiple_of(n)
+    }
+}
+
+
+/// Randomly generated program, along with the input it may consume
+#[derive(Clone, Debug)]
+pub struct Generated {
+
+    /// Initial memory, `MEM_LEN` cells long
+    pub mem: Vec<isize>,
+
+    /// Input values, at least as many as the program can read
+    pub input: Vec<isize>,
+
+    /// Number of cells occupied by code
+    pub code_len: usize,
+}
+
+
+/// State of the program being generated
+struct Gen {
+    rng: Rng,
+    features: Features,
+    mem: Vec<isize>,
+
+    /// Next free code address
+    ip: usize,
+
+    /// Next free constant address
+    next_const: usize,
+
+    /// Next free counter address
+    next_counter: usize,
+
+    /// Relative base at the current code address
+    rb: isize,
+
+    /// Remaining add/mul executions
+    growth: usize,
+
+    /// Upper bound on executions of the current code address
+    mult: usize,
+
+    /// Upper bound on input instructions executed
+    inputs: usize,
+}
+
+impl Gen {
+
+    fn room(&self) -> bool {
+
+        self.ip + 64 < CONST_BASE
+            && self.next_const + 4 < COUNTER_BASE
+            && self.next_counter + 2 < DATA_BASE
+    }
+
+    fn emit(&mut self, cells: &[isize]) {
+
+        self.mem[self.ip..self.ip + cells.len()].copy_from_slice(cells);
+        self.ip += cells.len();
+    }
+
+    fn constant(&mut self, val: isize) -> usize {
+
+        let addr = self.next_const;
+        self.mem[addr] = val;
+        self.next_const += 1;
+        addr
+    }
+
+    fn counter(&mut self) -> usize {
+
+        let addr = self.next_counter;
+        self.next_counter += 1;
+        addr
+    }
+
+    fn data_addr(&mut self) -> usize {
+
+        DATA_BASE + self.rng.range(0, DATA_LEN as isize - 1) as usize
+    }
+
+    fn modes(&self, read_only: bool) -> &'static [isize] {
+
+        match (self.features.relative, read_only) {
+            (true, true) => &[0, 1, 2],
+            (true, false) => &[0, 2],
+            (false, true) => &[0, 1],
+            (false, false) => &[0],
+        }
+    }
+
+    fn pick_mode(&mut self, read_only: bool) -> isize {
+
+        let modes = self.modes(read_only);
+        modes[self.rng.range(0, modes.len() as isize - 1) as usize]
+    }
+
+    /// Encodes a reference to `addr` in the given (non-immediate) mode
+    fn address(&self, mode: isize, addr: usize) -> isize {
+
+        match mode {
+            0 => addr as isize,
+            2 => addr as isize - self.rb,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Generates a parameter which reads `addr`, or an immediate value
+    fn read_param(&mut self, addr: usize, imm: isize) -> (isize, isize) {
+
+        match self.pick_mode(true) {
+            1 => (1, imm),
+            mode => (mode, self.address(mode, addr)),
+        }
+    }
+
+    /// Generates a parameter which reads a random data cell or immediate value
+    fn read_data(&mut self) -> (isize, isize) {
+
+        let addr = self.data_addr();
+        let imm = self.rng.range(-50, 50);
+        self.read_param(addr, imm)
+    }
+
+    /// Generates a non-immediate parameter which refers to `addr`
+    fn ref_param(&mut self, addr: usize) -> (isize, isize) {
+
+        let mode = self.pick_mode(false);
+        (mode, self.address(mode, addr))
+    }
+
+    fn arith(&mut self) {
+
+        let opcode = if self.growth >= self.mult && !self.rng.chance(3) {
+            self.growth -= self.mult;
+            [1, 2][self.rng.range(0, 1) as usize]
+        } else {
+            [7, 8][self.rng.range(0, 1) as usize]
+        };
+
+        let (m0, p0) = self.read_data();
+        let (m1, p1) = if opcode == 2 {
+            // Keep products small by always scaling by a small immediate
+            (1, self.rng.range(-3, 3))
+        } else {
+            self.read_data()
+        };
+        let dest = self.data_addr();
+        let (m2, p2) = self.ref_param(dest);
+
+        self.emit(&[isa::encode(opcode, &[m0, m1, m2]), p0, p1, p2]);
+    }
+
+    fn input(&mut self) {
+
+        let dest = self.data_addr();
+        let (m, p) = self.ref_param(dest);
+
+        self.emit(&[isa::encode(3, &[m]), p]);
+        self.inputs += self.mult;
+    }
+
+    fn output(&mut self) {
+
+        let (m, p) = self.read_data();
+
+        self.emit(&[isa::encode(4, &[m]), p]);
+    }
+
+    fn adj_rb(&mut self) {
+
+        let delta = self.rng.range(-8, 8);
+        let addr = self.constant(delta);
+        let (m, p) = self.read_param(addr, delta);
+
+        self.emit(&[isa::encode(9, &[m]), p]);
+        self.rb += delta;
+    }
+
+    /// Generates a parameter which yields the jump target stored at `addr`
+    fn target_param(&mut self, addr: usize) -> (isize, isize) {
+
+        let target = self.mem[addr];
+        self.read_param(addr, target)
+    }
+
+    /// Emits straight-line code, without changing the relative base
+    fn block(&mut self, depth: usize, len: usize) {
+
+        for _ in 0..len {
+
+            if !self.room() {
+                break;
+            }
+
+            match self.rng.range(0, 9) {
+                0..=4 => self.arith(),
+                5 => self.input(),
+                6 | 7 => self.output(),
+                8 if depth < MAX_DEPTH => self.repeat(depth + 1),
+                _ => self.skip(depth),
+            }
+        }
+    }
+
+    /// Emits a loop which runs its body a fixed number of times
+    fn repeat(&mut self, depth: usize) {
+
+        let iters = self.rng.range(1, 4);
+        let counter = self.counter();
+        let (mc, pc) = self.ref_param(counter);
+        self.emit(&[isa::encode(1, &[1, 1, mc]), 0, iters, pc]);
+
+        let body = self.ip;
+        let outer = self.mult;
+        self.mult *= iters as usize;
+        let len = self.rng.range(1, 4) as usize;
+        self.block(depth, len);
+        self.mult = outer;
+
+        let (md, pd) = self.ref_param(counter);
+        let (mw, pw) = self.ref_param(counter);
+        self.emit(&[isa::encode(1, &[md, 1, mw]), pd, -1, pw]);
+
+        let target = self.constant(body as isize);
+        let (mt, pt) = self.target_param(target);
+
+        if self.rng.chance(2) {
+
+            let (mc, pc) = self.ref_param(counter);
+            self.emit(&[isa::encode(5, &[mc, mt]), pc, pt]);
+
+        } else {
+
+            // Jump back while the counter is not yet zero
+            let flag = self.counter();
+            let (mc, pc) = self.ref_param(counter);
+            let (mf, pf) = self.ref_param(flag);
+            self.emit(&[isa::encode(8, &[mc, 1, mf]), pc, 0, pf]);
+            let (mr, pr) = self.ref_param(flag);
+            self.emit(&[isa::encode(6, &[mr, mt]), pr, pt]);
+        }
+    }
+
+    /// Emits a data-dependent forward jump over a block of code
+    fn skip(&mut self, depth: usize) {
+
+        let opcode = [5, 6][self.rng.range(0, 1) as usize];
+        let (mc, pc) = self.read_data();
+        let target = self.constant(0);
+        let (mt, pt) = self.target_param(target);
+
+        let jump = self.ip;
+        self.emit(&[isa::encode(opcode, &[mc, mt]), pc, pt]);
+
+        let len = self.rng.range(1, 3) as usize;
+        self.block(depth, len);
+
+        // Patch in the real target now that it is known
+        self.mem[target] = self.ip as isize;
+        if mt == 1 {
+            self.mem[jump + 2] = self.ip as isize;
+        }
+    }
+
+    fn program(&mut self) {
+
+        if self.features.relative {
+            let rb = self.rng.range(0, MEM_LEN as isize);
+            self.emit(&[109, rb]);
+            self.rb = rb;
+        }
+
+        let items = self.rng.range(8, 24);
+        for _ in 0..items {
+
+            if !self.room() {
+                break;
+            }
+
+            if self.features.relative && self.rng.chance(6) {
+                self.adj_rb();
+            } else {
+                self.block(0, 1);
+            }
+        }
+
+        self.emit(&[99]);
+    }
+}
+
+
+/// Generates random, well-formed intcode programs
+pub struct Generator {
+    features: Features,
+}
+
+impl Generator {
+
+    pub fn new(features: Features) -> Self {
+        Self {
+            features,
+        }
+    }
+
+    /// Generates the program for the given seed
+    ///
+    /// The same seed and features always produce the same program.
+    pub fn generate(&self, seed: u64) -> Generated {
+
+        let mut gen = Gen {
+            rng: Rng::new(seed),
+            features: self.features,
+            mem: vec![0; MEM_LEN],
+            ip: 0,
+            next_const: CONST_BASE,
+            next_counter: COUNTER_BASE,
+            rb: 0,
+            growth: GROWTH_BUDGET,
+            mult: 1,
+            inputs: 0,
+        };
+
+        for addr in DATA_BASE..MEM_LEN {
+            gen.mem[addr] = gen.rng.range(-100, 100);
+        }
+
+        gen.program();
+
+        let input = (0..gen.inputs)
+            .map(|_| gen.rng.range(-100, 100))
+            .collect();
+
+        Generated {
+            mem: gen.mem,
+            input,
+            code_len: gen.ip,
+        }
+    }
+}
+
+
+/// Observable behavior of an interpreter running a program
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Behavior {
+
+    /// Program halted normally
+    Halted {
+        mem: Vec<isize>,
+        output: Vec<isize>,
+    },
+
+    /// Interpreter returned an error
+    Error(Error),
+
+    /// Interpreter panicked
+    Panic,
+}
+
+
+/// Program on which interpreters disagreed
+#[derive(Debug)]
+pub struct Discrepancy {
+
+    /// Seed which reproduces the program
+    pub seed: u64,
+
+    /// The program and its input
+    pub prog: Generated,
+
+    /// Behavior of each interpreter, by name
+    pub behaviors: Vec<(String, Behavior)>,
+}
+
+
+/// Runs a program on an interpreter, capturing panics
+pub fn observe(interp: Interpreter, prog: &Generated) -> Behavior {
+
+    let mut mem = prog.mem.clone();
+
+    let res = panic::catch_unwind(AssertUnwindSafe(|| interp(&mut mem, &prog.input)));
+
+    match res {
+        Ok(Ok(output)) => Behavior::Halted { mem, output },
+        Ok(Err(err)) => Behavior::Error(err),
+        Err(_) => Behavior::Panic,
+    }
+}
+
+
+/// Runs `count` generated programs on every interpreter, starting at `seed`
+///
+/// Returns every program on which the interpreters did not all behave
+/// identically.
+pub fn differential(
+    interps: &[(&str, Interpreter)],
+    features: Features,
+    seed: u64,
+    count: u64,
+) -> Vec<Discrepancy> {
+
+    let gen = Generator::new(features);
+    let mut discrepancies = Vec::new();
+
+    for seed in seed..seed + count {
+
+        let prog = gen.generate(seed);
+
+        let behaviors: Vec<_> = interps.iter()
+            .map(|(name, interp)| (name.to_string(), observe(*interp, &prog)))
+            .collect();
+
+        if behaviors.windows(2).any(|w| w[0].1 != w[1].1) {
+            discrepancies.push(Discrepancy { seed, prog, behaviors });
+        }
+    }
+
+    discrepancies
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::conformance::run_computer;
+
+    /// Decodes the (opcode, modes) of each generated instruction
+    fn instructions(prog: &Generated) -> Vec<(isize, Vec<isize>)> {
+
+        let mut res = Vec::new();
+        let mut ip = 0;
+
+        while ip < prog.code_len {
+            let op = prog.mem[ip];
+            let n = match op % 100 {
+                1 | 2 | 7 | 8 => 3,
+                5 | 6 => 2,
+                3 | 4 | 9 => 1,
+                99 => 0,
+                _ => panic!("unexpected opcode {}", op),
+            };
+            let modes = (0..n)
+                .map(|i| (op / 10isize.pow(2 + i)) % 10)
+                .collect();
+            res.push((op % 100, modes));
+            ip += 1 + n as usize;
+        }
+
+        res
+    }
+
+    #[test]
+    fn generated_programs_halt() {
+
+        let gen = Generator::new(Features::default());
+
+        for seed in 0..500 {
+            let prog = gen.generate(seed);
+            let behavior = observe(&run_computer, &prog);
+            assert!(
+                matches!(behavior, Behavior::Halted { .. }),
+                "seed {} did not halt: {:?}",
+                seed,
+                behavior,
+            );
+        }
+    }
+
+    #[test]
+    fn covers_all_modes() {
+
+        let gen = Generator::new(Features::default());
+        let mut seen = std::collections::HashSet::new();
+
+        for seed in 0..200 {
+            for (op, modes) in instructions(&gen.generate(seed)) {
+                for (i, mode) in modes.into_iter().enumerate() {
+                    seen.insert((op, i, mode));
+                }
+            }
+        }
+
+        for op in &[1, 7, 8] {
+            for mode in &[0, 1, 2] {
+                assert!(seen.contains(&(*op, 0, *mode)));
+                assert!(seen.contains(&(*op, 1, *mode)));
+            }
+            assert!(seen.contains(&(*op, 2, 0)));
+            assert!(seen.contains(&(*op, 2, 2)));
+        }
+        for op in &[4, 5, 6, 9] {
+            for mode in &[0, 1, 2] {
+                assert!(seen.contains(&(*op, 0, *mode)));
+            }
+        }
+        for op in &[5, 6] {
+            for mode in &[0, 1, 2] {
+                assert!(seen.contains(&(*op, 1, *mode)));
+            }
+        }
+        assert!(seen.contains(&(3, 0, 2)));
+    }
+
+    #[test]
+    fn day5_features() {
+
+        let gen = Generator::new(Features { relative: false });
+
+        for seed in 0..200 {
+            for (op, modes) in instructions(&gen.generate(seed)) {
+                assert_ne!(op, 9);
+                assert!(!modes.contains(&2));
+            }
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+
+        let gen = Generator::new(Features::default());
+
+        assert_eq!(gen.generate(42).mem, gen.generate(42).mem);
+        assert_ne!(gen.generate(42).mem, gen.generate(43).mem);
+    }
+
+    #[test]
+    fn detects_divergence() {
+
+        // Interpreter which loses its last output
+        let broken = |mem: &mut [isize], input: &[isize]| {
+            let mut output = run_computer(mem, input)?;
+            output.pop();
+            Ok(output)
+        };
+
+        let found = differential(
+            &[("computer", &run_computer), ("broken", &broken)],
+            Features::default(),
+            0,
+            50,
+        );
+
+        assert!(!found.is_empty());
+
+        let same = differential(
+            &[("a", &run_computer), ("b", &run_computer)],
+            Features::default(),
+            0,
+            50,
+        );
+
+        assert!(same.is_empty());
+    }
+}
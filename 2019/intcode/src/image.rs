@@ -0,0 +1,439 @@
+//! Compact binary images of intcode programs
+//!
+//! An image holds the same cells as a program's text, but is much smaller and
+//! quicker to load. It is laid out as:
+//!
+//! ```text
+//! magic     4 bytes, "\0INT"
+//! version   1 byte
+//! length    varint, number of cells
+//! cells     zigzag varint per cell
+//! sections  any number of: tag varint, size varint, payload
+//! ```
+//!
+//! Varints are unsigned LEB128. Sections carry optional metadata; readers skip
+//! any section whose tag they don't recognize. Text programs can never start
+//! with a NUL byte, so images and text are told apart by the magic number.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str;
+#[cfg(feature = "std")]
+use std::fs::{self, File};
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[cfg(feature = "std")]
+use crate::program::LoadError;
+use crate::program::Program;
+
+
+/// Bytes every image starts with
+pub const MAGIC: [u8; 4] = *b"\0INT";
+
+/// Version of the format written by `Image::to_bytes`
+pub const VERSION: u8 = 1;
+
+/// Section holding the name of the program's source
+const TAG_NAME: u64 = 1;
+
+/// Section holding one entry point: its address, then its name
+const TAG_ENTRY: u64 = 2;
+
+
+/// Error encountered while decoding an image
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImageError {
+
+    /// Data does not start with the magic number
+    Magic,
+
+    /// Image was written in an unsupported version of the format
+    Version(u8),
+
+    /// Data ended in the middle of a value or section
+    Truncated,
+
+    /// Value is too large for the type it is decoded into
+    Overflow,
+
+    /// Text in a section is not valid UTF-8
+    Utf8,
+}
+
+impl fmt::Display for ImageError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            Self::Magic => write!(f, "not a program image"),
+            Self::Version(v) => write!(f, "unsupported image version {}", v),
+            Self::Truncated => write!(f, "image is truncated"),
+            Self::Overflow => write!(f, "value in image is out of range"),
+            Self::Utf8 => write!(f, "text in image is not valid UTF-8"),
+        }
+    }
+}
+
+
+/// Known entry point of a program
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+
+    /// Address execution can start from
+    pub addr: usize,
+
+    /// What the entry point does
+    pub name: String,
+}
+
+
+/// Program cells along with optional metadata
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Image {
+
+    /// Cells of the program, without any padding
+    pub cells: Vec<isize>,
+
+    /// Name of the program's source (e.g. its file name)
+    pub name: Option<String>,
+
+    /// Known entry points, in the order they were added
+    pub entries: Vec<Entry>,
+}
+
+impl Image {
+
+    /// Creates an image of `prog` with no metadata
+    pub fn new(prog: &Program) -> Self {
+        Self {
+            cells: prog.cells().to_vec(),
+            ..Self::default()
+        }
+    }
+
+    /// Decodes an image
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ImageError> {
+
+        if !is_image(data) {
+            return Err(ImageError::Magic);
+        }
+
+        let mut r = Reader { data: &data[MAGIC.len()..] };
+
+        match r.byte()? {
+            VERSION => { },
+            v => return Err(ImageError::Version(v)),
+        }
+
+        let len = r.usize()?;
+
+        // Every cell takes at least a byte, so don't trust `len` any further
+        if len > r.data.len() {
+            return Err(ImageError::Truncated);
+        }
+
+        let mut image = Self::default();
+        image.cells.reserve(len);
+        for _ in 0..len {
+            let cell = unzigzag(r.varint()?);
+            let cell = isize::try_from(cell)
+                .map_err(|_| ImageError::Overflow)?;
+            image.cells.push(cell);
+        }
+
+        while !r.data.is_empty() {
+
+            let tag = r.varint()?;
+            let size = r.usize()?;
+            let mut section = Reader { data: r.take(size)? };
+
+            match tag {
+                TAG_NAME => image.name = Some(section.text()?),
+                TAG_ENTRY => {
+                    let addr = section.usize()?;
+                    let name = section.text()?;
+                    image.entries.push(Entry { addr, name });
+                },
+                _ => { },
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Encodes the image
+    pub fn to_bytes(&self) -> Vec<u8> {
+
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION);
+
+        put_varint(&mut data, self.cells.len() as u64);
+        for &cell in &self.cells {
+            put_varint(&mut data, zigzag(cell as i64));
+        }
+
+        if let Some(name) = &self.name {
+            put_section(&mut data, TAG_NAME, name.as_bytes());
+        }
+
+        for entry in &self.entries {
+            let mut payload = Vec::new();
+            put_varint(&mut payload, entry.addr as u64);
+            payload.extend_from_slice(entry.name.as_bytes());
+            put_section(&mut data, TAG_ENTRY, &payload);
+        }
+
+        data
+    }
+
+    /// Loads an image from the file at `path`
+    ///
+    /// Text programs are accepted too, and produce an image with no metadata.
+    #[cfg(feature = "std")]
+    pub fn load<P>(path: P) -> Result<Self, LoadError>
+    where P: AsRef<Path>
+    {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Loads an image, or a text program, from any reader
+    #[cfg(feature = "std")]
+    pub fn from_reader<R>(mut reader: R) -> Result<Self, LoadError>
+    where R: Read
+    {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        if is_image(&data) {
+            return Ok(Self::from_bytes(&data)?);
+        }
+
+        let src = String::from_utf8(data)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let prog: Program = src.parse()?;
+
+        Ok(Self::new(&prog))
+    }
+
+    /// Writes the image to the file at `path`
+    #[cfg(feature = "std")]
+    pub fn save<P>(&self, path: P) -> io::Result<()>
+    where P: AsRef<Path>
+    {
+        fs::write(path, self.to_bytes())
+    }
+}
+
+impl From<Image> for Program {
+    fn from(image: Image) -> Self {
+        Self::from(image.cells)
+    }
+}
+
+
+/// Whether `data` starts like an image rather than a text program
+pub fn is_image(data: &[u8]) -> bool {
+
+    data.starts_with(&MAGIC)
+}
+
+
+/// Cursor over the bytes of an image
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ImageError> {
+
+        if n > self.data.len() {
+            return Err(ImageError::Truncated);
+        }
+
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8, ImageError> {
+
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, ImageError> {
+
+        let mut val = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7f);
+
+            // Bits shifted past the top would be silently lost
+            if bits << shift >> shift != bits {
+                return Err(ImageError::Overflow);
+            }
+            val |= bits << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+
+        Err(ImageError::Overflow)
+    }
+
+    fn usize(&mut self) -> Result<usize, ImageError> {
+
+        usize::try_from(self.varint()?)
+            .map_err(|_| ImageError::Overflow)
+    }
+
+    /// Takes the rest of the data as text
+    fn text(&mut self) -> Result<String, ImageError> {
+
+        let text = str::from_utf8(self.data)
+            .map_err(|_| ImageError::Utf8)?;
+        self.data = &[];
+
+        Ok(text.into())
+    }
+}
+
+
+fn put_varint(data: &mut Vec<u8>, mut val: u64) {
+
+    while val >= 0x80 {
+        data.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    data.push(val as u8);
+}
+
+fn put_section(data: &mut Vec<u8>, tag: u64, payload: &[u8]) {
+
+    put_varint(data, tag);
+    put_varint(data, payload.len() as u64);
+    data.extend_from_slice(payload);
+}
+
+/// Maps signed values to unsigned ones so small magnitudes stay small
+fn zigzag(val: i64) -> u64 {
+
+    ((val << 1) ^ (val >> 63)) as u64
+}
+
+fn unzigzag(val: u64) -> i64 {
+
+    (val >> 1) as i64 ^ -((val & 1) as i64)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GRAV_PROG: &str = include_str!("grav-prog.txt");
+
+    #[test]
+    fn round_trip() {
+
+        let prog: Program = GRAV_PROG.parse()
+            .unwrap();
+        let data = Image::new(&prog).to_bytes();
+
+        // Most cells of a real program fit in a byte or two
+        assert!(data.len() * 3 < GRAV_PROG.len() * 2);
+
+        let image = Image::from_bytes(&data)
+            .unwrap();
+        assert_eq!(image.name, None);
+        assert!(image.entries.is_empty());
+
+        let loaded = Program::from(image);
+        assert_eq!(loaded, prog);
+        assert_eq!(loaded.to_string(), prog.to_string());
+    }
+
+    #[test]
+    fn extremes() {
+
+        for &val in &[0, 1, -1, 63, -64, 64, isize::MAX, isize::MIN] {
+            let image = Image { cells: vec![val], ..Image::default() };
+            let data = image.to_bytes();
+            assert_eq!(Image::from_bytes(&data), Ok(image));
+        }
+
+        assert_eq!(Image { cells: vec![-64], ..Image::default() }.to_bytes()[5..], [1, 127]);
+    }
+
+    #[test]
+    fn metadata() {
+
+        let image = Image {
+            cells: vec![1101, 2, 3, 0, 99],
+            name: Some("add.txt".into()),
+            entries: vec![
+                Entry { addr: 0, name: "main".into() },
+                Entry { addr: 4, name: "".into() },
+            ],
+        };
+
+        let mut data = image.to_bytes();
+        assert_eq!(Image::from_bytes(&data), Ok(image.clone()));
+
+        // Sections from newer writers are skipped
+        data.extend(&[9, 3, 1, 2, 3]);
+        assert_eq!(Image::from_bytes(&data), Ok(image));
+    }
+
+    #[test]
+    fn errors() {
+
+        let image = Image {
+            cells: vec![300, -300, 7],
+            name: Some("prog".into()),
+            entries: Vec::new(),
+        };
+        let data = image.to_bytes();
+
+        // Stopping just after the cells leaves a valid image without a name,
+        // but every other proper prefix is truncated somewhere
+        let cells_end = 11;
+        for len in MAGIC.len() + 1..data.len() {
+            let res = Image::from_bytes(&data[..len]);
+            match len {
+                len if len == cells_end => assert_eq!(res.unwrap().cells, image.cells),
+                _ => assert_eq!(res, Err(ImageError::Truncated), "{}", len),
+            }
+        }
+
+        assert_eq!(Image::from_bytes(b"1,2,3"), Err(ImageError::Magic));
+        assert_eq!(Image::from_bytes(b"\0INT\x02\x00"), Err(ImageError::Version(2)));
+
+        let huge = [0, b'I', b'N', b'T', 1, 1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        assert_eq!(Image::from_bytes(&huge), Err(ImageError::Overflow));
+
+        let bad_name = [0, b'I', b'N', b'T', 1, 0, 1, 1, 0xff];
+        assert_eq!(Image::from_bytes(&bad_name), Err(ImageError::Utf8));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_reader() {
+
+        let text = Image::from_reader("104,7,99\n".as_bytes())
+            .unwrap();
+        assert_eq!(text.cells, [104, 7, 99]);
+
+        let image = Image { name: Some("out".into()), ..text };
+        let data = image.to_bytes();
+        assert_eq!(Image::from_reader(&data[..]).unwrap(), image);
+    }
+}
@@ -0,0 +1,616 @@
+//! Full-screen terminal view of a running intcode machine
+//!
+//! An `Inspector` wraps any `IoHandler`, so solutions can be watched as they
+//! run by handing their handler to `Inspector::new` and calling `interact`.
+//! The screen shows the disassembly around `ip`, the registers, memory in hex
+//! with recent writes highlighted, the I/O history, and any patches applied
+//! with `Inspector::apply`.
+//!
+//! Keys are read a line at a time, so each must be followed by enter. An empty
+//! line repeats the previous key.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt::{self, Write as _};
+use std::io::{self, BufRead, Write};
+
+use crate::{Computer, Error, InputError, IoHandler};
+use crate::isa;
+use crate::patch::{Patch, PatchError};
+use crate::program::MEM_SIZE;
+
+
+/// Summary of available keys
+pub const KEYS: &str = "\
+s [n] step  c continue  b <addr> breakpoint  e <addr> <val> edit  \
+m [addr] memory  q quit";
+
+
+/// Most instructions a single continue executes before pausing
+const CONTINUE_LIMIT: usize = 10_000_000;
+
+/// Number of I/O events kept for display
+const HISTORY_LEN: usize = 8;
+
+/// Number of written addresses highlighted in the memory view
+const RECENT_WRITES: usize = 16;
+
+/// Number of executed instructions shown before `ip`
+const TRAIL_LEN: usize = 4;
+
+/// Number of instructions shown from `ip` onwards
+const AHEAD_LEN: usize = 8;
+
+/// Number of cells per row of the memory view
+const ROW_LEN: usize = 8;
+
+/// Number of rows in the memory view
+const ROWS: usize = 8;
+
+
+/// Single value passed between a program and its I/O handler
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Event {
+    Input(isize),
+    Output(isize),
+}
+
+
+/// I/O handler which records traffic on its way to another handler
+pub struct Watch<H> {
+
+    /// Handler doing the actual I/O
+    inner: H,
+
+    /// Most recent events, oldest first
+    history: VecDeque<Event>,
+}
+
+impl<H> Watch<H> {
+
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Most recent events, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &Event> {
+
+        self.history.iter()
+    }
+
+    pub fn inner(&self) -> &H {
+
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> H {
+
+        self.inner
+    }
+
+    fn record(&mut self, event: Event) {
+
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(event);
+    }
+}
+
+impl<H> IoHandler for Watch<H>
+where H: IoHandler
+{
+
+    fn input(&mut self) -> Result<isize, InputError> {
+
+        let val = self.inner.input()?;
+        self.record(Event::Input(val));
+
+        Ok(val)
+    }
+
+    fn output(&mut self, val: isize) {
+
+        self.record(Event::Output(val));
+        self.inner.output(val);
+    }
+}
+
+
+/// Reason a key could not be handled
+#[derive(Debug, Eq, PartialEq)]
+pub enum InspectError {
+
+    /// Line is not a valid key
+    Syntax(String),
+
+    /// Machine failed while executing
+    Machine(Error),
+}
+
+impl From<Error> for InspectError {
+    fn from(err: Error) -> Self {
+        Self::Machine(err)
+    }
+}
+
+impl fmt::Display for InspectError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            Self::Syntax(line) => write!(f, "cannot parse {:?} ({})", line, KEYS),
+            Self::Machine(err) => write!(f, "machine error: {:?}", err),
+        }
+    }
+}
+
+
+/// Machine being inspected, along with everything needed to draw it
+pub struct Inspector<H> {
+
+    /// Main memory
+    mem: Vec<isize>,
+
+    /// Computer holding the registers between keys
+    computer: Computer<Watch<H>>,
+
+    /// Addresses where continuing stops
+    breakpoints: BTreeSet<usize>,
+
+    /// Most recently written addresses, oldest first
+    writes: VecDeque<usize>,
+
+    /// Most recently executed instructions, oldest first
+    trail: VecDeque<usize>,
+
+    /// Patches applied to memory, oldest first
+    patches: Vec<Patch>,
+
+    /// First address of the memory view, or `None` to follow writes
+    view: Option<usize>,
+
+    /// Message shown below the screen
+    status: String,
+
+    /// Previous non-empty line, repeated by an empty one
+    last: String,
+}
+
+impl<H> Inspector<H>
+where H: IoHandler
+{
+
+    /// Prepares to run `prog` from the beginning, padding memory as usual
+    pub fn new(prog: &[isize], io: H) -> Self {
+
+        let mut mem = prog.to_vec();
+        mem.resize(mem.len().max(MEM_SIZE), 0);
+
+        Self {
+            mem,
+            computer: Computer::new(Watch::new(io)),
+            breakpoints: BTreeSet::new(),
+            writes: VecDeque::new(),
+            trail: VecDeque::new(),
+            patches: Vec::new(),
+            view: None,
+            status: String::new(),
+            last: String::new(),
+        }
+    }
+
+    /// Main memory
+    pub fn mem(&self) -> &[isize] {
+
+        &self.mem
+    }
+
+    pub fn computer(&self) -> &Computer<Watch<H>> {
+
+        &self.computer
+    }
+
+    /// Gives back the wrapped handler
+    pub fn into_inner(self) -> H {
+
+        self.computer.into_io()
+            .into_inner()
+    }
+
+    /// Adds a breakpoint, or removes it if already set
+    pub fn toggle_breakpoint(&mut self, addr: usize) {
+
+        if !self.breakpoints.remove(&addr) {
+            self.breakpoints.insert(addr);
+        }
+    }
+
+    /// Writes a single memory cell
+    pub fn edit(&mut self, addr: usize, val: isize) -> Result<(), Error> {
+
+        *self.mem.get_mut(addr)
+            .ok_or(Error::Address)? = val;
+        self.wrote(addr);
+
+        Ok(())
+    }
+
+    /// Applies a patch to memory, highlighting the cells it changes
+    pub fn apply(&mut self, patch: &Patch) -> Result<(), PatchError> {
+
+        patch.apply(&mut self.mem)?;
+
+        for edit in patch.edits() {
+            self.wrote(edit.addr);
+        }
+        self.patches.push(patch.clone());
+        self.status = format!("applied patch {}", patch.name());
+
+        Ok(())
+    }
+
+    /// Executes a single instruction
+    ///
+    /// Returns whether the program is still running.
+    pub fn step(&mut self) -> Result<bool, Error> {
+
+        let ip = self.computer.ip();
+        let targets = self.targets();
+
+        let running = self.computer.attach(&mut self.mem)
+            .step()?;
+
+        if self.trail.len() == TRAIL_LEN {
+            self.trail.pop_front();
+        }
+        self.trail.push_back(ip);
+
+        for addr in targets {
+            self.wrote(addr);
+        }
+
+        Ok(running)
+    }
+
+    /// Executes instructions until the program halts or reaches a breakpoint
+    ///
+    /// At least one instruction is executed, so continuing from a breakpoint
+    /// moves past it. Returns the number of instructions executed.
+    pub fn cont(&mut self) -> Result<usize, Error> {
+
+        for steps in 1..=CONTINUE_LIMIT {
+            if !self.step()? || self.breakpoints.contains(&self.computer.ip()) {
+                return Ok(steps);
+            }
+        }
+
+        Ok(CONTINUE_LIMIT)
+    }
+
+    /// Handles a single line of keys
+    ///
+    /// Returns `false` once the user asks to quit.
+    pub fn exec(&mut self, line: &str) -> Result<bool, InspectError> {
+
+        let line = match line.trim() {
+            "" => self.last.clone(),
+            line => line.to_owned(),
+        };
+        self.last = line.clone();
+
+        let syntax = || InspectError::Syntax(line.clone());
+
+        let mut words = line.split_whitespace();
+        let key = words.next()
+            .unwrap_or("s");
+        let args: Vec<isize> = words.map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| syntax())?;
+        let addr = |val: isize| usize::try_from(val)
+            .map_err(|_| InspectError::Machine(Error::Address));
+
+        self.status = match (key, &args[..]) {
+            ("q", []) => return Ok(false),
+            ("s", []) | ("s", [_]) => {
+                let count = args.first().cloned().unwrap_or(1);
+                for _ in 0..count {
+                    if !self.step()? {
+                        break;
+                    }
+                }
+                String::new()
+            },
+            ("c", []) => {
+                let steps = self.cont()?;
+                format!("executed {} instructions", steps)
+            },
+            ("b", [val]) => {
+                let at = addr(*val)?;
+                self.toggle_breakpoint(at);
+                match self.breakpoints.contains(&at) {
+                    true => format!("breakpoint set at {}", at),
+                    false => format!("breakpoint cleared at {}", at),
+                }
+            },
+            ("e", [at, val]) => {
+                self.edit(addr(*at)?, *val)?;
+                String::new()
+            },
+            ("m", []) => {
+                self.view = None;
+                String::new()
+            },
+            ("m", [val]) => {
+                self.view = Some(addr(*val)?);
+                String::new()
+            },
+            _ => return Err(syntax()),
+        };
+
+        Ok(true)
+    }
+
+    /// Draws the current state as text
+    ///
+    /// Recent writes are highlighted using ANSI escapes; nothing else depends
+    /// on the terminal.
+    pub fn render(&self) -> String {
+
+        let ip = self.computer.ip();
+        let mut text = String::new();
+
+        let state = if self.computer.is_halted() { "halted" } else { "running" };
+        writeln!(text, "ip {:<6} rb {:<6} {}", ip, self.computer.rb(), state).unwrap();
+
+        text.push_str("\n-- code\n");
+        for &addr in self.trail.iter().filter(|&&a| a != ip) {
+            self.render_instr(&mut text, addr, ' ');
+        }
+        let mut addr = ip;
+        for i in 0..AHEAD_LEN {
+            addr += self.render_instr(&mut text, addr, if i == 0 { '>' } else { ' ' });
+        }
+
+        let start = self.view
+            .or_else(|| self.writes.back().map(|&a| a.saturating_sub(ROWS / 2 * ROW_LEN)))
+            .unwrap_or(0);
+        let start = start / ROW_LEN * ROW_LEN;
+        let end = (start + ROWS * ROW_LEN).min(self.mem.len());
+
+        text.push_str("\n-- memory\n");
+        for row in (start..end).step_by(ROW_LEN) {
+            write!(text, "{:>6}", row).unwrap();
+            for addr in row..(row + ROW_LEN).min(end) {
+                let cell = format!("{:>9}", hex(self.mem[addr]));
+                if self.writes.contains(&addr) {
+                    write!(text, "\x1b[7m{}\x1b[0m", cell).unwrap();
+                } else {
+                    text.push_str(&cell);
+                }
+            }
+            text.push('\n');
+        }
+
+        text.push_str("\n-- io\n");
+        for event in self.computer.io().history() {
+            match event {
+                Event::Input(val) => writeln!(text, "  in  {}", val).unwrap(),
+                Event::Output(val) => writeln!(text, "  out {}", val).unwrap(),
+            }
+        }
+
+        if !self.patches.is_empty() {
+            text.push_str("\n-- patches\n");
+        }
+        for patch in &self.patches {
+            writeln!(text, "  {}", patch.summary()).unwrap();
+        }
+
+        writeln!(text, "\n{}\n{}", self.status, KEYS).unwrap();
+
+        text
+    }
+
+    /// Draws the screen and handles keys from standard input until the user
+    /// quits or input ends
+    pub fn interact(&mut self) -> io::Result<()> {
+
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+
+        loop {
+            // Clear the screen and move to the top left corner
+            print!("\x1b[2J\x1b[H{}> ", self.render());
+            io::stdout().flush()?;
+
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+
+            match self.exec(&line) {
+                Ok(true) => { },
+                Ok(false) => return Ok(()),
+                Err(err) => self.status = err.to_string(),
+            }
+        }
+    }
+
+    /// Addresses which the instruction at `ip` is about to write
+    fn targets(&self) -> Vec<usize> {
+
+        isa::write_targets(&self.mem, self.computer.ip(), self.computer.rb())
+    }
+
+    fn wrote(&mut self, addr: usize) {
+
+        self.writes.retain(|&a| a != addr);
+        if self.writes.len() == RECENT_WRITES {
+            self.writes.pop_front();
+        }
+        self.writes.push_back(addr);
+    }
+
+    /// Appends one line of disassembly, returning the instruction's size
+    fn render_instr(&self, text: &mut String, addr: usize, marker: char) -> usize {
+
+        let (line, size) = isa::disassemble(&self.mem, addr)
+            .unwrap_or_else(|| {
+                let val = self.mem.get(addr).cloned().unwrap_or(0);
+                (format!("{} ?", val), 1)
+            });
+        let brk = if self.breakpoints.contains(&addr) { '*' } else { ' ' };
+
+        writeln!(text, "{}{}{:>5}  {}", marker, brk, addr, line).unwrap();
+
+        size
+    }
+}
+
+
+/// Formats a value in hex, keeping its sign
+fn hex(val: isize) -> String {
+
+    if val < 0 {
+        format!("-{:x}", val.unsigned_abs())
+    } else {
+        format!("{:x}", val)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Feeds inputs from a list, collecting outputs
+    struct ListIo {
+        input: Vec<isize>,
+        output: Vec<isize>,
+    }
+
+    impl IoHandler for ListIo {
+
+        fn input(&mut self) -> Result<isize, InputError> {
+            match self.input.is_empty() {
+                true => Err(InputError::Unavailable),
+                false => Ok(self.input.remove(0)),
+            }
+        }
+
+        fn output(&mut self, val: isize) {
+            self.output.push(val);
+        }
+    }
+
+    fn inspector(prog: &[isize], input: &[isize]) -> Inspector<ListIo> {
+
+        Inspector::new(prog, ListIo { input: input.to_vec(), output: Vec::new() })
+    }
+
+    #[test]
+    fn step_and_history() {
+
+        // Doubles its input, stores it at 20 and outputs it
+        let prog = [3,20,1002,20,2,20,4,20,99];
+        let mut insp = inspector(&prog, &[21]);
+
+        assert!(insp.exec("s 2").unwrap());
+        assert_eq!(insp.mem()[20], 42);
+        assert_eq!(insp.computer().ip(), 6);
+
+        let screen = insp.render();
+        assert!(screen.contains(">     6  out [20]"));
+        assert!(screen.contains("\x1b[7m       2a\x1b[0m"));
+        assert!(screen.contains("  in  21"));
+
+        // An empty line repeats the previous step
+        assert!(insp.exec("").unwrap());
+        assert!(insp.exec("").unwrap());
+        assert!(insp.computer().is_halted());
+        assert!(insp.render().contains("  out 42"));
+        assert_eq!(insp.into_inner().output, vec![42]);
+    }
+
+    #[test]
+    fn breakpoints() {
+
+        // Counts down from 3, outputting each value
+        let prog = [4,12,1001,12,-1,12,1005,12,0,99,0,0,3];
+        let mut insp = inspector(&prog, &[]);
+
+        insp.exec("b 6").unwrap();
+        assert!(insp.render().contains(" *    6  jt [12], 0"));
+
+        insp.exec("c").unwrap();
+        assert_eq!(insp.computer().ip(), 6);
+        assert_eq!(insp.mem()[12], 2);
+
+        insp.exec("c").unwrap();
+        assert_eq!(insp.computer().ip(), 6);
+        assert_eq!(insp.mem()[12], 1);
+
+        insp.exec("b 6").unwrap();
+        insp.exec("c").unwrap();
+        assert!(insp.computer().is_halted());
+        assert_eq!(insp.into_inner().output, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn edit() {
+
+        let prog = [1101,2,3,7,4,7,99,0];
+        let mut insp = inspector(&prog, &[]);
+
+        insp.exec("e 2 40").unwrap();
+        insp.exec("c").unwrap();
+        assert_eq!(insp.into_inner().output, vec![42]);
+    }
+
+    #[test]
+    fn patches() {
+
+        let prog = [1101,2,3,7,4,7,99,0];
+        let mut insp = inspector(&prog, &[]);
+
+        let patch = Patch::new("forty")
+            .set(2, 3, 40)
+            .unwrap();
+        insp.apply(&patch)
+            .unwrap();
+
+        let screen = insp.render();
+        assert!(screen.contains("-- patches\n  forty: [2] 3 -> 40\n"));
+        assert!(screen.contains("applied patch forty"));
+
+        // Applying it again finds the patched value
+        assert!(matches!(insp.apply(&patch), Err(PatchError::Mismatch { addr: 2, found: 40, .. })));
+
+        insp.exec("c").unwrap();
+        assert_eq!(insp.into_inner().output, vec![42]);
+    }
+
+    #[test]
+    fn bad_keys() {
+
+        let mut insp = inspector(&[99], &[]);
+
+        assert_eq!(insp.exec("x"), Err(InspectError::Syntax("x".to_owned())));
+        assert_eq!(insp.exec("b -1"), Err(InspectError::Machine(Error::Address)));
+        assert_eq!(insp.exec("e 1 two"), Err(InspectError::Syntax("e 1 two".to_owned())));
+        assert_eq!(insp.exec("e 20000 1"), Err(InspectError::Machine(Error::Address)));
+        assert_eq!(insp.exec("q"), Ok(false));
+    }
+
+    #[test]
+    fn hex_cells() {
+
+        assert_eq!(hex(255), "ff");
+        assert_eq!(hex(-16), "-10");
+        assert_eq!(hex(0), "0");
+    }
+}
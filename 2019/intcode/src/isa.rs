@@ -0,0 +1,354 @@
+//! Static description of the built-in intcode instruction set
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt::Write;
+
+use crate::ext::Param;
+
+
+/// Built-in instruction
+#[derive(Debug)]
+pub struct Instr {
+
+    /// Two-digit opcode
+    pub opcode: isize,
+
+    /// Short assembly name
+    pub mnemonic: &'static str,
+
+    /// Role of each parameter, in order
+    pub params: &'static [Param],
+
+    /// Revision which introduced the instruction
+    pub since: Revision,
+}
+
+impl Instr {
+
+    /// Size of the instruction in cells, including the opcode
+    pub fn size(&self) -> usize {
+
+        1 + self.params.len()
+    }
+
+    /// Whether this instruction can transfer control somewhere other than
+    /// the next instruction
+    pub fn is_jump(&self) -> bool {
+
+        self.opcode == 5 || self.opcode == 6
+    }
+}
+
+
+/// Revision of the intcode specification, which grew over several puzzles
+///
+/// The latest revision is the default.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Revision {
+
+    /// Add, multiply and halt, with position mode parameters only
+    Day2,
+
+    /// Adds I/O, jumps, comparisons and immediate mode
+    Day5,
+
+    /// Adds relative mode and adjusting the relative base
+    #[default]
+    Day9,
+}
+
+impl Revision {
+
+    /// Every revision, oldest first
+    pub const ALL: [Revision; 3] = [Self::Day2, Self::Day5, Self::Day9];
+
+    /// Finds the revision with the given name (e.g. `day5`)
+    pub fn by_name(name: &str) -> Option<Self> {
+
+        Self::ALL.iter()
+            .cloned()
+            .find(|rev| rev.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+
+        match self {
+            Self::Day2 => "day2",
+            Self::Day5 => "day5",
+            Self::Day9 => "day9",
+        }
+    }
+
+    /// Whether a built-in instruction exists in this revision
+    pub fn has(self, instr: &Instr) -> bool {
+
+        instr.since <= self
+    }
+
+    /// Whether a parameter mode exists in this revision
+    pub fn has_mode(self, mode: isize) -> bool {
+
+        match mode {
+            0 => true,
+            1 => self >= Self::Day5,
+            2 => self >= Self::Day9,
+            _ => false,
+        }
+    }
+}
+
+
+/// Most parameters an instruction can have, such that any mode digit for each
+/// of them fits in an instruction cell alongside the two-digit opcode
+pub const MAX_PARAMS: usize = isize::MAX.ilog10() as usize - 2;
+
+
+const RRW: &[Param] = &[Param::Read, Param::Read, Param::Write];
+const RR: &[Param] = &[Param::Read, Param::Read];
+const R: &[Param] = &[Param::Read];
+const W: &[Param] = &[Param::Write];
+
+
+/// All built-in instructions
+pub const INSTRUCTIONS: [Instr; 10] = [
+    Instr { opcode: 1, mnemonic: "add", params: RRW, since: Revision::Day2 },
+    Instr { opcode: 2, mnemonic: "mul", params: RRW, since: Revision::Day2 },
+    Instr { opcode: 3, mnemonic: "in", params: W, since: Revision::Day5 },
+    Instr { opcode: 4, mnemonic: "out", params: R, since: Revision::Day5 },
+    Instr { opcode: 5, mnemonic: "jt", params: RR, since: Revision::Day5 },
+    Instr { opcode: 6, mnemonic: "jf", params: RR, since: Revision::Day5 },
+    Instr { opcode: 7, mnemonic: "lt", params: RRW, since: Revision::Day5 },
+    Instr { opcode: 8, mnemonic: "eq", params: RRW, since: Revision::Day5 },
+    Instr { opcode: 9, mnemonic: "arb", params: R, since: Revision::Day9 },
+    Instr { opcode: 99, mnemonic: "hlt", params: &[], since: Revision::Day2 },
+];
+
+
+/// Finds the built-in instruction with the given two-digit opcode
+pub fn lookup(opcode: isize) -> Option<&'static Instr> {
+
+    INSTRUCTIONS.iter()
+        .find(|i| i.opcode == opcode)
+}
+
+/// Finds the built-in instruction with the given mnemonic
+pub fn by_mnemonic(mnemonic: &str) -> Option<&'static Instr> {
+
+    INSTRUCTIONS.iter()
+        .find(|i| i.mnemonic.eq_ignore_ascii_case(mnemonic))
+}
+
+/// Extracts the two-digit opcode from an instruction cell
+pub fn opcode(op: isize) -> isize {
+
+    op % 100
+}
+
+/// Extracts the mode digit of the given parameter from an instruction cell
+pub fn mode(op: isize, param_idx: usize) -> isize {
+
+    (op / 10isize.pow(2 + param_idx as u32)) % 10
+}
+
+/// Resolves the address a position or relative mode parameter refers to
+///
+/// Returns `None` for other modes, and for addresses which are negative or
+/// overflow.
+pub fn address(mode: isize, arg: isize, rb: isize) -> Option<usize> {
+
+    match mode {
+        0 => usize::try_from(arg).ok(),
+        2 => rb.checked_add(arg)
+            .and_then(|addr| usize::try_from(addr).ok()),
+        _ => None,
+    }
+}
+
+/// Addresses which the built-in instruction at `addr` writes, given the
+/// relative base `rb`
+///
+/// Returns nothing if `addr` does not hold a complete built-in instruction.
+pub fn write_targets(cells: &[isize], addr: usize, rb: isize) -> Vec<usize> {
+
+    let op = cells.get(addr)
+        .cloned()
+        .unwrap_or(0);
+
+    let instr = match lookup(opcode(op)) {
+        Some(instr) if addr + instr.size() <= cells.len() => instr,
+        _ => return Vec::new(),
+    };
+
+    instr.params.iter()
+        .enumerate()
+        .filter(|(_, &role)| role == Param::Write)
+        .filter_map(|(i, _)| address(mode(op, i), cells[addr + 1 + i], rb))
+        .collect()
+}
+
+/// Builds an instruction cell from an opcode and parameter modes
+pub fn encode(opcode: isize, modes: &[isize]) -> isize {
+
+    modes.iter()
+        .enumerate()
+        .fold(opcode, |op, (i, m)| op + m * 10isize.pow(2 + i as u32))
+}
+
+/// Formats the instruction at `addr` in assembly syntax
+///
+/// Position mode parameters are shown as `[addr]`, relative mode parameters as
+/// `[rb+offset]` and immediate parameters as plain numbers. Returns the text
+/// and size of the instruction, or `None` if it is not a valid instruction.
+pub fn disassemble(cells: &[isize], addr: usize) -> Option<(String, usize)> {
+
+    let op = *cells.get(addr)?;
+    let instr = lookup(opcode(op))
+        .filter(|_| op > 0)?;
+    let args = cells.get(addr + 1..addr + instr.size())?;
+
+    let mut text = instr.mnemonic.to_owned();
+
+    for (i, (&arg, &role)) in args.iter().zip(instr.params).enumerate() {
+
+        text.push_str(if i == 0 { " " } else { ", " });
+
+        match (mode(op, i), role) {
+            (0, _) => write!(text, "[{}]", arg),
+            (1, Param::Read) => write!(text, "{}", arg),
+            (2, _) => write!(text, "[rb{:+}]", arg),
+            _ => return None,
+        }.unwrap();
+    }
+
+    Some((text, instr.size()))
+}
+
+/// Parses a single instruction in the syntax produced by `disassemble`
+///
+/// Returns the encoded cells, or `None` if `text` is not a valid instruction.
+pub fn assemble(text: &str) -> Option<Vec<isize>> {
+
+    let text = text.trim();
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(idx) => text.split_at(idx),
+        None => (text, ""),
+    };
+    let instr = by_mnemonic(mnemonic)?;
+
+    let args: Vec<&str> = match rest.trim() {
+        "" => Vec::new(),
+        rest => rest.split(',').map(str::trim).collect(),
+    };
+    if args.len() != instr.params.len() {
+        return None;
+    }
+
+    let mut modes = Vec::new();
+    let mut cells = vec![0];
+
+    for (arg, &role) in args.iter().zip(instr.params) {
+
+        let (mode, val) = match arg.strip_prefix('[').and_then(|a| a.strip_suffix(']')) {
+            Some(inner) => match inner.trim().strip_prefix("rb") {
+                Some(offset) => match offset.trim() {
+                    "" => (2, 0),
+                    offset => (2, offset.strip_prefix('+').unwrap_or(offset).trim().parse().ok()?),
+                },
+                None => (0, inner.trim().parse().ok()?),
+            },
+            None if role == Param::Read => (1, arg.parse().ok()?),
+            None => return None,
+        };
+
+        modes.push(mode);
+        cells.push(val);
+    }
+
+    cells[0] = encode(instr.opcode, &modes);
+
+    Some(cells)
+}
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+
+        let op = encode(7, &[1, 2, 0]);
+
+        assert_eq!(op, 2107);
+        assert_eq!(opcode(op), 7);
+        assert_eq!((mode(op, 0), mode(op, 1), mode(op, 2)), (1, 2, 0));
+        assert_eq!(by_mnemonic("LT").map(|i| i.opcode), Some(7));
+    }
+
+    #[test]
+    fn write_targets_modes() {
+
+        let cells = [21101, 1, 2, -3, 1002, 9, 2, 9, 3, 5];
+
+        assert_eq!(write_targets(&cells, 0, 10), vec![7]);
+        assert_eq!(write_targets(&cells, 0, 2), vec![]);
+        assert_eq!(write_targets(&cells, 4, 0), vec![9]);
+        assert_eq!(write_targets(&cells, 8, 0), vec![5]);
+        assert_eq!(write_targets(&cells, 9, 0), vec![]);
+        assert_eq!(address(2, 1, isize::MAX), None);
+        assert_eq!(address(1, 1, 0), None);
+    }
+
+    #[test]
+    fn disassemble_modes() {
+
+        let cells = [2101, 4, -3, 5, 104, 0, 9];
+
+        assert_eq!(disassemble(&cells, 0), Some(("add 4, [rb-3], [5]".to_owned(), 4)));
+        assert_eq!(disassemble(&cells, 4), Some(("out 0".to_owned(), 2)));
+        assert_eq!(disassemble(&cells, 5), None);
+        assert_eq!(disassemble(&cells, 6), None);
+    }
+
+    #[test]
+    fn assemble_round_trip() {
+
+        for text in &["add 4, [rb-3], [5]", "in [rb+2]", "jt [1], 7", "hlt"] {
+            let cells = assemble(text)
+                .unwrap();
+            assert_eq!(disassemble(&cells, 0), Some((text.to_string(), cells.len())));
+        }
+
+        assert_eq!(assemble("MUL [rb], -2, [0]"), Some(vec![1202, 0, -2, 0]));
+        assert_eq!(assemble("in 5"), None);
+        assert_eq!(assemble("add 1, 2"), None);
+        assert_eq!(assemble("nop"), None);
+        assert_eq!(assemble("out [x]"), None);
+    }
+
+    #[test]
+    fn revisions() {
+
+        let introduced = |rev| INSTRUCTIONS.iter()
+            .filter(|i| i.since == rev)
+            .map(|i| i.opcode)
+            .collect::<Vec<_>>();
+
+        assert_eq!(introduced(Revision::Day2), vec![1, 2, 99]);
+        assert_eq!(introduced(Revision::Day5), vec![3, 4, 5, 6, 7, 8]);
+        assert_eq!(introduced(Revision::Day9), vec![9]);
+
+        assert!(!Revision::Day2.has_mode(1));
+        assert!(Revision::Day5.has_mode(1));
+        assert!(!Revision::Day5.has_mode(2));
+        assert!(!Revision::Day9.has_mode(3));
+
+        assert_eq!(Revision::by_name("Day5"), Some(Revision::Day5));
+        assert_eq!(Revision::by_name("day7"), None);
+    }
+}
@@ -0,0 +1,401 @@
+//! Classification of program memory into code and data
+//!
+//! A `Layout` starts from a static walk of the control flow reachable from
+//! address 0, and can be refined by observing a real execution. Cells which
+//! are executed are code; cells which are only ever read or written by
+//! instructions are data; everything else is unknown.
+
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::{Computer, Error, IoHandler, Session};
+use crate::ext::Param;
+use crate::flow::{Flow, Node};
+use crate::isa;
+
+
+/// What a memory cell is used for
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+
+    /// Part of an instruction
+    Code,
+
+    /// Operand of an instruction
+    Data,
+
+    /// Never seen in use
+    Unknown,
+}
+
+
+/// Contiguous run of cells of the same kind
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Region {
+
+    /// What the cells are used for
+    pub kind: Kind,
+
+    /// Addresses of the cells
+    pub range: Range<usize>,
+}
+
+impl Region {
+
+    /// Converts the cells of this region into values of type `T`
+    ///
+    /// Fails if any cell cannot be represented as a `T`.
+    pub fn extract<T>(&self, mem: &[isize]) -> Result<Vec<T>, T::Error>
+    where T: TryFrom<isize>
+    {
+        mem[self.range.clone()].iter()
+            .map(|&v| T::try_from(v))
+            .collect()
+    }
+}
+
+
+/// Classification of every cell of a program
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Layout {
+
+    /// Kind of each cell
+    kinds: Vec<Kind>,
+
+    /// Whether each cell starts an instruction
+    starts: Vec<bool>,
+
+    /// Whether each cell is read by an instruction parameter
+    reads: Vec<bool>,
+
+    /// Whether each cell is written by an instruction parameter
+    writes: Vec<bool>,
+
+    /// Whether the static walk found a jump through memory
+    computed_jumps: bool,
+}
+
+impl Layout {
+
+    /// Classifies a program using only its static control flow
+    ///
+    /// Jumps through memory cannot be followed, so code reached only that way
+    /// is left unknown until it is observed.
+    pub fn analyze(prog: &[isize]) -> Self {
+
+        let flow = Flow::walk(prog);
+        let mut layout = Self {
+            kinds: vec![Kind::Unknown; prog.len()],
+            starts: vec![false; prog.len()],
+            reads: vec![false; prog.len()],
+            writes: vec![false; prog.len()],
+            computed_jumps: flow.computed_jumps,
+        };
+
+        for &(addr, node) in &flow.nodes {
+
+            let instr = match node {
+                Node::Instr(instr) => instr,
+                _ => continue,
+            };
+            let op = prog[addr];
+            let next = addr + instr.size();
+            let args = &prog[addr + 1..next];
+
+            layout.mark(addr..next, Kind::Code);
+            layout.starts[addr] = true;
+
+            for (i, (&arg, &role)) in args.iter().zip(instr.params).enumerate() {
+                if isa::mode(op, i) == 0 {
+                    layout.access(arg, role);
+                }
+            }
+        }
+
+        layout
+    }
+
+    /// Classifies a program statically, then refines the result by running it
+    /// to completion on a fresh computer
+    pub fn trace<H>(prog: &[isize], io: H) -> Result<Self, Error>
+    where H: IoHandler
+    {
+        let mut layout = Self::analyze(prog);
+        layout.run(prog, io)?;
+
+        Ok(layout)
+    }
+
+    /// Refines the classification by running a program to completion on a
+    /// fresh computer
+    ///
+    /// Can be called repeatedly (e.g. with different input) to accumulate
+    /// observations.
+    pub fn run<H>(&mut self, prog: &[isize], io: H) -> Result<(), Error>
+    where H: IoHandler
+    {
+        let mut mem = prog.to_vec();
+        mem.resize(mem.len().max(crate::program::MEM_SIZE), 0);
+
+        let mut computer = Computer::new(io);
+        let mut session = computer.attach(&mut mem);
+
+        loop {
+            self.observe(&session);
+            if !session.step()? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records the instruction `session` is about to execute
+    ///
+    /// Cells outside of the program are ignored.
+    pub fn observe<H>(&mut self, session: &Session<H>) {
+
+        let mem = session.mem();
+        let ip = session.ip();
+        let op = mem.get(ip)
+            .cloned()
+            .unwrap_or(0);
+
+        let instr = match isa::lookup(isa::opcode(op)) {
+            Some(instr) if op > 0 && ip + instr.size() <= mem.len() => instr,
+            _ => return,
+        };
+
+        self.mark(ip..ip + instr.size(), Kind::Code);
+        if let Some(start) = self.starts.get_mut(ip) {
+            *start = true;
+        }
+
+        let args = &mem[ip + 1..ip + instr.size()];
+        for (i, (&arg, &role)) in args.iter().zip(instr.params).enumerate() {
+            match isa::mode(op, i) {
+                0 => self.access(arg, role),
+                2 => self.access(session.rb() + arg, role),
+                _ => { },
+            }
+        }
+    }
+
+    /// Records an access to `addr` by a parameter
+    fn access(&mut self, addr: isize, role: Param) {
+
+        let addr = match usize::try_from(addr) {
+            Ok(addr) if addr < self.kinds.len() => addr,
+            _ => return,
+        };
+
+        self.mark(addr..addr + 1, Kind::Data);
+        match role {
+            Param::Read => self.reads[addr] = true,
+            Param::Write => self.writes[addr] = true,
+        }
+    }
+
+    /// Upgrades the kind of cells in `range`
+    ///
+    /// Code is never downgraded to data, so self-modifying instructions stay
+    /// classified as code.
+    fn mark(&mut self, range: Range<usize>, kind: Kind) {
+
+        let end = range.end.min(self.kinds.len());
+
+        for cell in self.kinds.get_mut(range.start..end).into_iter().flatten() {
+            if *cell != Kind::Code {
+                *cell = kind;
+            }
+        }
+    }
+
+    /// Kind of the cell at `addr`
+    pub fn kind(&self, addr: usize) -> Kind {
+
+        self.kinds.get(addr)
+            .cloned()
+            .unwrap_or(Kind::Unknown)
+    }
+
+    /// Whether an instruction was found starting at `addr`
+    pub fn is_instruction(&self, addr: usize) -> bool {
+
+        self.starts.get(addr) == Some(&true)
+    }
+
+    /// Whether the cell at `addr` was seen being read by a parameter
+    pub fn is_read(&self, addr: usize) -> bool {
+
+        self.reads.get(addr) == Some(&true)
+    }
+
+    /// Whether the cell at `addr` was seen being written by a parameter
+    ///
+    /// Code which is written to may be self-modifying.
+    pub fn is_written(&self, addr: usize) -> bool {
+
+        self.writes.get(addr) == Some(&true)
+    }
+
+    /// Whether reachable code jumps to addresses held in memory, in which case
+    /// the static walk may have missed some code
+    pub fn has_computed_jumps(&self) -> bool {
+
+        self.computed_jumps
+    }
+
+    /// Splits the program into regions, in order of address
+    pub fn regions(&self) -> Vec<Region> {
+
+        let mut regions: Vec<Region> = Vec::new();
+
+        for (addr, &kind) in self.kinds.iter().enumerate() {
+            match regions.last_mut() {
+                Some(r) if r.kind == kind => r.range.end = addr + 1,
+                _ => regions.push(Region { kind, range: addr..addr + 1 }),
+            }
+        }
+
+        regions
+    }
+
+    /// Regions of data, which are candidates for embedded tables
+    pub fn tables(&self) -> Vec<Region> {
+
+        self.regions()
+            .into_iter()
+            .filter(|r| r.kind == Kind::Data)
+            .collect()
+    }
+
+    /// Formats `mem` region by region
+    ///
+    /// Code is disassembled, while data and unknown cells are listed eight to
+    /// a line.
+    pub fn dump(&self, mem: &[isize]) -> String {
+
+        let mut out = String::new();
+
+        for region in self.regions() {
+
+            let label = match region.kind {
+                Kind::Code => "code",
+                Kind::Data => "data",
+                Kind::Unknown => "unknown",
+            };
+            writeln!(out, "{} {}..{}", label, region.range.start, region.range.end)
+                .unwrap();
+
+            let mut addr = region.range.start;
+            while addr < region.range.end {
+
+                let text = match region.kind {
+                    Kind::Code => isa::disassemble(mem, addr)
+                        .filter(|(_, size)| addr + size <= region.range.end),
+                    _ => None,
+                };
+
+                let (text, size) = text.unwrap_or_else(|| {
+                    let end = (addr + 8).min(region.range.end);
+                    let vals: Vec<String> = mem[addr..end].iter()
+                        .map(|v| v.to_string())
+                        .collect();
+                    (vals.join(" "), end - addr)
+                });
+
+                writeln!(out, "{:>6}  {}", addr, text)
+                    .unwrap();
+                addr += size;
+            }
+        }
+
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::InputError;
+
+    #[test]
+    fn static_layout() {
+
+        // Adds two numbers stored after the program
+        let prog = [1,9,10,11,4,11,99,0,0,30,12,0];
+        let layout = Layout::analyze(&prog);
+
+        assert_eq!(layout.regions(), vec![
+            Region { kind: Kind::Code, range: 0..7 },
+            Region { kind: Kind::Unknown, range: 7..9 },
+            Region { kind: Kind::Data, range: 9..12 },
+        ]);
+
+        assert_eq!(layout.tables()[0].extract::<u8>(&prog), Ok(vec![30, 12, 0]));
+        assert!(Region { kind: Kind::Data, range: 0..2 }.extract::<u8>(&[1, -1]).is_err());
+
+        assert_eq!(layout.dump(&prog), "\
+code 0..7
+     0  add [9], [10], [11]
+     4  out [11]
+     6  hlt
+unknown 7..9
+     7  0 0
+data 9..12
+     9  30 12 0
+");
+    }
+
+    #[test]
+    fn observed_layout() {
+
+        // Jumps through memory to code which the static walk cannot see
+        let mut prog = vec![3,20,6,21,22,99,0,4,20,99];
+        prog.resize(22, 0);
+        prog.push(7);
+
+        let layout = Layout::analyze(&prog);
+        assert_eq!(layout.kind(7), Kind::Unknown);
+
+        struct Echo;
+
+        impl IoHandler for Echo {
+
+            fn input(&mut self) -> Result<isize, InputError> {
+                Ok(5)
+            }
+
+            fn output(&mut self, val: isize) {
+                assert_eq!(val, 5);
+            }
+        }
+
+        let layout = Layout::trace(&prog, Echo)
+            .unwrap();
+
+        assert_eq!(layout.kind(7), Kind::Code);
+        assert_eq!(layout.kind(8), Kind::Code);
+        assert!(layout.is_instruction(7));
+        assert!(!layout.is_instruction(8));
+        assert!(layout.is_written(20));
+        assert!(layout.is_read(22));
+        assert!(!layout.is_written(22));
+        assert!(layout.has_computed_jumps());
+        assert_eq!(layout.kind(20), Kind::Data);
+        assert_eq!(layout.kind(22), Kind::Data);
+        assert_eq!(layout.kind(6), Kind::Unknown);
+    }
+
+    #[test]
+    fn empty() {
+
+        let layout = Layout::analyze(&[]);
+        assert!(layout.regions().is_empty());
+        assert_eq!(layout.kind(0), Kind::Unknown);
+        assert_eq!(layout.dump(&[]), "");
+    }
+}
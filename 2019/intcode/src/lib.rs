@@ -1,22 +1,112 @@
 //! Implementation of the intcode computer for AoC 2019
-
-use std::convert::TryInto;
-use std::io;
-use std::num::ParseIntError;
+//!
+//! The interpreter itself only needs `core` and `alloc`, so the crate can be
+//! built with `default-features = false` for targets without `std`. The
+//! default `std` feature adds stdin/stdout I/O, loading programs from files,
+//! the async executor and the analysis and debugging tools.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::ops::Range;
+
+#[cfg(feature = "std")]
+use aio::AsyncIoHandler;
+use ext::{Extension, Invocation, Param, Registry};
+use image::{Image, ImageError};
+use isa::Revision;
+use mmio::{Bus, Device};
+use program::{ParseError, Program};
+
+// Tests are always built with `std`, and use the stdio handler throughout
+#[cfg(any(feature = "std", test))]
+pub use stdio::{eval, DefaultIoHandler};
+
+#[cfg(feature = "std")]
+pub mod aio;
+#[cfg(feature = "std")]
+pub mod conformance;
+#[cfg(feature = "std")]
+pub mod diff;
+pub mod ext;
+#[cfg(feature = "std")]
+mod flow;
+pub mod frame;
+#[cfg(feature = "std")]
+pub mod fuzz;
+pub mod image;
+#[cfg(feature = "std")]
+pub mod inspect;
+pub mod isa;
+#[cfg(feature = "std")]
+pub mod layout;
+#[cfg(feature = "std")]
+pub mod lint;
+pub mod machine;
+pub mod mmio;
+#[cfg(feature = "std")]
+pub mod opt;
+#[cfg(feature = "std")]
+pub mod patch;
+pub mod program;
+#[cfg(feature = "std")]
+pub mod repl;
+#[cfg(feature = "std")]
+pub mod search;
+#[cfg(any(feature = "std", test))]
+mod stdio;
+#[cfg(feature = "std")]
+pub mod sweep;
+#[cfg(feature = "std")]
+pub mod symbolic;
+#[cfg(feature = "std")]
+pub mod timeline;
 
 
 /// Error encountered during the execution of an intcode program
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Error {
 
     /// Invalid address
     Address,
 
-    /// Unrecognized opcode
+    /// Unrecognized opcode, or one not available in the selected revision
     Opcode,
 
+    /// Parameter mode is unrecognized, not available in the selected revision
+    /// or cannot be used for the parameter (e.g. writing in immediate mode)
+    Mode,
+
     /// Requested operation is not valid in the current state
     State,
+
+    /// I/O handler could not supply input
+    ///
+    /// The instruction pointer is left on the input instruction, so execution
+    /// resumes there once input is available.
+    Input(InputError),
+}
+
+impl From<InputError> for Error {
+    fn from(err: InputError) -> Self {
+        Self::Input(err)
+    }
+}
+
+
+/// Reason an I/O handler could not supply input
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InputError {
+
+    /// No input is available, either yet or at all (e.g. end of file)
+    Unavailable,
+
+    /// Input was received but is not an integer
+    Invalid,
 }
 
 
@@ -24,39 +114,32 @@ pub enum Error {
 pub trait IoHandler {
 
     /// Retrieves a single integer as input
-    fn input(&mut self) -> isize;
+    fn input(&mut self) -> Result<isize, InputError>;
 
     /// Outputs a single integer
     fn output(&mut self, val: isize);
 }
 
 
-/// Default I/O handler
-pub struct DefaultIoHandler;
-
-impl IoHandler for DefaultIoHandler {
+/// Result of executing a single instruction
+enum Step {
 
-    fn input(&mut self) -> isize {
+    /// Instruction completed
+    Continue,
 
-        let mut input = String::new();
+    /// Program halted
+    Halt,
 
-        io::stdin().read_line(&mut input)
-            .expect("failed to read input");
+    /// Input instruction is waiting for a value (see `Cpu::provide_input`)
+    Input,
 
-        input.trim()
-            .parse()
-            .expect("failed to parse input")
-    }
-
-    fn output(&mut self, val: isize) {
-
-        println!("{}", val);
-    }
+    /// Output instruction produced a value
+    Output(isize),
 }
 
 
 /// Tracks state of an executing CPU
-struct Cpu<'a, H> {
+struct Cpu<'a> {
 
     /// Instruction pointer
     ip: usize,
@@ -64,44 +147,84 @@ struct Cpu<'a, H> {
     /// Main memory
     mem: &'a mut [isize],
 
-    /// I/O handler
-    io: &'a mut H,
-
     /// Relative base
     rb: isize,
+
+    /// Custom opcodes
+    ext: &'a mut Registry,
+
+    /// Memory-mapped devices
+    bus: &'a mut Bus,
+
+    /// Revision of the instruction set to accept
+    rev: Revision,
 }
 
-impl<'a, H> Cpu<'a, H>
-where H: IoHandler
-{
+impl<'a> Cpu<'a> {
 
-    fn decode_op(&self) -> isize {
+    /// Loads the instruction cell at the instruction pointer
+    fn op(&self) -> Result<isize, Error> {
 
-        let op = self.mem[self.ip];
-        let op = format!("{:0>2}", op);
-        let op_idx = op.len() - 2;
+        self.mem.get(self.ip)
+            .cloned()
+            .ok_or(Error::Address)
+    }
 
-        op[op_idx..].parse()
-            .expect("failed to parse opcode")
+    /// Loads the raw value of a parameter of the current instruction
+    ///
+    /// Fails if the instruction extends past the end of memory.
+    fn arg(&self, param_idx: usize) -> Result<isize, Error> {
+
+        self.mem.get(self.ip + 1 + param_idx)
+            .cloned()
+            .ok_or(Error::Address)
     }
 
-    fn load_param(&self, param_idx: usize) -> Result<isize, Error> {
+    fn decode_op(&self) -> Result<isize, Error> {
 
-        let op = format!("{:0>10}", self.mem[self.ip]);
-        let flag_idx = op.len() - 3 - param_idx;
+        Ok(isa::opcode(self.op()?))
+    }
 
-        let param_addr: usize = match &op[flag_idx..(flag_idx + 1)] {
-            "1" => self.ip + 1 + param_idx,
-            "0" => self.mem[self.ip + 1 + param_idx]
-                .try_into()
-                .map_err(|_| Error::Address)?,
-            "2" => (self.rb + self.mem[self.ip + 1 + param_idx])
-                    .try_into()
-                    .map_err(|_| Error::Address)?,
-            _ => panic!("unknown parameter mode"),
+    /// Loads from an address, routing to any device mapped there
+    ///
+    /// Fails if the address is neither mapped nor within memory.
+    fn read(&mut self, addr: usize) -> Result<isize, Error> {
+
+        match self.bus.lookup(addr) {
+            Some((dev, offset)) => dev.read(offset),
+            None => self.mem.get(addr)
+                .cloned()
+                .ok_or(Error::Address),
+        }
+    }
+
+    /// Stores to an address, routing to any device mapped there
+    ///
+    /// Fails if the address is neither mapped nor within memory.
+    fn write(&mut self, addr: usize, val: isize) -> Result<(), Error> {
+
+        match self.bus.lookup(addr) {
+            Some((dev, offset)) => dev.write(offset, val),
+            None => {
+                *self.mem.get_mut(addr).ok_or(Error::Address)? = val;
+                Ok(())
+            },
+        }
+    }
+
+    fn load_param(&mut self, param_idx: usize) -> Result<isize, Error> {
+
+        let mode = isa::mode(self.op()?, param_idx);
+        let arg = self.arg(param_idx)?;
+
+        let param_addr = match mode {
+            1 => return Ok(arg),
+            0 | 2 => isa::address(mode, arg, self.rb)
+                .ok_or(Error::Address)?,
+            _ => return Err(Error::Mode),
         };
 
-        Ok(self.mem[param_addr])
+        self.read(param_addr)
     }
 
     fn store_by_param(
@@ -110,23 +233,16 @@ where H: IoHandler
         val: isize,
     ) -> Result<(), Error> {
 
-        let op = format!("{:0>10}", self.mem[self.ip]);
-        let flag_idx = op.len() - 3 - param_idx;
+        let mode = isa::mode(self.op()?, param_idx);
+        let arg = self.arg(param_idx)?;
 
-        let param_addr: usize = match &op[flag_idx..(flag_idx + 1)] {
-            "1" => panic!("immediate mode not supported for writing"),
-            "0" => self.mem[self.ip + 1 + param_idx]
-                .try_into()
-                .map_err(|_| Error::Address)?,
-            "2" => (self.rb + self.mem[self.ip + 1 + param_idx])
-                    .try_into()
-                    .map_err(|_| Error::Address)?,
-            _ => panic!("unknown parameter mode"),
+        let param_addr = match mode {
+            0 | 2 => isa::address(mode, arg, self.rb)
+                .ok_or(Error::Address)?,
+            _ => return Err(Error::Mode),
         };
 
-        self.mem[param_addr] = val;
-
-        Ok(())
+        self.write(param_addr, val)
     }
 
     fn add(&mut self) -> Result<(), Error> {
@@ -153,9 +269,9 @@ where H: IoHandler
         Ok(())
     }
 
-    fn input(&mut self) -> Result<(), Error> {
+    /// Completes an input instruction after `cycle` returns `Step::Input`
+    fn provide_input(&mut self, val: isize) -> Result<(), Error> {
 
-        let val = self.io.input();
         self.store_by_param(0, val)?;
 
         self.ip += 2;
@@ -163,14 +279,13 @@ where H: IoHandler
         Ok(())
     }
 
-    fn output(&mut self) -> Result<(), Error> {
+    fn output(&mut self) -> Result<isize, Error> {
 
         let val = self.load_param(0)?;
-        self.io.output(val);
 
         self.ip += 2;
 
-        Ok(())
+        Ok(val)
     }
 
     fn jump_if_true(&mut self) -> Result<(), Error> {
@@ -236,72 +351,524 @@ where H: IoHandler
         Ok(())
     }
 
-    fn cycle(&mut self) -> Result<bool, Error> {
+    fn extension(&mut self, op: isize) -> Result<(), Error> {
+
+        let params = match self.ext.get(&op) {
+            Some(ext) => ext.params.clone(),
+            None => return Err(Error::Opcode),
+        };
+
+        let mut args = Vec::with_capacity(params.len());
+        let mut results = Vec::with_capacity(params.len());
+        for (i, param) in params.iter().enumerate() {
+            match param {
+                Param::Read => {
+                    args.push(self.load_param(i)?);
+                    results.push(None);
+                },
+                Param::Write => {
+                    args.push(0);
+                    results.push(Some(0));
+                },
+            }
+        }
+
+        let mut inv = Invocation {
+            ip: self.ip,
+            rb: self.rb,
+            mem: self.mem,
+            args,
+            results,
+            jump: None,
+        };
+
+        let ext = self.ext.get_mut(&op)
+            .expect("extension disappeared during execution");
+        (ext.handler)(&mut inv)?;
+
+        let Invocation { results, jump, .. } = inv;
+
+        for (i, res) in results.into_iter().enumerate() {
+            if let Some(val) = res {
+                self.store_by_param(i, val)?;
+            }
+        }
+
+        self.ip = match jump {
+            Some(addr) => addr,
+            None => self.ip + 1 + params.len(),
+        };
+
+        Ok(())
+    }
+
+    /// Rejects instructions and parameter modes outside of the revision
+    fn check_revision(&self) -> Result<(), Error> {
+
+        let op = self.op()?;
+
+        let params = match isa::lookup(isa::opcode(op)) {
+            Some(instr) if self.rev.has(instr) => instr.params,
+            Some(_) => return Err(Error::Opcode),
+            None => match self.ext.get(&isa::opcode(op)) {
+                Some(ext) => &ext.params[..],
+                None => return Err(Error::Opcode),
+            },
+        };
+
+        for (i, &param) in params.iter().enumerate() {
+            match isa::mode(op, i) {
+                mode if !self.rev.has_mode(mode) => return Err(Error::Mode),
+                1 if param == Param::Write => return Err(Error::Mode),
+                _ => { },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn cycle(&mut self) -> Result<Step, Error> {
 
-        match self.decode_op() {
+        self.check_revision()?;
+
+        match self.decode_op()? {
             1  => self.add()?,
             2  => self.mul()?,
-            3  => self.input()?,
-            4  => self.output()?,
+            3  => return Ok(Step::Input),
+            4  => return Ok(Step::Output(self.output()?)),
             5  => self.jump_if_true()?,
             6  => self.jump_if_false()?,
             7  => self.less_than()?,
             8  => self.equals()?,
             9  => self.adj_rb()?,
-            99 => return Ok(false),
-            _  => return Err(Error::Opcode),
+            99 => return Ok(Step::Halt),
+            op => self.extension(op)?,
         }
 
-        Ok(true)
+        Ok(Step::Continue)
+    }
+
+    /// Executes a single instruction, unless the program has halted
+    ///
+    /// Sets `halted` when a halt instruction executes. Input instructions are
+    /// not executed, but reported as `Step::Input` until `provide_input` is
+    /// called. Fails if the instruction pointer is outside of memory.
+    fn step(&mut self, halted: &mut bool) -> Result<Step, Error> {
+
+        if *halted {
+            return Ok(Step::Halt);
+        }
+
+        if self.ip >= self.mem.len() {
+            return Err(Error::Address);
+        }
+
+        let step = self.cycle()?;
+        if let Step::Halt = step {
+            *halted = true;
+        }
+
+        Ok(step)
     }
 }
 
 
+/// Execution state of a computer which persists between runs
+#[derive(Clone, Copy, Debug, Default)]
+struct Registers {
+
+    /// Instruction pointer
+    ip: usize,
+
+    /// Relative base
+    rb: isize,
+
+    /// Whether the program has executed a halt instruction
+    halted: bool,
+}
+
+
 /// A computer capable of executing Intcode programs
 pub struct Computer<H> {
 
     /// I/O handler used by this computer
     io: H,
+
+    /// Registers saved since the last run
+    regs: Registers,
+
+    /// Custom opcodes registered on this computer
+    ext: Registry,
+
+    /// Devices mapped into this computer's address space
+    bus: Bus,
+
+    /// Revision of the instruction set accepted by this computer
+    rev: Revision,
 }
 
-impl<H> Computer<H>
-where H: IoHandler
-{
+impl<H> Computer<H> {
 
     pub fn new(io: H) -> Self {
         Self {
             io,
+            regs: Registers::default(),
+            ext: Registry::new(),
+            bus: Bus::default(),
+            rev: Revision::default(),
+        }
+    }
+
+    /// Instruction pointer, as of the end of the last run
+    pub fn ip(&self) -> usize {
+
+        self.regs.ip
+    }
+
+    /// Sets the instruction pointer used when execution next resumes
+    pub fn set_ip(&mut self, ip: usize) {
+
+        self.regs.ip = ip;
+        self.regs.halted = false;
+    }
+
+    /// Relative base, as of the end of the last run
+    pub fn rb(&self) -> isize {
+
+        self.regs.rb
+    }
+
+    /// Sets the relative base used when execution next resumes
+    pub fn set_rb(&mut self, rb: isize) {
+
+        self.regs.rb = rb;
+    }
+
+    /// Whether the last run ended by executing a halt instruction
+    pub fn is_halted(&self) -> bool {
+
+        self.regs.halted
+    }
+
+    /// Sets whether the program counts as halted, e.g. when restoring saved
+    /// registers
+    #[cfg(feature = "std")]
+    pub(crate) fn set_halted(&mut self, halted: bool) {
+
+        self.regs.halted = halted;
+    }
+
+    /// Revision of the instruction set accepted by this computer
+    pub fn revision(&self) -> Revision {
+
+        self.rev
+    }
+
+    /// Limits programs to an earlier revision of the instruction set
+    ///
+    /// Instructions and parameter modes introduced by later revisions fail
+    /// with `Error::Opcode` or `Error::Mode` instead of executing. Custom
+    /// opcodes are accepted by every revision, but their parameters are
+    /// limited to its modes. Computers start out accepting the latest
+    /// revision.
+    pub fn set_revision(&mut self, rev: Revision) {
+
+        self.rev = rev;
+    }
+
+    /// I/O handler used by this computer
+    pub fn io(&self) -> &H {
+
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut H {
+
+        &mut self.io
+    }
+
+    /// Consumes the computer, returning its I/O handler
+    pub fn into_io(self) -> H {
+
+        self.io
+    }
+
+    /// Resets registers so the next session starts from the beginning
+    pub fn reset(&mut self) {
+
+        self.regs = Registers::default();
+    }
+
+    /// Pairs this computer with memory so a program can be stepped through
+    ///
+    /// Execution resumes from the registers saved by the previous session (or
+    /// from the start, after `new` or `reset`). Registers are saved back into
+    /// the computer when the session is dropped.
+    pub fn attach<'a>(&'a mut self, mem: &'a mut [isize]) -> Session<'a, H> {
+
+        let Self { io, regs, ext, bus, rev } = self;
+        let cpu = Cpu { ip: regs.ip, mem, rb: regs.rb, ext, bus, rev: *rev };
+
+        Session { cpu, io, regs }
+    }
+
+    /// Attaches a device to a range of addresses
+    ///
+    /// Loads and stores made by instruction parameters within `range` are
+    /// handled by `device` instead of main memory. Fails if `range` is empty
+    /// or overlaps a previously mapped range.
+    pub fn map<D>(&mut self, range: Range<usize>, device: D) -> Result<&mut Self, Error>
+    where D: Device + 'static
+    {
+        self.bus.map(range, Box::new(device))?;
+
+        Ok(self)
+    }
+
+    /// Registers a custom opcode
+    ///
+    /// Parameters of the new opcode are resolved using the same parameter
+    /// modes as built-in instructions. Fails if `opcode` is not a two-digit
+    /// opcode, is already taken by a built-in or registered instruction, or
+    /// has more than `isa::MAX_PARAMS` parameters.
+    pub fn register<F>(
+        &mut self,
+        opcode: isize,
+        params: &[Param],
+        handler: F,
+    ) -> Result<&mut Self, Error>
+    where F: FnMut(&mut Invocation) -> Result<(), Error> + 'static
+    {
+        if !(0..100).contains(&opcode)
+            || isa::lookup(opcode).is_some()
+            || self.ext.contains_key(&opcode)
+            || params.len() > isa::MAX_PARAMS
+        {
+            return Err(Error::Opcode);
         }
+
+        self.ext.insert(opcode, Extension {
+            params: params.to_vec(),
+            handler: Box::new(handler),
+        });
+
+        Ok(self)
     }
+}
+
+impl<H> Computer<H>
+where H: IoHandler
+{
 
+    /// Executes a program from the beginning until it halts
     pub fn eval(&mut self, mem: &mut [isize]) -> Result<(), Error> {
 
-        let mut cpu = Cpu { ip: 0, mem, io: &mut self.io, rb: 0 };
+        self.reset();
+        self.attach(mem)
+            .run()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H> Computer<H>
+where H: AsyncIoHandler
+{
+
+    /// Executes a program, awaiting the I/O handler whenever it performs I/O
+    ///
+    /// The returned future is not `Send` (see `aio`).
+    pub async fn eval_async(&mut self, mem: &mut [isize]) -> Result<(), Error> {
+
+        self.reset();
+
+        let Self { io, regs, ext, bus, rev } = self;
+        let mut cpu = Cpu { ip: 0, mem, rb: 0, ext, bus, rev: *rev };
+
+        let res = loop {
+            let step = match cpu.step(&mut regs.halted) {
+                Ok(step) => step,
+                Err(err) => break Err(err),
+            };
+            match step {
+                Step::Continue => { },
+                Step::Halt => break Ok(()),
+                Step::Input => {
+                    let res = match io.input().await {
+                        Ok(val) => cpu.provide_input(val),
+                        Err(err) => Err(err.into()),
+                    };
+                    if let Err(err) = res {
+                        break Err(err);
+                    }
+                },
+                Step::Output(val) => io.output(val).await,
+            }
+        };
+
+        regs.ip = cpu.ip;
+        regs.rb = cpu.rb;
+
+        res
+    }
+}
+
+
+/// Computer paired with memory, allowing a program to be stepped and inspected
+///
+/// Memory accessors operate on main memory directly; mapped devices are not
+/// consulted.
+pub struct Session<'a, H> {
+
+    /// CPU executing the program
+    cpu: Cpu<'a>,
+
+    /// I/O handler of the computer
+    io: &'a mut H,
+
+    /// Where registers are saved when the session ends
+    regs: &'a mut Registers,
+}
+
+impl<'a, H> Session<'a, H> {
+
+    pub fn ip(&self) -> usize {
+
+        self.cpu.ip
+    }
+
+    pub fn set_ip(&mut self, ip: usize) {
+
+        self.cpu.ip = ip;
+        self.regs.halted = false;
+    }
+
+    pub fn rb(&self) -> isize {
+
+        self.cpu.rb
+    }
+
+    pub fn set_rb(&mut self, rb: isize) {
+
+        self.cpu.rb = rb;
+    }
+
+    pub fn is_halted(&self) -> bool {
+
+        self.regs.halted
+    }
+
+    /// Reads a single memory cell
+    pub fn read(&self, addr: usize) -> Result<isize, Error> {
+
+        self.cpu.mem.get(addr)
+            .cloned()
+            .ok_or(Error::Address)
+    }
+
+    /// Writes a single memory cell
+    pub fn write(&mut self, addr: usize, val: isize) -> Result<(), Error> {
 
-        while cpu.cycle()? { }
+        let cell = self.cpu.mem.get_mut(addr)
+            .ok_or(Error::Address)?;
+        *cell = val;
 
         Ok(())
     }
+
+    /// Reads a range of memory cells
+    pub fn read_range(&self, range: Range<usize>) -> Result<&[isize], Error> {
+
+        self.cpu.mem.get(range)
+            .ok_or(Error::Address)
+    }
+
+    /// Writes consecutive memory cells starting at `addr`
+    ///
+    /// Nothing is written unless every cell is in bounds.
+    pub fn write_range(&mut self, addr: usize, vals: &[isize]) -> Result<(), Error> {
+
+        let end = addr.checked_add(vals.len())
+            .ok_or(Error::Address)?;
+        self.cpu.mem.get_mut(addr..end)
+            .ok_or(Error::Address)?
+            .copy_from_slice(vals);
+
+        Ok(())
+    }
+
+    /// Entire contents of main memory
+    pub fn mem(&self) -> &[isize] {
+
+        self.cpu.mem
+    }
+
+    /// I/O handler of the computer
+    pub fn io(&self) -> &H {
+
+        self.io
+    }
 }
 
+impl<'a, H> Session<'a, H>
+where H: IoHandler
+{
 
-/// Parses a textual representation of an intcode program
-pub fn parse_prog(prog: &str) -> Result<Vec<isize>, ParseIntError> {
+    /// Executes a single instruction
+    ///
+    /// Returns whether the program is still running. Stepping a halted program
+    /// does nothing. If input is needed but the I/O handler has none, the
+    /// program stays paused on the input instruction and can be stepped again
+    /// later.
+    pub fn step(&mut self) -> Result<bool, Error> {
+
+        match self.cpu.step(&mut self.regs.halted)? {
+            Step::Continue | Step::Halt => { },
+            Step::Input => self.cpu.provide_input(self.io.input()?)?,
+            Step::Output(val) => self.io.output(val),
+        }
 
-    let mut mem = vec![0; 10_000];
+        Ok(!self.regs.halted)
+    }
+
+    /// Executes instructions until the program halts
+    pub fn run(&mut self) -> Result<(), Error> {
+
+        while self.step()? { }
 
-    for (i, val) in prog.split(",").enumerate() {
-        mem[i] = val.trim().parse()?;
+        Ok(())
     }
+}
+
+impl<'a, H> Drop for Session<'a, H> {
 
-    Ok(mem)
+    fn drop(&mut self) {
+
+        self.regs.ip = self.cpu.ip;
+        self.regs.rb = self.cpu.rb;
+    }
+}
+
+
+/// Parses a textual representation of an intcode program
+///
+/// The returned memory is padded with zeroes. See `Program` for details of the
+/// accepted format.
+pub fn parse_prog(prog: &str) -> Result<Vec<isize>, ParseError> {
+
+    let prog: Program = prog.parse()?;
+
+    Ok(prog.memory())
 }
 
+/// Decodes a program image
+///
+/// Like `parse_prog`, the returned memory is padded with zeroes. See `image`
+/// for details of the format.
+pub fn load_image(data: &[u8]) -> Result<Vec<isize>, ImageError> {
 
-pub fn eval(prog: &mut [isize]) -> Result<(), Error> {
+    let prog: Program = Image::from_bytes(data)?.into();
 
-    Computer::new(DefaultIoHandler)
-        .eval(prog)
+    Ok(prog.memory())
 }
 
 
@@ -313,7 +880,7 @@ mod test {
 
     impl<'a> IoHandler for TestHandler<'a> {
 
-        fn input(&mut self) -> isize { self.0 }
+        fn input(&mut self) -> Result<isize, InputError> { Ok(self.0) }
 
         fn output(&mut self, val: isize) { self.1.push(val) }
     }
@@ -411,7 +978,7 @@ mod test {
         ]);
     }
 
-    const GRAV_PROG: &'static str = include_str!("grav-prog.txt");
+    const GRAV_PROG: &str = include_str!("grav-prog.txt");
 
     #[test]
     fn day2_part1_sln() {
@@ -469,8 +1036,8 @@ mod test {
         }
 
         impl<'a> IoHandler for SimpleHandler<'a> {
-            fn input(&mut self) -> isize {
-                self.input
+            fn input(&mut self) -> Result<isize, InputError> {
+                Ok(self.input)
             }
             fn output(&mut self, val: isize) {
                 self.output.push(val);
@@ -532,7 +1099,7 @@ mod test {
         ]);
     }
 
-    const TEST_PROG: &'static str = include_str!("test-prog.txt");
+    const TEST_PROG: &str = include_str!("test-prog.txt");
 
     #[test]
     fn day5_part1_sln() {
@@ -581,5 +1148,416 @@ mod test {
         }
     }
 
-    // TODO: port remaining day5 unit tests
+    #[test]
+    fn ext_square() {
+
+        // Opcode 10 squares its first parameter into its second
+        let mut prog = [
+            10,7,8,
+            110,-3,9,
+            99,
+            12,0,0,
+        ];
+
+        let mut computer = Computer::new(DefaultIoHandler);
+        computer
+            .register(10, &[Param::Read, Param::Write], |inv| {
+                let val = inv.get(0);
+                inv.set(1, val * val);
+                Ok(())
+            })
+            .unwrap();
+        computer
+            .eval(&mut prog)
+            .unwrap();
+
+        assert_eq!(prog[8], 144);
+        assert_eq!(prog[9], 9);
+    }
+
+    #[test]
+    fn ext_relative_mode() {
+
+        // Sets rb to 10, then copies rb+1 into rb+2 using opcode 11
+        let mut prog = [
+            109,10,
+            22211,1,2,
+            99,
+            0,0,0,0,
+            0,42,0,
+        ];
+
+        let mut computer = Computer::new(DefaultIoHandler);
+        computer
+            .register(11, &[Param::Read, Param::Write], |inv| {
+                let val = inv.get(0);
+                inv.set(1, val);
+                Ok(())
+            })
+            .unwrap();
+        computer
+            .eval(&mut prog)
+            .unwrap();
+
+        assert_eq!(prog[12], 42);
+    }
+
+    #[test]
+    fn ext_dump() {
+
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        // Opcode 12 records a snapshot of memory
+        let mut prog = [
+            12,
+            1101,1,1,7,
+            12,
+            99,
+            0,
+        ];
+
+        let dumps = Rc::new(RefCell::new(Vec::new()));
+        let sink = dumps.clone();
+
+        let mut computer = Computer::new(DefaultIoHandler);
+        computer
+            .register(12, &[], move |inv| {
+                sink.borrow_mut().push((inv.ip(), inv.mem().to_vec()));
+                Ok(())
+            })
+            .unwrap();
+        computer
+            .eval(&mut prog)
+            .unwrap();
+
+        let dumps = dumps.borrow();
+        assert_eq!(dumps.len(), 2);
+        assert_eq!(dumps[0].0, 0);
+        assert_eq!(dumps[0].1[7], 0);
+        assert_eq!(dumps[1].0, 5);
+        assert_eq!(dumps[1].1[7], 2);
+    }
+
+    #[test]
+    fn ext_conflicts() {
+
+        let mut computer = Computer::new(DefaultIoHandler);
+
+        assert!(computer.register(1, &[], |_| Ok(())).is_err());
+        assert!(computer.register(99, &[], |_| Ok(())).is_err());
+        assert!(computer.register(100, &[], |_| Ok(())).is_err());
+        assert!(computer.register(20, &[], |_| Ok(())).is_ok());
+        assert!(computer.register(20, &[], |_| Ok(())).is_err());
+
+        // Mode digits of every parameter must fit in an instruction cell
+        let params = [Param::Read; isa::MAX_PARAMS + 1];
+        assert!(computer.register(21, &params, |_| Ok(())).is_err());
+        assert!(computer.register(21, &params[1..], |_| Ok(())).is_ok());
+    }
+
+    #[test]
+    fn inspect_paused() {
+
+        // Sets rb to 7, then adds input to [17] until it reaches 10
+        let mut prog = vec![
+            109,7,
+            3,16,
+            1,17,16,17,
+            1007,17,10,18,
+            1005,18,4,
+            99,
+        ];
+        prog.resize(20, 0);
+
+        let mut output = vec![];
+        let mut computer = Computer::new(TestHandler(4, &mut output));
+
+        {
+            let mut session = computer.attach(&mut prog);
+
+            // Run up to (but not including) the first add
+            session.step().unwrap();
+            session.step().unwrap();
+            assert_eq!(session.ip(), 4);
+            assert_eq!(session.rb(), 7);
+            assert_eq!(session.read(16), Ok(4));
+
+            // Steer the machine by changing the addend
+            session.write(16, 6).unwrap();
+            session.step().unwrap();
+            assert_eq!(session.read(17), Ok(6));
+
+            assert_eq!(session.read(20), Err(Error::Address));
+            assert_eq!(session.write(20, 0), Err(Error::Address));
+            assert_eq!(session.read_range(18..21), Err(Error::Address));
+            assert_eq!(session.write_range(19, &[1, 2]), Err(Error::Address));
+            assert_eq!(session.read_range(4..8), Ok(&[1,17,16,17][..]));
+        }
+
+        // Registers persist once the session ends
+        assert_eq!(computer.ip(), 8);
+        assert!(!computer.is_halted());
+
+        computer.set_rb(0);
+        computer.attach(&mut prog)
+            .run()
+            .unwrap();
+
+        assert!(computer.is_halted());
+        assert_eq!(computer.ip(), 15);
+        assert_eq!(computer.rb(), 0);
+        assert_eq!(prog[17], 12);
+    }
+
+    #[test]
+    fn resume_after_set_ip() {
+
+        let mut prog = [
+            104,1,
+            99,
+            104,2,
+            99,
+        ];
+
+        let mut output = vec![];
+        let mut computer = Computer::new(TestHandler(0, &mut output));
+
+        computer.eval(&mut prog)
+            .unwrap();
+        assert!(computer.is_halted());
+
+        computer.set_ip(3);
+        computer.attach(&mut prog)
+            .run()
+            .unwrap();
+
+        drop(computer);
+        assert_eq!(output, vec![1, 2]);
+    }
+
+    #[test]
+    fn pause_for_input() {
+
+        /// Supplies queued input, if there is any
+        struct QueueIo(Vec<isize>, Vec<isize>);
+
+        impl IoHandler for QueueIo {
+
+            fn input(&mut self) -> Result<isize, InputError> {
+                match self.0.pop() {
+                    Some(-1) => Err(InputError::Invalid),
+                    Some(val) => Ok(val),
+                    None => Err(InputError::Unavailable),
+                }
+            }
+
+            fn output(&mut self, val: isize) {
+                self.1.push(val);
+            }
+        }
+
+        // Echoes two inputs
+        let mut prog = [3,9,4,9,3,9,4,9,99,0];
+        let mut computer = Computer::new(QueueIo(vec![1], vec![]));
+
+        assert_eq!(computer.eval(&mut prog), Err(Error::Input(InputError::Unavailable)));
+        assert_eq!(computer.ip(), 4);
+        assert_eq!(computer.io().1, vec![1]);
+
+        // Resumes from the second input instruction
+        computer.io_mut().0.push(2);
+        computer.attach(&mut prog)
+            .run()
+            .unwrap();
+        assert!(computer.is_halted());
+        assert_eq!(computer.io().1, vec![1, 2]);
+
+        let mut prog = [3,9,4,9,3,9,4,9,99,0];
+        let mut computer = Computer::new(QueueIo(vec![-1], vec![]));
+        assert_eq!(computer.eval(&mut prog), Err(Error::Input(InputError::Invalid)));
+        assert_eq!(computer.ip(), 0);
+    }
+
+    #[test]
+    fn mmio_sensor_and_screen() {
+
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Sensor(isize);
+
+        impl Device for Sensor {
+            fn read(&mut self, _offset: usize) -> Result<isize, Error> {
+                self.0 += 1;
+                Ok(self.0)
+            }
+            fn write(&mut self, _offset: usize, _val: isize) -> Result<(), Error> {
+                Err(Error::Address)
+            }
+        }
+
+        struct Screen(Rc<RefCell<Vec<(usize, isize)>>>);
+
+        impl Device for Screen {
+            fn read(&mut self, _offset: usize) -> Result<isize, Error> {
+                Ok(0)
+            }
+            fn write(&mut self, offset: usize, val: isize) -> Result<(), Error> {
+                self.0.borrow_mut().push((offset, val));
+                Ok(())
+            }
+        }
+
+        // Copies two sensor readings to the screen, then writes to plain
+        // memory at address 9
+        let mut prog = [
+            1,100,100,201,
+            1101,7,0,9,
+            99,
+            0,
+        ];
+
+        let pixels = Rc::new(RefCell::new(Vec::new()));
+
+        let mut computer = Computer::new(DefaultIoHandler);
+        computer
+            .map(100..101, Sensor(0))
+            .unwrap()
+            .map(200..210, Screen(pixels.clone()))
+            .unwrap();
+        computer
+            .eval(&mut prog)
+            .unwrap();
+
+        assert_eq!(*pixels.borrow(), vec![(1, 3)]);
+        assert_eq!(prog[9], 7);
+    }
+
+    #[test]
+    fn mmio_overlap() {
+
+        struct Null;
+
+        impl Device for Null {
+            fn read(&mut self, _offset: usize) -> Result<isize, Error> {
+                Ok(0)
+            }
+            fn write(&mut self, _offset: usize, _val: isize) -> Result<(), Error> {
+                Ok(())
+            }
+        }
+
+        let mut computer = Computer::new(DefaultIoHandler);
+
+        assert!(computer.map(10..20, Null).is_ok());
+        assert!(computer.map(19..30, Null).is_err());
+        assert!(computer.map(5..5, Null).is_err());
+        assert!(computer.map(20..30, Null).is_ok());
+    }
+
+    #[test]
+    fn mmio_device_error() {
+
+        struct ReadOnly;
+
+        impl Device for ReadOnly {
+            fn read(&mut self, _offset: usize) -> Result<isize, Error> {
+                Ok(0)
+            }
+            fn write(&mut self, _offset: usize, _val: isize) -> Result<(), Error> {
+                Err(Error::Address)
+            }
+        }
+
+        let mut prog = [
+            1101,1,1,50,
+            99,
+        ];
+
+        let res = Computer::new(DefaultIoHandler)
+            .map(50..51, ReadOnly)
+            .and_then(|c| c.eval(&mut prog));
+
+        assert!(matches!(res, Err(Error::Address)));
+    }
+
+    #[test]
+    fn out_of_bounds() {
+
+        // Loads past the end of memory
+        let mut prog = [1,0,5,0,99];
+        assert_eq!(eval(&mut prog), Err(Error::Address));
+
+        // Stores past the end of memory, leaving memory untouched
+        let mut prog = [1101,1,1,5,99];
+        assert_eq!(eval(&mut prog), Err(Error::Address));
+        assert_eq!(prog, [1101,1,1,5,99]);
+
+        // Instruction cut short by the end of memory
+        let mut prog = [1,0];
+        assert_eq!(eval(&mut prog), Err(Error::Address));
+
+        struct Three;
+
+        impl Device for Three {
+            fn read(&mut self, _offset: usize) -> Result<isize, Error> {
+                Ok(3)
+            }
+            fn write(&mut self, _offset: usize, _val: isize) -> Result<(), Error> {
+                Ok(())
+            }
+        }
+
+        // Mapped addresses past the end of memory are fine
+        let mut out = Vec::new();
+        let mut prog = [4,5,99];
+        let res = Computer::new(TestHandler(0, &mut out))
+            .map(5..6, Three)
+            .and_then(|c| c.eval(&mut prog));
+        assert_eq!(res, Ok(()));
+        assert_eq!(out, [3]);
+    }
+
+    #[test]
+    fn revisions() {
+
+        let run = |rev, prog: &[isize]| {
+            let mut mem = prog.to_vec();
+            let mut out = Vec::new();
+            let mut computer = Computer::new(TestHandler(7, &mut out));
+            computer.set_revision(rev);
+            computer.eval(&mut mem)
+                .map(|()| mem[0])
+        };
+
+        // Day 2 programs run the same everywhere
+        let add = [1,0,0,0,99];
+        for &rev in &Revision::ALL {
+            assert_eq!(run(rev, &add), Ok(2));
+        }
+
+        // Immediate mode
+        let add_imm = [1101,3,4,0,99];
+        assert_eq!(run(Revision::Day2, &add_imm), Err(Error::Mode));
+        assert_eq!(run(Revision::Day5, &add_imm), Ok(7));
+
+        // Input
+        let input = [3,0,99];
+        assert_eq!(run(Revision::Day2, &input), Err(Error::Opcode));
+        assert_eq!(run(Revision::Day5, &input), Ok(7));
+
+        // Relative base and relative mode
+        let arb = [109,1,99];
+        assert_eq!(run(Revision::Day5, &arb), Err(Error::Opcode));
+        assert_eq!(run(Revision::Day9, &arb), Ok(109));
+        let rel = [1201,0,1,0,99];
+        assert_eq!(run(Revision::Day5, &rel), Err(Error::Mode));
+        assert_eq!(run(Revision::Day9, &rel), Ok(1202));
+
+        // Writing in immediate mode, or using modes which never existed, fails
+        // in every revision
+        assert_eq!(run(Revision::Day9, &[11101,1,1,0,99]), Err(Error::Mode));
+        assert_eq!(run(Revision::Day9, &[301,0,0,0,99]), Err(Error::Mode));
+    }
 }
@@ -0,0 +1,422 @@
+//! Static checks of intcode programs
+//!
+//! Control flow is followed from address 0 without running the program (see
+//! `flow`). Jumps through memory cannot be resolved statically, so when a
+//! program contains any of them, unreachable code is only reported as
+//! informational.
+//!
+//! Instructions are checked as written, but programs often store into their
+//! own code before running it. Problems in cells which reachable instructions
+//! write to, or in code reached only by way of such cells, are therefore
+//! reported as warnings rather than errors.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::ext::Param;
+use crate::flow::{Flow, Node};
+use crate::isa;
+
+
+/// How serious a finding is
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+
+    /// Worth knowing, but possibly intended
+    Info,
+
+    /// Suspicious, but will not by itself stop the program
+    Warning,
+
+    /// Will fail if executed
+    Error,
+}
+
+impl fmt::Display for Severity {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        f.write_str(match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+
+/// Problem detected by the linter
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Lint {
+
+    /// Reachable instruction has an unknown opcode
+    UnknownOpcode(isize),
+
+    /// Parameter has a mode digit other than 0, 1 or 2
+    BadMode {
+        param: usize,
+        mode: isize,
+    },
+
+    /// Mode digits are given for parameters the instruction does not have
+    ExtraModes,
+
+    /// Write parameter is in immediate mode
+    ImmediateWrite {
+        param: usize,
+    },
+
+    /// Parameter in position mode refers to a negative address
+    NegativeAddress {
+        param: usize,
+        addr: isize,
+    },
+
+    /// Immediate jump target is outside of the program
+    JumpTarget(isize),
+
+    /// Instruction extends past the end of the program
+    Truncated,
+
+    /// Execution can continue past the end of the program
+    RunsOffEnd,
+
+    /// Cells within the code which are never executed
+    Unreachable {
+        len: usize,
+    },
+}
+
+impl fmt::Display for Lint {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            Self::UnknownOpcode(op) => write!(f, "unknown opcode {}", op),
+            Self::BadMode { param, mode } => write!(
+                f,
+                "parameter {} has invalid mode {}",
+                param,
+                mode,
+            ),
+            Self::ExtraModes => write!(f, "mode given for nonexistent parameter"),
+            Self::ImmediateWrite { param } => write!(
+                f,
+                "parameter {} is written in immediate mode",
+                param,
+            ),
+            Self::NegativeAddress { param, addr } => write!(
+                f,
+                "parameter {} refers to negative address {}",
+                param,
+                addr,
+            ),
+            Self::JumpTarget(target) => write!(
+                f,
+                "jump target {} is outside of the program",
+                target,
+            ),
+            Self::Truncated => write!(f, "instruction extends past end of program"),
+            Self::RunsOffEnd => write!(f, "execution can run past end of program"),
+            Self::Unreachable { len } => write!(f, "{} unreachable cells", len),
+        }
+    }
+}
+
+
+/// Single problem found in a program
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Finding {
+
+    /// Address of the offending instruction or region
+    pub addr: usize,
+
+    /// How serious the problem is
+    pub severity: Severity,
+
+    /// What the problem is
+    pub lint: Lint,
+}
+
+impl fmt::Display for Finding {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        write!(f, "{}: {}: {}", self.addr, self.severity, self.lint)
+    }
+}
+
+
+/// Checks a program, returning findings in order of address
+pub fn lint(prog: &[isize]) -> Vec<Finding> {
+
+    let flow = Flow::walk(prog);
+    let suspect = suspect(prog, &flow);
+    let mut findings = Vec::new();
+    let mut code = vec![false; prog.len()];
+
+    let mut report = |addr, severity, lint| {
+        let severity = match severity {
+            Severity::Error if suspect[addr] => Severity::Warning,
+            severity => severity,
+        };
+        findings.push(Finding { addr, severity, lint });
+    };
+
+    for &(addr, node) in &flow.nodes {
+
+        let instr = match node {
+            Node::Instr(instr) => instr,
+            Node::Unknown => {
+                report(addr, Severity::Error, Lint::UnknownOpcode(prog[addr]));
+                continue;
+            },
+            Node::Truncated => {
+                report(addr, Severity::Error, Lint::Truncated);
+                continue;
+            },
+            Node::End => {
+                report(addr, Severity::Error, Lint::RunsOffEnd);
+                continue;
+            },
+        };
+
+        let op = prog[addr];
+        let next = addr + instr.size();
+        let args = &prog[addr + 1..next];
+
+        for cell in &mut code[addr..next] {
+            *cell = true;
+        }
+
+        for (i, (&role, &arg)) in instr.params.iter().zip(args).enumerate() {
+            let lint = match (isa::mode(op, i), role) {
+                (0, _) if arg < 0 => Lint::NegativeAddress { param: i, addr: arg },
+                (0, _) | (1, Param::Read) | (2, _) => continue,
+                (1, Param::Write) => Lint::ImmediateWrite { param: i },
+                (mode, _) => Lint::BadMode { param: i, mode },
+            };
+            report(addr, Severity::Error, lint);
+        }
+
+        if op / 10isize.pow(2 + instr.params.len() as u32) != 0 {
+            report(addr, Severity::Warning, Lint::ExtraModes);
+        }
+    }
+
+    for &(addr, target) in &flow.bad_targets {
+        report(addr, Severity::Error, Lint::JumpTarget(target));
+    }
+
+    let end = code.iter()
+        .rposition(|&c| c)
+        .unwrap_or(0);
+    let severity = if flow.computed_jumps { Severity::Info } else { Severity::Warning };
+
+    let mut addr = 0;
+    while addr < end {
+        let len = code[addr..end].iter()
+            .take_while(|&&c| !c)
+            .count();
+        if len > 0 {
+            findings.push(Finding { addr, severity, lint: Lint::Unreachable { len } });
+        }
+        addr += len + 1;
+    }
+
+    findings.sort_by_key(|f| f.addr);
+    findings
+}
+
+/// Finds reachable addresses whose code may not be what runs
+///
+/// These are addresses whose cells are written by a reachable instruction
+/// (other than the instruction itself), along with anything reachable only
+/// through them.
+fn suspect(prog: &[isize], flow: &Flow) -> Vec<bool> {
+
+    let mut written = vec![false; prog.len()];
+
+    for &(addr, node) in &flow.nodes {
+
+        let instr = match node {
+            Node::Instr(instr) => instr,
+            _ => continue,
+        };
+        let op = prog[addr];
+        let own = addr..addr + instr.size();
+        let args = &prog[own.start + 1..own.end];
+
+        for (i, (&role, &arg)) in instr.params.iter().zip(args).enumerate() {
+            if role != Param::Write || isa::mode(op, i) != 0 {
+                continue;
+            }
+            match usize::try_from(arg) {
+                Ok(cell) if cell < prog.len() && !own.contains(&cell) => written[cell] = true,
+                _ => { },
+            }
+        }
+    }
+
+    let mut rewritten = vec![false; prog.len() + 1];
+    for &(addr, node) in &flow.nodes {
+        let end = match node {
+            Node::Instr(instr) => addr + instr.size(),
+            Node::Unknown => addr + 1,
+            Node::Truncated => prog.len(),
+            Node::End => addr,
+        };
+        rewritten[addr] = written[addr..end].iter().any(|&w| w);
+    }
+
+    // Trust spreads from the entry point, but not past rewritten code
+    let mut trusted = vec![false; prog.len() + 1];
+    let mut pending = if prog.is_empty() { vec![] } else { vec![0] };
+
+    while let Some(addr) = pending.pop() {
+
+        if trusted[addr] {
+            continue;
+        }
+        trusted[addr] = true;
+
+        if !rewritten[addr] {
+            pending.extend(flow.edges.iter()
+                .filter(|&&(from, _)| from == addr)
+                .map(|&(_, to)| to));
+        }
+    }
+
+    trusted.iter()
+        .zip(&rewritten)
+        .map(|(&t, &r)| !t || r)
+        .collect()
+}
+
+/// Formats findings as text, one per line
+pub fn report(findings: &[Finding]) -> String {
+
+    findings.iter()
+        .map(|f| format!("{}\n", f))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GRAV_PROG: &str = include_str!("grav-prog.txt");
+
+    fn lints(prog: &[isize]) -> Vec<(usize, Severity, Lint)> {
+
+        lint(prog).into_iter()
+            .map(|f| (f.addr, f.severity, f.lint))
+            .collect()
+    }
+
+    #[test]
+    fn clean() {
+
+        let prog: crate::program::Program = GRAV_PROG.parse()
+            .unwrap();
+
+        assert_eq!(lints(prog.cells()), vec![]);
+        assert_eq!(lints(&[3,9,8,9,10,9,4,9,99,-1,8]), vec![]);
+        assert_eq!(lints(&[3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9]), vec![]);
+    }
+
+    #[test]
+    fn opcodes_and_modes() {
+
+        assert_eq!(lints(&[1,0,0,0,42]), vec![
+            (4, Severity::Error, Lint::UnknownOpcode(42)),
+        ]);
+        assert_eq!(lints(&[1301,0,0,0,99]), vec![
+            (0, Severity::Error, Lint::BadMode { param: 0, mode: 3 }),
+        ]);
+        assert_eq!(lints(&[10001,0,0,0,99]), vec![
+            (0, Severity::Error, Lint::ImmediateWrite { param: 2 }),
+        ]);
+        assert_eq!(lints(&[1104,0,99]), vec![
+            (0, Severity::Warning, Lint::ExtraModes),
+        ]);
+        assert_eq!(lints(&[1,-1,0,0,99]), vec![
+            (0, Severity::Error, Lint::NegativeAddress { param: 0, addr: -1 }),
+        ]);
+        assert_eq!(lints(&[1,0,0]), vec![
+            (0, Severity::Error, Lint::Truncated),
+        ]);
+        assert_eq!(lints(&[1,0,0,0]), vec![
+            (4, Severity::Error, Lint::RunsOffEnd),
+        ]);
+    }
+
+    #[test]
+    fn jumps() {
+
+        // Jump is always taken, skipping the add
+        assert_eq!(lints(&[1105,1,7,1,0,0,0,99]), vec![
+            (3, Severity::Warning, Lint::Unreachable { len: 4 }),
+        ]);
+
+        // Jump is never taken, so its target is irrelevant
+        assert_eq!(lints(&[1106,1,-5,99]), vec![]);
+
+        // Target is out of range
+        assert_eq!(lints(&[1005,5,100,99,99,0]), vec![
+            (0, Severity::Error, Lint::JumpTarget(100)),
+        ]);
+
+        // Computed jumps may reach the skipped code
+        assert_eq!(lints(&[1105,1,7,1,0,0,0,5,10,10,99]), vec![
+            (3, Severity::Info, Lint::Unreachable { len: 4 }),
+        ]);
+    }
+
+    #[test]
+    fn rewritten() {
+
+        // Opcode is stored before it runs
+        assert_eq!(lints(&[1101,1,98,4,0]), vec![
+            (4, Severity::Warning, Lint::UnknownOpcode(0)),
+        ]);
+
+        // Jump target is stored before it runs, so what it jumps to as
+        // written is suspect too
+        assert_eq!(lints(&[1101,0,10,6,1105,1,7,42,0,0,99]), vec![
+            (7, Severity::Warning, Lint::UnknownOpcode(42)),
+        ]);
+    }
+
+    #[test]
+    fn puzzle_programs() {
+
+        let progs = [
+            GRAV_PROG,
+            include_str!("test-prog.txt"),
+            include_str!("../../07/src/amp-prog.txt"),
+            include_str!("../../09/src/boost.txt"),
+            include_str!("../../11/src/hull-paint.txt"),
+            include_str!("../../13/src/game.txt"),
+        ];
+
+        for src in progs.iter() {
+            let prog: crate::program::Program = src.parse()
+                .unwrap();
+            let errors: Vec<_> = lint(prog.cells()).into_iter()
+                .filter(|f| f.severity == Severity::Error)
+                .collect();
+            assert_eq!(errors, vec![]);
+        }
+    }
+
+    #[test]
+    fn report_format() {
+
+        assert_eq!(
+            report(&lint(&[1,0,0,0,42])),
+            "4: error: unknown opcode 42\n",
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Self-contained intcode machines
+//!
+//! Unlike a `Computer`, which borrows memory for each session and owns its I/O
+//! handler, a `Machine` owns its memory and registers and leaves I/O to the
+//! caller. That makes it cheap to clone: a machine paused on an input
+//! instruction can be forked to try several inputs, e.g. while exploring a
+//! maze.
+//!
+//! Machines run the built-in instructions of the latest revision only; custom
+//! opcodes and mapped devices are not supported.
+
+use alloc::vec::Vec;
+
+use crate::{Cpu, Error, IoHandler, Step};
+use crate::ext::Registry;
+use crate::isa::{self, Revision};
+use crate::mmio::Bus;
+use crate::program::MEM_SIZE;
+
+
+/// Reason a machine stopped running
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stop {
+
+    /// Paused on an input instruction (see `Machine::provide`)
+    Input,
+
+    /// Output instruction produced a value
+    Output(isize),
+
+    /// Program halted
+    Halt,
+}
+
+
+/// Intcode machine owning its memory and registers
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Machine {
+
+    /// Main memory
+    mem: Vec<isize>,
+
+    /// Instruction pointer
+    ip: usize,
+
+    /// Relative base
+    rb: isize,
+
+    /// Whether the program has executed a halt instruction
+    halted: bool,
+}
+
+impl Machine {
+
+    /// Creates a machine ready to run `prog` from the beginning
+    ///
+    /// Memory is padded with zeroes in the same way as `Program::memory`.
+    pub fn new(prog: &[isize]) -> Self {
+
+        let mut mem = prog.to_vec();
+        mem.resize(mem.len().max(MEM_SIZE), 0);
+
+        Self {
+            mem,
+            ip: 0,
+            rb: 0,
+            halted: false,
+        }
+    }
+
+    pub fn ip(&self) -> usize {
+
+        self.ip
+    }
+
+    pub fn set_ip(&mut self, ip: usize) {
+
+        self.ip = ip;
+        self.halted = false;
+    }
+
+    pub fn rb(&self) -> isize {
+
+        self.rb
+    }
+
+    pub fn set_rb(&mut self, rb: isize) {
+
+        self.rb = rb;
+    }
+
+    pub fn is_halted(&self) -> bool {
+
+        self.halted
+    }
+
+    /// Main memory
+    pub fn mem(&self) -> &[isize] {
+
+        &self.mem
+    }
+
+    pub fn mem_mut(&mut self) -> &mut [isize] {
+
+        &mut self.mem
+    }
+
+    /// Whether the next instruction is an input instruction
+    pub fn needs_input(&self) -> bool {
+
+        !self.halted && self.mem.get(self.ip).map(|&op| isa::opcode(op)) == Some(3)
+    }
+
+    /// Runs `f` on a CPU attached to this machine, saving its registers
+    /// afterwards
+    fn drive<T, F>(&mut self, f: F) -> Result<T, Error>
+    where F: FnOnce(&mut Cpu, &mut bool) -> Result<T, Error>
+    {
+        let mut ext = Registry::new();
+        let mut bus = Bus::default();
+
+        let mut cpu = Cpu {
+            ip: self.ip,
+            mem: &mut self.mem,
+            rb: self.rb,
+            ext: &mut ext,
+            bus: &mut bus,
+            rev: Revision::default(),
+        };
+        let res = f(&mut cpu, &mut self.halted);
+
+        self.ip = cpu.ip;
+        self.rb = cpu.rb;
+
+        res
+    }
+
+    /// Executes a single instruction
+    ///
+    /// Returns `None` if execution can simply continue. An input instruction
+    /// is not executed, but reported as `Stop::Input` until `provide` is called.
+    pub fn step(&mut self) -> Result<Option<Stop>, Error> {
+
+        self.drive(|cpu, halted| cpu.step(halted)
+            .map(stop))
+    }
+
+    /// Executes instructions until the machine needs input, produces output or
+    /// halts
+    pub fn run(&mut self) -> Result<Stop, Error> {
+
+        self.drive(|cpu, halted| loop {
+            if let Some(stop) = stop(cpu.step(halted)?) {
+                return Ok(stop);
+            }
+        })
+    }
+
+    /// Completes the input instruction the machine is paused on
+    ///
+    /// Fails with `Error::State` if the next instruction is not an input.
+    pub fn provide(&mut self, val: isize) -> Result<(), Error> {
+
+        if !self.needs_input() {
+            return Err(Error::State);
+        }
+
+        self.drive(|cpu, _| cpu.provide_input(val))
+    }
+
+    /// Runs until the machine halts or needs more input than `input` holds,
+    /// returning everything it outputs along the way
+    pub fn feed(&mut self, input: &[isize]) -> Result<Vec<isize>, Error> {
+
+        let mut input = input.iter();
+        let mut output = Vec::new();
+
+        loop {
+            match self.run()? {
+                Stop::Output(val) => output.push(val),
+                Stop::Halt => return Ok(output),
+                Stop::Input => match input.next() {
+                    Some(&val) => self.provide(val)?,
+                    None => return Ok(output),
+                },
+            }
+        }
+    }
+
+    /// Runs until the machine halts, performing I/O with `io`
+    ///
+    /// If `io` has no input to give, the machine stays paused on the input
+    /// instruction and the handler's error is returned.
+    pub fn run_io<H>(&mut self, io: &mut H) -> Result<(), Error>
+    where H: IoHandler
+    {
+        loop {
+            match self.run()? {
+                Stop::Output(val) => io.output(val),
+                Stop::Halt => return Ok(()),
+                Stop::Input => self.provide(io.input()?)?,
+            }
+        }
+    }
+}
+
+
+/// Converts the result of a CPU step into the reason to stop, if any
+fn stop(step: Step) -> Option<Stop> {
+
+    match step {
+        Step::Continue => None,
+        Step::Input => Some(Stop::Input),
+        Step::Output(val) => Some(Stop::Output(val)),
+        Step::Halt => Some(Stop::Halt),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::InputError;
+
+    /// Reads numbers until it sees 0, then outputs their sum
+    const SUM: [isize; 15] = [3,15,1006,15,12,1,15,16,16,1105,1,0,4,16,99];
+
+    #[test]
+    fn fork() {
+
+        let mut machine = Machine::new(&SUM);
+        assert_eq!(machine.feed(&[5, 6]), Ok(vec![]));
+        assert!(machine.needs_input());
+
+        // Each fork continues independently from the same point
+        let mut a = machine.clone();
+        let mut b = machine.clone();
+        assert_eq!(a.feed(&[0]), Ok(vec![11]));
+        assert_eq!(b.feed(&[4, 0]), Ok(vec![15]));
+        assert_eq!(machine.feed(&[0]), Ok(vec![11]));
+
+        assert!(a.is_halted());
+        assert_eq!(a, machine);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn stops() {
+
+        let mut machine = Machine::new(&[3,7,104,9,4,7,99,0]);
+
+        assert_eq!(machine.provide(1), Ok(()));
+        assert_eq!(machine.provide(1), Err(Error::State));
+        assert_eq!(machine.run(), Ok(Stop::Output(9)));
+        assert_eq!(machine.run(), Ok(Stop::Output(1)));
+        assert_eq!(machine.run(), Ok(Stop::Halt));
+        assert_eq!(machine.run(), Ok(Stop::Halt));
+        assert_eq!(machine.ip(), 6);
+
+        let mut machine = Machine::new(&[3,7,104,9,4,7,99,0]);
+        assert_eq!(machine.run(), Ok(Stop::Input));
+        assert_eq!(machine.run(), Ok(Stop::Input));
+        assert_eq!(machine.ip(), 0);
+    }
+
+    #[test]
+    fn run_io() {
+
+        struct ListIo(Vec<isize>, Vec<isize>);
+
+        impl IoHandler for ListIo {
+
+            fn input(&mut self) -> Result<isize, InputError> {
+                self.0.pop()
+                    .ok_or(InputError::Unavailable)
+            }
+
+            fn output(&mut self, val: isize) {
+                self.1.push(val);
+            }
+        }
+
+        let mut io = ListIo(vec![0, 2, 1], vec![]);
+        let mut machine = Machine::new(&SUM);
+        assert_eq!(machine.run_io(&mut io), Ok(()));
+        assert_eq!(io.1, vec![3]);
+
+        // Out of input, so the machine waits for more
+        let mut io = ListIo(vec![2], vec![]);
+        let mut machine = Machine::new(&SUM);
+        assert_eq!(machine.run_io(&mut io), Err(Error::Input(InputError::Unavailable)));
+        assert!(machine.needs_input());
+        io.0.push(0);
+        assert_eq!(machine.run_io(&mut io), Ok(()));
+        assert_eq!(io.1, vec![2]);
+    }
+}
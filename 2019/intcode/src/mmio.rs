@@ -0,0 +1,70 @@
+//! Memory-mapped devices
+//!
+//! A device attached to a range of addresses receives every load and store
+//! made through instruction parameters within that range, in place of main
+//! memory. Instruction fetch always reads main memory.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::Error;
+
+
+/// Peripheral attached to a range of intcode addresses
+pub trait Device {
+
+    /// Loads the value at `offset` from the start of the mapped range
+    fn read(&mut self, offset: usize) -> Result<isize, Error>;
+
+    /// Stores `val` at `offset` from the start of the mapped range
+    fn write(&mut self, offset: usize, val: isize) -> Result<(), Error>;
+}
+
+
+/// Device attached to a range of addresses
+struct Mapping {
+
+    /// Addresses routed to the device
+    range: Range<usize>,
+
+    /// Device which handles loads and stores
+    device: Box<dyn Device>,
+}
+
+
+/// All devices attached to a computer
+#[derive(Default)]
+pub(crate) struct Bus {
+    mappings: Vec<Mapping>,
+}
+
+impl Bus {
+
+    /// Attaches a device, failing if its range overlaps an existing mapping
+    pub(crate) fn map(
+        &mut self,
+        range: Range<usize>,
+        device: Box<dyn Device>,
+    ) -> Result<(), Error> {
+
+        let overlaps = self.mappings.iter()
+            .any(|m| m.range.start < range.end && range.start < m.range.end);
+
+        if range.is_empty() || overlaps {
+            return Err(Error::Address);
+        }
+
+        self.mappings.push(Mapping { range, device });
+
+        Ok(())
+    }
+
+    /// Finds the device mapped at `addr`, along with the offset into its range
+    pub(crate) fn lookup(&mut self, addr: usize) -> Option<(&mut (dyn Device + 'static), usize)> {
+
+        self.mappings.iter_mut()
+            .find(|m| m.range.contains(&addr))
+            .map(|m| (m.device.as_mut(), addr - m.range.start))
+    }
+}
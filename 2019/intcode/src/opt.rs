@@ -0,0 +1,465 @@
+//! Peephole optimization of intcode programs
+//!
+//! The optimizer makes these rewrites:
+//!
+//! - arithmetic and comparisons on two immediate operands are folded into a
+//!   move of the result
+//! - additions of zero and multiplications by one become plain moves, and
+//!   moves of a cell onto itself are removed
+//! - jumps to the next instruction, and jumps which are never taken, are
+//!   removed
+//! - zeroes at the end of the image, which are indistinguishable from the
+//!   padding added when the program is loaded, are trimmed
+//!
+//! Instructions whose cells are read or written as data, whether found
+//! statically or while running the test inputs, might be self-modified and
+//! are left alone.
+//!
+//! Removing instructions shifts everything after them, so it is only done when
+//! every address in the program can be found and relocated: there must be no
+//! jumps through memory, no relative mode parameters and no writes to code.
+//! Otherwise, rewrites are made in place.
+//!
+//! Finally, the result is checked by running both programs on every test
+//! input and comparing their outputs.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::Error;
+use crate::ext::Param;
+use crate::isa;
+use crate::layout::{Kind, Layout};
+use crate::program::{Program, MEM_SIZE};
+use crate::sweep::{Candidate, Sweep};
+use crate::{InputError, IoHandler};
+
+
+/// Summary of the changes made by the optimizer
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Report {
+
+    /// Constant operations folded into moves
+    pub folded: usize,
+
+    /// Additions and multiplications rewritten as moves
+    pub moves: usize,
+
+    /// Instructions removed
+    pub removed: usize,
+
+    /// Instructions left alone because they might be self-modified
+    pub protected: usize,
+
+    /// Whether instructions could be removed and the program relocated
+    pub relocatable: bool,
+
+    /// Size of the program before and after optimizing
+    pub size: (usize, usize),
+}
+
+
+/// Reason a program could not be optimized
+#[derive(Debug, Eq, PartialEq)]
+pub enum OptError {
+
+    /// Original program failed while being traced
+    Trace {
+        input: Vec<isize>,
+        err: Error,
+    },
+
+    /// Optimized program behaved differently from the original
+    Mismatch {
+        input: Vec<isize>,
+        expected: Result<Vec<isize>, Error>,
+        actual: Result<Vec<isize>, Error>,
+    },
+}
+
+impl fmt::Display for OptError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            Self::Trace { input, err } => write!(
+                f,
+                "program failed on input {:?}: {:?}",
+                input,
+                err,
+            ),
+            Self::Mismatch { input, expected, actual } => write!(
+                f,
+                "optimized program differs on input {:?}: expected {:?}, got {:?}",
+                input,
+                expected,
+                actual,
+            ),
+        }
+    }
+}
+
+
+/// I/O handler which feeds a fixed list of inputs
+struct TraceIo<'a> {
+    input: std::slice::Iter<'a, isize>,
+}
+
+impl<'a> IoHandler for TraceIo<'a> {
+
+    fn input(&mut self) -> Result<isize, InputError> {
+
+        self.input.next()
+            .cloned()
+            .ok_or(InputError::Unavailable)
+    }
+
+    fn output(&mut self, _: isize) { }
+}
+
+
+/// Checks that two programs produce the same output for every test input
+///
+/// Both programs are padded as if loaded by `Program::memory`.
+pub fn verify(a: &[isize], b: &[isize], tests: &[Vec<isize>]) -> Result<(), OptError> {
+
+    let a = Program::from(a.to_vec()).memory();
+    let b = Program::from(b.to_vec()).memory();
+
+    for input in tests {
+
+        let cand = Candidate::input(input.clone());
+        let expected = Sweep::new(&a).run(&cand).map(|o| o.output);
+        let actual = Sweep::new(&b).run(&cand).map(|o| o.output);
+
+        if expected != actual {
+            return Err(OptError::Mismatch {
+                input: input.clone(),
+                expected,
+                actual,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Optimizes a program, checking the result against the given test inputs
+pub fn optimize(prog: &[isize], tests: &[Vec<isize>]) -> Result<(Vec<isize>, Report), OptError> {
+
+    let mut layout = Layout::analyze(prog);
+    for input in tests {
+        layout.run(prog, TraceIo { input: input.iter() })
+            .map_err(|err| OptError::Trace { input: input.clone(), err })?;
+    }
+
+    let starts: Vec<usize> = (0..prog.len())
+        .filter(|&addr| layout.is_instruction(addr))
+        .collect();
+
+    let mut out = prog.to_vec();
+    let mut removed = vec![false; prog.len()];
+    let mut report = Report {
+        relocatable: relocatable(prog, &layout, &starts),
+        ..Report::default()
+    };
+
+    for &addr in &starts {
+
+        // Instructions seen only while running may not be in the original
+        let op = prog[addr];
+        let instr = isa::lookup(isa::opcode(op))
+            .filter(|i| op > 0 && addr + i.size() <= prog.len());
+        let cells = addr..addr + instr.map_or(1, |i| i.size());
+
+        let instr = match instr {
+            Some(instr) if !cells.clone().any(|a| layout.is_read(a) || layout.is_written(a)) => instr,
+            _ => {
+                report.protected += 1;
+                continue;
+            },
+        };
+
+        let args = &prog[addr + 1..cells.end];
+        let imm = |i: usize| if isa::mode(op, i) == 1 { Some(args[i]) } else { None };
+
+        match instr.opcode {
+
+            1 | 2 | 7 | 8 => {
+
+                let folded = match (imm(0), imm(1)) {
+                    (Some(a), Some(b)) => match instr.opcode {
+                        1 => a.checked_add(b),
+                        2 => a.checked_mul(b),
+                        7 => Some((a < b) as isize),
+                        _ => Some((a == b) as isize),
+                    },
+                    _ => None,
+                };
+
+                // Operand which is moved unchanged, as (mode, value)
+                let moved = match (folded, instr.opcode, imm(0), imm(1)) {
+                    (Some(val), ..) => Some((1, val)),
+                    (None, 1, _, Some(0)) | (None, 2, _, Some(1)) => Some((isa::mode(op, 0), args[0])),
+                    (None, 1, Some(0), _) | (None, 2, Some(1), _) => Some((isa::mode(op, 1), args[1])),
+                    _ => None,
+                };
+
+                let (mode, val) = match moved {
+                    Some(moved) => moved,
+                    None => continue,
+                };
+                let dst = (isa::mode(op, 2), args[2]);
+
+                if (mode, val) == dst && mode != 1 && report.relocatable {
+                    removed[cells].iter_mut().for_each(|r| *r = true);
+                    report.removed += 1;
+                    continue;
+                }
+
+                let rewritten = [isa::encode(1, &[mode, 1, dst.0]), val, 0, dst.1];
+                if out[cells.clone()] != rewritten {
+                    out[cells].copy_from_slice(&rewritten);
+                    if folded.is_some() {
+                        report.folded += 1;
+                    } else {
+                        report.moves += 1;
+                    }
+                }
+            },
+
+            5 | 6 => {
+
+                let never = imm(0).map(|c| (c != 0) != (instr.opcode == 5)) == Some(true);
+                let to_next = imm(1) == Some(cells.end as isize);
+
+                // Reading a negative address faults, so must not be removed
+                let faults = isa::mode(op, 0) == 0 && args[0] < 0;
+
+                if (never || to_next) && !faults && report.relocatable {
+                    removed[cells].iter_mut().for_each(|r| *r = true);
+                    report.removed += 1;
+                }
+            },
+
+            _ => { },
+        }
+    }
+
+    if report.removed > 0 {
+        out = relocate(&out, &removed, &starts);
+    }
+
+    // Trailing zeroes match the padding, as long as the padded size is unchanged
+    if prog.len() <= MEM_SIZE {
+        let kept: Vec<usize> = (0..prog.len())
+            .filter(|&a| !removed[a])
+            .collect();
+        while out.last() == Some(&0) && layout.kind(kept[out.len() - 1]) != Kind::Code {
+            out.pop();
+        }
+    }
+
+    report.size = (prog.len(), out.len());
+
+    verify(prog, &out, tests)?;
+
+    Ok((out, report))
+}
+
+/// Whether every address used by a program can be found statically
+fn relocatable(prog: &[isize], layout: &Layout, starts: &[usize]) -> bool {
+
+    if layout.has_computed_jumps() {
+        return false;
+    }
+
+    for &addr in starts {
+
+        let op = prog[addr];
+        let instr = match isa::lookup(isa::opcode(op)) {
+            Some(instr) if op > 0 && addr + instr.size() <= prog.len() => instr,
+            _ => return false,
+        };
+
+        let overlaps = (addr + 1..addr + instr.size())
+            .any(|a| layout.is_instruction(a));
+        let relative = (0..instr.params.len())
+            .any(|i| isa::mode(op, i) == 2);
+        let modified = (addr..addr + instr.size())
+            .any(|a| layout.is_written(a));
+
+        if overlaps || relative || modified {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Removes cells from a program, adjusting addresses to match
+///
+/// Position mode parameters and immediate jump targets are relocated. An
+/// address within removed cells maps to whatever follows them.
+fn relocate(prog: &[isize], removed: &[bool], starts: &[usize]) -> Vec<isize> {
+
+    // New address of every old address, plus the end of the program
+    let mut map = vec![0; prog.len() + 1];
+    let mut next = 0;
+    for addr in 0..prog.len() {
+        map[addr] = next;
+        if !removed[addr] {
+            next += 1;
+        }
+    }
+    map[prog.len()] = next;
+
+    let reloc = |addr: isize| match usize::try_from(addr) {
+        Ok(a) if a <= prog.len() => map[a] as isize,
+        _ => addr,
+    };
+
+    let mut out = prog.to_vec();
+
+    for &addr in starts.iter().filter(|&&a| !removed[a]) {
+
+        let op = prog[addr];
+        let instr = isa::lookup(isa::opcode(op))
+            .unwrap();
+
+        for (i, &role) in instr.params.iter().enumerate() {
+            let cell = &mut out[addr + 1 + i];
+            let is_target = instr.is_jump() && i == 1 && role == Param::Read;
+            match isa::mode(op, i) {
+                0 if *cell < prog.len() as isize => *cell = reloc(*cell),
+                1 if is_target => *cell = reloc(*cell),
+                _ => { },
+            }
+        }
+    }
+
+    out.into_iter()
+        .zip(removed)
+        .filter(|(_, &r)| !r)
+        .map(|(v, _)| v)
+        .collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GRAV_PROG: &str = include_str!("grav-prog.txt");
+    const TEST_PROG: &str = include_str!("test-prog.txt");
+
+    #[test]
+    fn rewrites_in_place() {
+
+        // Uses relative mode, so nothing can be removed
+        let prog = [
+            109,0,
+            1101,2,3,17,
+            1002,17,1,18,
+            1,18,18,18,
+            204,18,
+            99,
+            0,0,
+        ];
+
+        let (out, report) = optimize(&prog, &[vec![]])
+            .unwrap();
+
+        assert_eq!(out, [
+            109,0,
+            1101,5,0,17,
+            1001,17,0,18,
+            1,18,18,18,
+            204,18,
+            99,
+        ]);
+        assert_eq!(report, Report {
+            folded: 1,
+            moves: 1,
+            removed: 0,
+            protected: 0,
+            relocatable: false,
+            size: (19, 17),
+        });
+    }
+
+    #[test]
+    fn removes_and_relocates() {
+
+        let prog = [
+            3,22,
+            1105,0,99,
+            1006,22,15,
+            1001,22,0,22,
+            1105,1,15,
+            102,1,22,23,
+            4,23,
+            99,
+            0,0,
+        ];
+
+        let (out, report) = optimize(&prog, &[vec![0], vec![7]])
+            .unwrap();
+
+        assert_eq!(out, [
+            3,12,
+            1006,12,5,
+            1001,12,0,13,
+            4,13,
+            99,
+        ]);
+        assert_eq!(report.removed, 3);
+        assert_eq!(report.moves, 1);
+        assert!(report.relocatable);
+    }
+
+    #[test]
+    fn protects_self_modified_code() {
+
+        // Writes the opcode of the instruction at 4 before running it
+        let prog = [1101,100,4,4,0,7,99];
+
+        let (out, report) = optimize(&prog, &[vec![]])
+            .unwrap();
+
+        assert_eq!(out, [1101,104,0,4,0,7,99]);
+        assert_eq!(report.folded, 1);
+        assert_eq!(report.protected, 1);
+        assert!(!report.relocatable);
+    }
+
+    #[test]
+    fn puzzle_programs() {
+
+        let grav: crate::program::Program = GRAV_PROG.parse()
+            .unwrap();
+        let (_, report) = optimize(grav.cells(), &[vec![]])
+            .unwrap();
+        assert!(report.protected > 0);
+
+        let diag: crate::program::Program = TEST_PROG.parse()
+            .unwrap();
+        let (out, _) = optimize(diag.cells(), &[vec![1], vec![5]])
+            .unwrap();
+        assert!(out.len() <= diag.len());
+    }
+
+    #[test]
+    fn detects_mismatch() {
+
+        let err = verify(&[104,1,99], &[104,2,99], &[vec![]])
+            .unwrap_err();
+
+        assert_eq!(err, OptError::Mismatch {
+            input: vec![],
+            expected: Ok(vec![1]),
+            actual: Ok(vec![2]),
+        });
+    }
+}
@@ -0,0 +1,534 @@
+//! Named, validated edits to program memory
+//!
+//! Each edit records the value a cell is expected to hold before it is
+//! changed, so applying a patch to the wrong program (or applying it twice)
+//! fails loudly instead of silently corrupting memory.
+//!
+//! Patches can also be described in text:
+//!
+//! ```text
+//! # Restore to state just before spontaneous combustion
+//! [alarm-1202]
+//! 1 = 0 -> 12
+//! 2 = 0 -> 2
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+
+/// Change to a single memory cell
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Edit {
+
+    /// Address of the cell
+    pub addr: usize,
+
+    /// Value the cell must hold before the edit
+    pub old: isize,
+
+    /// Value written to the cell
+    pub new: isize,
+}
+
+
+/// Error encountered while applying or composing patches
+#[derive(Debug, Eq, PartialEq)]
+pub enum PatchError {
+
+    /// Cell did not hold the expected value
+    Mismatch {
+        patch: String,
+        addr: usize,
+        expected: isize,
+        found: isize,
+    },
+
+    /// Cell is outside of memory
+    Address {
+        patch: String,
+        addr: usize,
+    },
+
+    /// Composed patches disagree about the value of a cell
+    Conflict {
+        patch: String,
+        addr: usize,
+    },
+
+    /// Patch edits the same cell more than once
+    Duplicate {
+        patch: String,
+        addr: usize,
+    },
+}
+
+impl fmt::Display for PatchError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            Self::Mismatch { patch, addr, expected, found } => write!(
+                f,
+                "patch {} expected [{}] = {}, found {}",
+                patch,
+                addr,
+                expected,
+                found,
+            ),
+            Self::Address { patch, addr } => write!(
+                f,
+                "patch {} edits [{}], which is outside of memory",
+                patch,
+                addr,
+            ),
+            Self::Conflict { patch, addr } => write!(
+                f,
+                "patch {} conflicts with an earlier edit of [{}]",
+                patch,
+                addr,
+            ),
+            Self::Duplicate { patch, addr } => write!(
+                f,
+                "patch {} edits [{}] twice",
+                patch,
+                addr,
+            ),
+        }
+    }
+}
+
+
+/// Named set of edits applied to program memory as a unit
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Patch {
+
+    /// Name shown in descriptions and error messages
+    name: String,
+
+    /// Edits making up the patch, in order of address
+    edits: Vec<Edit>,
+}
+
+impl Patch {
+
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            edits: Vec::new(),
+        }
+    }
+
+    /// Adds an edit which changes `addr` from `old` to `new`
+    ///
+    /// Fails if the patch already edits `addr`.
+    pub fn set(mut self, addr: usize, old: isize, new: isize) -> Result<Self, PatchError> {
+
+        if !self.insert(Edit { addr, old, new }) {
+            return Err(PatchError::Duplicate {
+                patch: self.name,
+                addr,
+            });
+        }
+
+        Ok(self)
+    }
+
+    /// Adds an edit, returning false if the patch already edits its address
+    fn insert(&mut self, edit: Edit) -> bool {
+
+        match self.edits.binary_search_by_key(&edit.addr, |e| e.addr) {
+            Ok(_) => false,
+            Err(idx) => {
+                self.edits.insert(idx, edit);
+                true
+            },
+        }
+    }
+
+    pub fn name(&self) -> &str {
+
+        &self.name
+    }
+
+    pub fn edits(&self) -> &[Edit] {
+
+        &self.edits
+    }
+
+    /// Describes the patch on a single line, for traces and status messages
+    pub fn summary(&self) -> String {
+
+        let edits: Vec<String> = self.edits.iter()
+            .map(|e| format!("[{}] {} -> {}", e.addr, e.old, e.new))
+            .collect();
+
+        format!("{}: {}", self.name, edits.join(", "))
+    }
+
+    /// Finds the edit made to `addr`, if any
+    pub fn edit_at(&self, addr: usize) -> Option<&Edit> {
+
+        self.edits.binary_search_by_key(&addr, |e| e.addr)
+            .ok()
+            .map(|idx| &self.edits[idx])
+    }
+
+    /// Combines this patch with one applied after it
+    ///
+    /// Where both patches edit the same cell, `next` must expect the value
+    /// written by this patch.
+    pub fn then(&self, next: &Patch) -> Result<Patch, PatchError> {
+
+        let mut res = Patch::new(&format!("{}+{}", self.name, next.name));
+        res.edits = self.edits.clone();
+
+        for edit in &next.edits {
+            match res.edits.binary_search_by_key(&edit.addr, |e| e.addr) {
+                Ok(idx) => {
+                    if res.edits[idx].new != edit.old {
+                        return Err(PatchError::Conflict {
+                            patch: next.name.clone(),
+                            addr: edit.addr,
+                        });
+                    }
+                    res.edits[idx].new = edit.new;
+                },
+                Err(idx) => res.edits.insert(idx, *edit),
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Checks whether this patch could be applied to `mem`
+    pub fn check(&self, mem: &[isize]) -> Result<(), PatchError> {
+
+        for edit in &self.edits {
+
+            let found = *mem.get(edit.addr)
+                .ok_or_else(|| PatchError::Address {
+                    patch: self.name.clone(),
+                    addr: edit.addr,
+                })?;
+
+            if found != edit.old {
+                return Err(PatchError::Mismatch {
+                    patch: self.name.clone(),
+                    addr: edit.addr,
+                    expected: edit.old,
+                    found,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies this patch to `mem`
+    ///
+    /// Memory is left untouched unless every edit is valid.
+    pub fn apply(&self, mem: &mut [isize]) -> Result<(), PatchError> {
+
+        self.check(mem)?;
+
+        for edit in &self.edits {
+            mem[edit.addr] = edit.new;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the patch which undoes this one
+    pub fn inverse(&self) -> Patch {
+
+        Patch {
+            name: format!("!{}", self.name),
+            edits: self.edits.iter()
+                .map(|e| Edit { addr: e.addr, old: e.new, new: e.old })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Patch {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        writeln!(f, "[{}]", self.name)?;
+
+        for edit in &self.edits {
+            writeln!(f, "{} = {} -> {}", edit.addr, edit.old, edit.new)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Error encountered while parsing a textual description of patches
+#[derive(Debug, Eq, PartialEq)]
+pub struct PatchParseError {
+
+    /// Line number (starting from 1) of the offending line
+    pub line: usize,
+
+    /// Offending line, with surrounding whitespace removed
+    pub text: String,
+}
+
+impl fmt::Display for PatchParseError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        write!(f, "invalid patch on line {}: {:?}", self.line, self.text)
+    }
+}
+
+
+/// Collection of named patches, typically loaded from a file
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PatchSet {
+    patches: Vec<Patch>,
+}
+
+impl PatchSet {
+
+    /// Finds the patch with the given name
+    pub fn get(&self, name: &str) -> Option<&Patch> {
+
+        self.patches.iter()
+            .find(|p| p.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Patch> {
+
+        self.patches.iter()
+    }
+
+    /// Finds the patch (if any) responsible for the value of `addr`
+    pub fn owner(&self, addr: usize) -> Option<&Patch> {
+
+        self.patches.iter()
+            .find(|p| p.edit_at(addr).is_some())
+    }
+}
+
+impl FromStr for PatchSet {
+    type Err = PatchParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+
+        let mut patches: Vec<Patch> = Vec::new();
+
+        for (i, line) in s.lines().enumerate() {
+
+            let text = match line.find('#') {
+                Some(idx) => &line[..idx],
+                None => line,
+            }.trim();
+
+            let err = || PatchParseError {
+                line: i + 1,
+                text: line.trim().to_owned(),
+            };
+
+            if text.is_empty() {
+                continue;
+            }
+
+            if text.starts_with('[') && text.ends_with(']') {
+                let name = text[1..text.len() - 1].trim();
+                if name.is_empty() || patches.iter().any(|p| p.name == name) {
+                    return Err(err());
+                }
+                patches.push(Patch::new(name));
+                continue;
+            }
+
+            let patch = patches.last_mut()
+                .ok_or_else(err)?;
+
+            let mut parts = text.splitn(2, '=');
+            let addr: usize = parts.next()
+                .and_then(|a| a.trim().parse().ok())
+                .ok_or_else(err)?;
+            let mut vals = parts.next()
+                .ok_or_else(err)?
+                .splitn(2, "->");
+            let old: isize = vals.next()
+                .and_then(|v| v.trim().parse().ok())
+                .ok_or_else(err)?;
+            let new: isize = vals.next()
+                .and_then(|v| v.trim().parse().ok())
+                .ok_or_else(err)?;
+
+            if !patch.insert(Edit { addr, old, new }) {
+                return Err(err());
+            }
+        }
+
+        Ok(Self { patches })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GRAV_PROG: &str = include_str!("grav-prog.txt");
+
+    #[test]
+    fn day2_part1_sln() {
+
+        let mut prog = crate::parse_prog(GRAV_PROG)
+            .unwrap();
+
+        Patch::new("alarm-1202")
+            .set(1, 0, 12)
+            .and_then(|p| p.set(2, 0, 2))
+            .and_then(|p| p.apply(&mut prog))
+            .unwrap();
+
+        crate::eval(&mut prog)
+            .unwrap();
+
+        assert_eq!(prog[0], 10566835);
+    }
+
+    #[test]
+    fn mismatch() {
+
+        let mut mem = [1, 2, 3];
+
+        let err = Patch::new("bad")
+            .set(0, 1, 5)
+            .and_then(|p| p.set(2, 4, 5))
+            .and_then(|p| p.apply(&mut mem))
+            .unwrap_err();
+
+        assert_eq!(err, PatchError::Mismatch {
+            patch: "bad".to_owned(),
+            addr: 2,
+            expected: 4,
+            found: 3,
+        });
+        assert_eq!(err.to_string(), "patch bad expected [2] = 4, found 3");
+
+        // Nothing applied
+        assert_eq!(mem, [1, 2, 3]);
+    }
+
+    #[test]
+    fn out_of_bounds() {
+
+        let err = Patch::new("far")
+            .set(10, 0, 1)
+            .and_then(|p| p.apply(&mut [0; 3]))
+            .unwrap_err();
+
+        assert!(matches!(err, PatchError::Address { addr: 10, .. }));
+    }
+
+    #[test]
+    fn duplicate() {
+
+        let err = Patch::new("twice")
+            .set(1, 0, 1)
+            .and_then(|p| p.set(1, 1, 2))
+            .unwrap_err();
+
+        assert_eq!(err, PatchError::Duplicate {
+            patch: "twice".to_owned(),
+            addr: 1,
+        });
+        assert_eq!(err.to_string(), "patch twice edits [1] twice");
+    }
+
+    #[test]
+    fn compose() {
+
+        let a = Patch::new("a")
+            .set(0, 0, 1)
+            .and_then(|p| p.set(1, 0, 1))
+            .unwrap();
+        let b = Patch::new("b")
+            .set(1, 1, 2)
+            .and_then(|p| p.set(2, 0, 2))
+            .unwrap();
+
+        let ab = a.then(&b)
+            .unwrap();
+
+        assert_eq!(ab.name(), "a+b");
+        assert_eq!(ab.edits(), [
+            Edit { addr: 0, old: 0, new: 1 },
+            Edit { addr: 1, old: 0, new: 2 },
+            Edit { addr: 2, old: 0, new: 2 },
+        ]);
+
+        let mut mem = [0, 0, 0];
+        ab.apply(&mut mem)
+            .unwrap();
+        assert_eq!(mem, [1, 2, 2]);
+
+        ab.inverse()
+            .apply(&mut mem)
+            .unwrap();
+        assert_eq!(mem, [0, 0, 0]);
+
+        assert!(matches!(b.then(&a), Err(PatchError::Conflict { addr: 1, .. })));
+    }
+
+    #[test]
+    fn parse() {
+
+        let src = "
+            # Restore to state just before spontaneous combustion
+            [alarm-1202]
+            1 = 0 -> 12
+            2 = 0 -> 2   # verb
+
+            [free-play]
+            0 = 1 -> 2
+        ";
+
+        let set: PatchSet = src.parse()
+            .unwrap();
+
+        let alarm = Patch::new("alarm-1202")
+            .set(1, 0, 12)
+            .and_then(|p| p.set(2, 0, 2))
+            .unwrap();
+        assert_eq!(set.get("alarm-1202"), Some(&alarm));
+        assert_eq!(set.owner(0).map(Patch::name), Some("free-play"));
+        assert_eq!(set.iter().count(), 2);
+
+        // Round trip through Display
+        let text: String = set.iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(text.parse::<PatchSet>().unwrap(), set);
+    }
+
+    #[test]
+    fn parse_errors() {
+
+        let err = "1 = 0 -> 1".parse::<PatchSet>()
+            .unwrap_err();
+        assert_eq!(err.line, 1);
+
+        let err = "[a]\n1 = 0 -> x".parse::<PatchSet>()
+            .unwrap_err();
+        assert_eq!(err.line, 2);
+
+        let err = "[a]\n1 = 0 -> 1\n1 = 1 -> 2".parse::<PatchSet>()
+            .unwrap_err();
+        assert_eq!(err.line, 3);
+
+        let err = "[a]\n[a]".parse::<PatchSet>()
+            .unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
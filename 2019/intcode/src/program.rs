@@ -0,0 +1,361 @@
+//! Loading of intcode programs from text
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::num::ParseIntError;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[cfg(feature = "std")]
+use crate::image::{Image, ImageError};
+
+
+/// Minimum size of working memory handed to a program
+pub const MEM_SIZE: usize = 10_000;
+
+
+/// Reason a token could not be parsed
+#[derive(Debug)]
+pub enum ParseErrorKind {
+
+    /// Token was empty (e.g. two consecutive commas)
+    Empty,
+
+    /// Token was not a valid integer
+    Int(ParseIntError),
+}
+
+
+/// Error encountered while parsing the text of a program
+#[derive(Debug)]
+pub struct ParseError {
+
+    /// Index of the offending cell
+    pub index: usize,
+
+    /// Byte offset of the offending token within the source text
+    pub offset: usize,
+
+    /// Offending token, with surrounding whitespace removed
+    pub token: String,
+
+    /// Reason the token was rejected
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match &self.kind {
+            ParseErrorKind::Empty => write!(
+                f,
+                "empty value for cell {} at byte {}",
+                self.index,
+                self.offset,
+            ),
+            ParseErrorKind::Int(err) => write!(
+                f,
+                "invalid value {:?} for cell {} at byte {}: {}",
+                self.token,
+                self.index,
+                self.offset,
+                err,
+            ),
+        }
+    }
+}
+
+
+/// Error encountered while loading a program
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum LoadError {
+
+    /// Source could not be read
+    Io(io::Error),
+
+    /// Source could not be parsed
+    Parse(ParseError),
+
+    /// Source is an image which could not be decoded
+    Image(ImageError),
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<ParseError> for LoadError {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<ImageError> for LoadError {
+    fn from(err: ImageError) -> Self {
+        Self::Image(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for LoadError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            Self::Io(err) => write!(f, "failed to read program: {}", err),
+            Self::Parse(err) => write!(f, "failed to parse program: {}", err),
+            Self::Image(err) => write!(f, "failed to decode program: {}", err),
+        }
+    }
+}
+
+
+/// An intcode program, as written in its source
+///
+/// The source format is a comma-separated list of integers. Whitespace
+/// (including newlines) around each value is ignored, a single trailing comma
+/// is permitted, and `#` starts a comment which runs to the end of the line.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Program {
+
+    /// Cells of the program, without any padding
+    cells: Vec<isize>,
+}
+
+impl Program {
+
+    /// Loads a program from the file at `path`
+    ///
+    /// The file may hold either text or an image (see `image`).
+    #[cfg(feature = "std")]
+    pub fn load<P>(path: P) -> Result<Self, LoadError>
+    where P: AsRef<Path>
+    {
+        Self::from_reader(File::open(path)?)
+    }
+
+    /// Loads a program, as text or an image, from any reader
+    #[cfg(feature = "std")]
+    pub fn from_reader<R>(reader: R) -> Result<Self, LoadError>
+    where R: Read
+    {
+        Ok(Image::from_reader(reader)?.into())
+    }
+
+    /// Number of cells in the program as written
+    pub fn len(&self) -> usize {
+
+        self.cells.len()
+    }
+
+    /// Whether the program has no cells
+    pub fn is_empty(&self) -> bool {
+
+        self.cells.is_empty()
+    }
+
+    /// Cells of the program as written
+    pub fn cells(&self) -> &[isize] {
+
+        &self.cells
+    }
+
+    /// Creates working memory for the program
+    ///
+    /// Memory holds the program followed by zeroes, and is at least
+    /// `MEM_SIZE` cells long.
+    pub fn memory(&self) -> Vec<isize> {
+
+        let mut mem = self.cells.clone();
+        mem.resize(self.cells.len().max(MEM_SIZE), 0);
+        mem
+    }
+}
+
+impl fmt::Display for Program {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        for (i, cell) in self.cells.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", cell)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Vec<isize>> for Program {
+    fn from(cells: Vec<isize>) -> Self {
+        Self { cells }
+    }
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+
+        // Blank out comments, keeping byte offsets intact
+        let mut src = String::with_capacity(s.len());
+        for line in s.split_inclusive('\n') {
+            match line.find('#') {
+                Some(idx) => {
+                    let comment = line[idx..].trim_end_matches('\n');
+                    src.push_str(&line[..idx]);
+                    src.extend(core::iter::repeat_n(' ', comment.len()));
+                    src.push_str(&line[idx + comment.len()..]);
+                },
+                None => src.push_str(line),
+            }
+        }
+
+        let tokens: Vec<(usize, &str)> = src.split(',')
+            .scan(0, |offset, tok| {
+                let start = *offset;
+                *offset += tok.len() + 1;
+                Some((start, tok))
+            })
+            .collect();
+
+        let mut cells = Vec::with_capacity(tokens.len());
+
+        for (index, &(start, tok)) in tokens.iter().enumerate() {
+
+            let trimmed = tok.trim();
+            let offset = start + (tok.len() - tok.trim_start().len());
+
+            if trimmed.is_empty() {
+
+                // Trailing comma (or empty source) is fine
+                if index == tokens.len() - 1 {
+                    break;
+                }
+
+                return Err(ParseError {
+                    index,
+                    offset,
+                    token: String::new(),
+                    kind: ParseErrorKind::Empty,
+                });
+            }
+
+            let val = trimmed.parse()
+                .map_err(|err| ParseError {
+                    index,
+                    offset,
+                    token: trimmed.to_owned(),
+                    kind: ParseErrorKind::Int(err),
+                })?;
+
+            cells.push(val);
+        }
+
+        Ok(Self { cells })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn simple() {
+
+        let prog: Program = "1,9,10,3,2,3,11,0,99,30,40,50".parse()
+            .unwrap();
+
+        assert_eq!(prog.len(), 12);
+        assert_eq!(prog.cells()[..4], [1, 9, 10, 3]);
+        assert_eq!(prog.memory().len(), MEM_SIZE);
+        assert_eq!(prog.to_string(), "1,9,10,3,2,3,11,0,99,30,40,50");
+    }
+
+    #[test]
+    fn comments_and_whitespace() {
+
+        let src = "\
+            # day 5 echo program\n\
+            3,0,   # read\n\
+            4,0,   # write\n\
+            99,\n\
+        ";
+
+        let prog: Program = src.parse()
+            .unwrap();
+
+        assert_eq!(prog.cells(), [3, 0, 4, 0, 99]);
+    }
+
+    #[test]
+    fn bad_token_position() {
+
+        let err = "1,2,\n  x3,4".parse::<Program>()
+            .unwrap_err();
+
+        assert_eq!(err.index, 2);
+        assert_eq!(err.offset, 7);
+        assert_eq!(err.token, "x3");
+        assert!(matches!(err.kind, ParseErrorKind::Int(_)));
+    }
+
+    #[test]
+    fn empty_token() {
+
+        let err = "1,,2".parse::<Program>()
+            .unwrap_err();
+
+        assert_eq!(err.index, 1);
+        assert_eq!(err.offset, 2);
+        assert!(matches!(err.kind, ParseErrorKind::Empty));
+    }
+
+    #[test]
+    fn offset_after_comment() {
+
+        let err = "1, # ünïcödé\n?".parse::<Program>()
+            .unwrap_err();
+
+        assert_eq!(err.index, 1);
+        assert_eq!(&"1, # ünïcödé\n?"[err.offset..], "?");
+    }
+
+    #[test]
+    fn large_program() {
+
+        let src = vec!["0"; MEM_SIZE + 5].join(",");
+
+        let prog: Program = src.parse()
+            .unwrap();
+
+        assert_eq!(prog.len(), MEM_SIZE + 5);
+        assert_eq!(prog.memory().len(), MEM_SIZE + 5);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_reader() {
+
+        let prog = Program::from_reader("104,7,99\n".as_bytes())
+            .unwrap();
+
+        assert_eq!(prog.cells(), [104, 7, 99]);
+    }
+}
@@ -0,0 +1,619 @@
+//! Interactive exploration of an intcode machine
+//!
+//! Each line given to a `Repl` is either an instruction or a command.
+//! Instructions, written as raw integers (`1101,2,3,100`) or as assembly
+//! (`add 2, 3, [100]`), are stored at the instruction pointer and executed
+//! immediately. Commands start with `:`; see `HELP` for the full list.
+//!
+//! Everything runs on a regular `Computer`, so programs behave exactly as they
+//! do in solutions.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::fs;
+use std::io;
+use std::ops::Range;
+
+use crate::{Computer, Error, InputError, IoHandler};
+use crate::isa;
+use crate::patch::{Patch, PatchError, PatchParseError, PatchSet};
+use crate::program::{LoadError, Program, MEM_SIZE};
+
+
+/// Summary of available commands
+pub const HELP: &str = "\
+<ints> | <asm>        store an instruction at ip and execute it
+:load <path>          load a program and reset registers
+:run                  run until an output, a halt or missing input
+:step [n]             execute n instructions (default 1)
+:input <ints>         queue values for input instructions
+:mem <addr> [len]     show memory
+:set <addr> <ints>    write memory
+:patch [path name]    apply a named patch from a file, or list applied ones
+:dis [addr] [n]       disassemble n instructions (default at ip)
+:regs                 show registers
+:ip <addr>            set instruction pointer
+:rb <val>             set relative base
+:undo                 revert the last command
+:help                 show this message
+:quit                 exit
+";
+
+
+/// Most instructions `:run` executes before giving up
+const RUN_LIMIT: usize = 1_000_000;
+
+/// Number of commands which can be undone
+const UNDO_DEPTH: usize = 100;
+
+
+/// Reason a line could not be executed
+#[derive(Debug)]
+pub enum ReplError {
+
+    /// Line is not a valid instruction or command
+    Syntax(String),
+
+    /// Program file could not be loaded
+    Load(LoadError),
+
+    /// Patch file could not be read
+    PatchFile(io::Error),
+
+    /// Patch file could not be parsed
+    PatchParse(PatchParseError),
+
+    /// Patch file has no patch with the given name
+    NoPatch(String),
+
+    /// Patch could not be applied to memory
+    Patch(PatchError),
+
+    /// Machine failed while executing
+    Machine(Error),
+
+    /// Input instruction reached with no queued input
+    NoInput,
+
+    /// No earlier state to return to
+    NothingToUndo,
+}
+
+impl From<Error> for ReplError {
+    fn from(err: Error) -> Self {
+        Self::Machine(err)
+    }
+}
+
+impl From<LoadError> for ReplError {
+    fn from(err: LoadError) -> Self {
+        Self::Load(err)
+    }
+}
+
+impl From<PatchError> for ReplError {
+    fn from(err: PatchError) -> Self {
+        Self::Patch(err)
+    }
+}
+
+impl fmt::Display for ReplError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match self {
+            Self::Syntax(line) => write!(f, "cannot parse {:?}", line),
+            Self::Load(err) => write!(f, "{}", err),
+            Self::PatchFile(err) => write!(f, "failed to read patches: {}", err),
+            Self::PatchParse(err) => write!(f, "{}", err),
+            Self::NoPatch(name) => write!(f, "no patch named {}", name),
+            Self::Patch(err) => write!(f, "{}", err),
+            Self::Machine(err) => write!(f, "machine error: {:?}", err),
+            Self::NoInput => write!(f, "waiting for input (use :input)"),
+            Self::NothingToUndo => write!(f, "nothing to undo"),
+        }
+    }
+}
+
+
+/// I/O handler fed from a queue, collecting outputs
+#[derive(Clone, Default)]
+struct ReplIo {
+    input: VecDeque<isize>,
+    output: Vec<isize>,
+}
+
+impl IoHandler for ReplIo {
+
+    fn input(&mut self) -> Result<isize, InputError> {
+
+        self.input.pop_front()
+            .ok_or(InputError::Unavailable)
+    }
+
+    fn output(&mut self, val: isize) {
+
+        self.output.push(val);
+    }
+}
+
+
+/// State which can be restored by `:undo`
+struct Snapshot {
+    mem: Vec<isize>,
+    ip: usize,
+    rb: isize,
+    halted: bool,
+    input: VecDeque<isize>,
+    patches: Vec<Patch>,
+}
+
+
+/// Why execution stopped
+enum Stop {
+    Output,
+    Halt,
+    Steps,
+}
+
+
+/// Machine driven by lines of text
+pub struct Repl {
+
+    /// Main memory
+    mem: Vec<isize>,
+
+    /// Computer holding the registers between commands
+    computer: Computer<ReplIo>,
+
+    /// States before each undoable command, most recent last
+    history: Vec<Snapshot>,
+
+    /// Patches applied since the program was loaded, oldest first
+    patches: Vec<Patch>,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+
+    /// Creates a REPL with zeroed memory
+    pub fn new() -> Self {
+        Self {
+            mem: vec![0; MEM_SIZE],
+            computer: Computer::new(ReplIo::default()),
+            history: Vec::new(),
+            patches: Vec::new(),
+        }
+    }
+
+    /// Main memory
+    pub fn mem(&self) -> &[isize] {
+
+        &self.mem
+    }
+
+    /// Executes a single line, returning the text to show for it
+    pub fn exec(&mut self, line: &str) -> Result<String, ReplError> {
+
+        let line = line.trim();
+        let syntax = || ReplError::Syntax(line.to_owned());
+
+        if line.is_empty() {
+            return Ok(String::new());
+        }
+
+        let (cmd, args) = match line.strip_prefix(':') {
+            Some(rest) => {
+                let mut parts = rest.splitn(2, char::is_whitespace);
+                (parts.next().unwrap(), parts.next().unwrap_or("").trim())
+            },
+            None => return self.undoable(|r| r.instruction(line)),
+        };
+
+        match cmd {
+            "help" => Ok(HELP.to_owned()),
+            "regs" => Ok(self.regs()),
+            "undo" => {
+                let snap = self.history.pop()
+                    .ok_or(ReplError::NothingToUndo)?;
+                self.restore(snap);
+                Ok(self.regs())
+            },
+            "mem" => {
+                let vals = ints(args).ok_or_else(syntax)?;
+                let (addr, len) = match vals[..] {
+                    [addr] => (addr, 8),
+                    [addr, len] => (addr, len),
+                    _ => return Err(syntax()),
+                };
+                let range = addr_range(addr, len).ok_or(Error::Address)?;
+                self.dump_mem(range)
+            },
+            "dis" => {
+                let vals = ints(args).ok_or_else(syntax)?;
+                let (addr, count) = match vals[..] {
+                    [] => (self.computer.ip() as isize, 8),
+                    [addr] => (addr, 8),
+                    [addr, count] => (addr, count),
+                    _ => return Err(syntax()),
+                };
+                Ok(self.disassemble(addr, count))
+            },
+            "load" if !args.is_empty() => self.undoable(|r| {
+                let prog = Program::load(args)?;
+                r.mem = prog.memory();
+                r.computer.reset();
+                r.patches.clear();
+                Ok(format!("loaded {} cells\n", prog.len()))
+            }),
+            "input" => {
+                let vals = ints(args).ok_or_else(syntax)?;
+                self.undoable(|r| {
+                    r.computer.io_mut().input.extend(vals);
+                    Ok(String::new())
+                })
+            },
+            "set" => {
+                let vals = ints(args).ok_or_else(syntax)?;
+                let (&addr, vals) = vals.split_first().ok_or_else(syntax)?;
+                self.undoable(|r| {
+                    let range = addr_range(addr, vals.len() as isize)
+                        .ok_or(Error::Address)?;
+                    r.computer.attach(&mut r.mem)
+                        .write_range(range.start, vals)?;
+                    Ok(String::new())
+                })
+            },
+            "patch" if args.is_empty() => Ok(self.patches.iter()
+                .map(|p| format!("{}\n", p.summary()))
+                .collect()),
+            "patch" => {
+                let mut parts = args.rsplitn(2, char::is_whitespace);
+                let name = parts.next()
+                    .unwrap();
+                let path = parts.next()
+                    .ok_or_else(syntax)?
+                    .trim();
+                let src = fs::read_to_string(path)
+                    .map_err(ReplError::PatchFile)?;
+                let set: PatchSet = src.parse()
+                    .map_err(ReplError::PatchParse)?;
+                let patch = set.get(name)
+                    .ok_or_else(|| ReplError::NoPatch(name.to_owned()))?;
+                self.undoable(|r| {
+                    r.patch(patch)?;
+                    Ok(format!("{}\n", patch.summary()))
+                })
+            },
+            "ip" | "rb" => {
+                let val = match ints(args).as_deref() {
+                    Some(&[val]) => val,
+                    _ => return Err(syntax()),
+                };
+                self.undoable(|r| {
+                    if cmd == "rb" {
+                        r.computer.set_rb(val);
+                    } else {
+                        let ip = addr_range(val, 0).ok_or(Error::Address)?;
+                        r.computer.set_ip(ip.start);
+                    }
+                    Ok(r.regs())
+                })
+            },
+            "step" => {
+                let count = match ints(args).as_deref() {
+                    Some(&[]) => 1,
+                    Some(&[n]) if n > 0 => n as usize,
+                    _ => return Err(syntax()),
+                };
+                self.undoable(|r| r.run(count, false))
+            },
+            "run" => self.undoable(|r| r.run(RUN_LIMIT, true)),
+            _ => Err(syntax()),
+        }
+    }
+
+    /// Runs `f`, restoring the state beforehand if it fails and otherwise
+    /// recording it for `:undo`
+    fn undoable<F>(&mut self, f: F) -> Result<String, ReplError>
+    where F: FnOnce(&mut Self) -> Result<String, ReplError>
+    {
+        let snap = Snapshot {
+            mem: self.mem.clone(),
+            ip: self.computer.ip(),
+            rb: self.computer.rb(),
+            halted: self.computer.is_halted(),
+            input: self.computer.io().input.clone(),
+            patches: self.patches.clone(),
+        };
+
+        match f(self) {
+            Ok(text) => {
+                if self.history.len() == UNDO_DEPTH {
+                    self.history.remove(0);
+                }
+                self.history.push(snap);
+                Ok(text)
+            },
+            Err(err) => {
+                self.restore(snap);
+                Err(err)
+            },
+        }
+    }
+
+    fn restore(&mut self, snap: Snapshot) {
+
+        self.mem = snap.mem;
+        self.computer.set_ip(snap.ip);
+        self.computer.set_rb(snap.rb);
+        self.computer.set_halted(snap.halted);
+        self.computer.io_mut().input = snap.input;
+        self.patches = snap.patches;
+    }
+
+    /// Applies a patch to memory, remembering it for `:patch`
+    pub fn patch(&mut self, patch: &Patch) -> Result<(), ReplError> {
+
+        patch.apply(&mut self.mem)?;
+        self.patches.push(patch.clone());
+
+        Ok(())
+    }
+
+    /// Stores an instruction at the instruction pointer and executes it
+    fn instruction(&mut self, line: &str) -> Result<String, ReplError> {
+
+        let cells = match ints(line) {
+            Some(cells) if !cells.is_empty() => cells,
+            _ => isa::assemble(line)
+                .ok_or_else(|| ReplError::Syntax(line.to_owned()))?,
+        };
+
+        let ip = self.computer.ip();
+        self.computer.set_ip(ip);
+        self.computer.attach(&mut self.mem)
+            .write_range(ip, &cells)?;
+
+        self.run(1, false)
+    }
+
+    /// Executes up to `limit` instructions, stopping early at a halt, at an
+    /// input with nothing queued, or (if `until_output` is set) after an output
+    fn run(&mut self, limit: usize, until_output: bool) -> Result<String, ReplError> {
+
+        let mut steps = 0;
+
+        let stop = {
+            let mut session = self.computer.attach(&mut self.mem);
+            loop {
+                if session.is_halted() {
+                    break Stop::Halt;
+                }
+                if steps == limit {
+                    break Stop::Steps;
+                }
+                let outputs = session.io().output.len();
+                match session.step() {
+                    Err(Error::Input(InputError::Unavailable)) if steps == 0 => {
+                        return Err(ReplError::NoInput);
+                    },
+                    Err(Error::Input(InputError::Unavailable)) => break Stop::Steps,
+                    res => res?,
+                };
+                steps += 1;
+                if until_output && session.io().output.len() > outputs {
+                    break Stop::Output;
+                }
+            }
+        };
+
+        let mut text = String::new();
+        for val in self.computer.io_mut().output.drain(..) {
+            writeln!(text, "out: {}", val).unwrap();
+        }
+        if let Stop::Halt = stop {
+            text.push_str("halted\n");
+        }
+        text.push_str(&self.regs());
+
+        Ok(text)
+    }
+
+    fn regs(&self) -> String {
+
+        let next = isa::disassemble(&self.mem, self.computer.ip())
+            .map(|(text, _)| text)
+            .unwrap_or_else(|| "?".to_owned());
+
+        format!(
+            "ip={} rb={}{}  next: {}\n",
+            self.computer.ip(),
+            self.computer.rb(),
+            if self.computer.is_halted() { " (halted)" } else { "" },
+            next,
+        )
+    }
+
+    fn dump_mem(&self, range: Range<usize>) -> Result<String, ReplError> {
+
+        let cells = self.mem.get(range.clone())
+            .ok_or(Error::Address)?;
+
+        let mut text = String::new();
+        for (i, chunk) in cells.chunks(8).enumerate() {
+            let vals: Vec<String> = chunk.iter()
+                .map(|v| v.to_string())
+                .collect();
+            writeln!(text, "{:>6}  {}", range.start + i * 8, vals.join(" ")).unwrap();
+        }
+
+        Ok(text)
+    }
+
+    fn disassemble(&self, addr: isize, count: isize) -> String {
+
+        let mut text = String::new();
+        let mut addr = addr.max(0) as usize;
+
+        for _ in 0..count {
+            let (line, size) = isa::disassemble(&self.mem, addr)
+                .unwrap_or_else(|| {
+                    let val = self.mem.get(addr).cloned().unwrap_or(0);
+                    (format!("{} ?", val), 1)
+                });
+            let marker = if addr == self.computer.ip() { ">" } else { " " };
+            writeln!(text, "{}{:>5}  {}", marker, addr, line).unwrap();
+            addr += size;
+        }
+
+        text
+    }
+}
+
+
+/// Parses integers separated by commas and/or whitespace
+fn ints(s: &str) -> Option<Vec<isize>> {
+
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.parse().ok())
+        .collect()
+}
+
+/// Converts a user-supplied start address and length into a range
+fn addr_range(addr: isize, len: isize) -> Option<Range<usize>> {
+
+    if addr < 0 || len < 0 {
+        return None;
+    }
+
+    let end = addr.checked_add(len)?;
+
+    Some(addr as usize..end as usize)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn exec(repl: &mut Repl, line: &str) -> String {
+
+        repl.exec(line)
+            .unwrap_or_else(|err| panic!("{}: {}", line, err))
+    }
+
+    #[test]
+    fn instructions() {
+
+        let mut repl = Repl::new();
+
+        exec(&mut repl, "1101,2,3,100");
+        exec(&mut repl, "mul [100], 7, [101]");
+        assert_eq!(repl.mem()[100..102], [5, 35]);
+
+        assert_eq!(exec(&mut repl, "out [101]"), "out: 35\nip=10 rb=0  next: ?\n");
+        assert_eq!(exec(&mut repl, "arb -4"), "ip=12 rb=-4  next: ?\n");
+        assert_eq!(exec(&mut repl, "99"), "halted\nip=12 rb=-4 (halted)  next: hlt\n");
+
+        assert!(matches!(repl.exec("bogus 1"), Err(ReplError::Syntax(_))));
+        assert!(matches!(repl.exec("in [0]"), Err(ReplError::NoInput)));
+    }
+
+    #[test]
+    fn memory_and_undo() {
+
+        let mut repl = Repl::new();
+
+        exec(&mut repl, ":set 4 10 20 30");
+        assert_eq!(exec(&mut repl, ":mem 3 4"), "     3  0 10 20 30\n");
+
+        exec(&mut repl, "add [4], [5], [4]");
+        assert_eq!(repl.mem()[4], 30);
+
+        exec(&mut repl, ":undo");
+        assert_eq!(repl.mem()[..7], [0, 0, 0, 0, 10, 20, 30]);
+        assert_eq!(exec(&mut repl, ":regs"), "ip=0 rb=0  next: ?\n");
+
+        exec(&mut repl, ":undo");
+        assert_eq!(repl.mem()[4], 0);
+        assert!(matches!(repl.exec(":undo"), Err(ReplError::NothingToUndo)));
+
+        // Failed commands leave no trace
+        assert!(matches!(repl.exec(":set 9999 1 2"), Err(ReplError::Machine(Error::Address))));
+        assert!(matches!(repl.exec(":undo"), Err(ReplError::NothingToUndo)));
+
+        assert!(matches!(
+            repl.exec(":mem 1 9223372036854775807"),
+            Err(ReplError::Machine(Error::Address)),
+        ));
+    }
+
+    #[test]
+    fn patches() {
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/grav-prog.txt");
+        let patches = concat!(env!("CARGO_MANIFEST_DIR"), "/../02/src/patches.txt");
+
+        let mut repl = Repl::new();
+        exec(&mut repl, &format!(":load {}", path));
+
+        assert_eq!(
+            exec(&mut repl, &format!(":patch {} alarm-1202", patches)),
+            "alarm-1202: [1] 0 -> 12, [2] 0 -> 2\n",
+        );
+        assert_eq!(repl.mem()[..3], [1, 12, 2]);
+        assert_eq!(exec(&mut repl, ":patch"), "alarm-1202: [1] 0 -> 12, [2] 0 -> 2\n");
+
+        // Applying it twice fails, naming the patch
+        let err = repl.exec(&format!(":patch {} alarm-1202", patches))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "patch alarm-1202 expected [1] = 0, found 12");
+        assert!(matches!(
+            repl.exec(&format!(":patch {} bogus", patches)),
+            Err(ReplError::NoPatch(_)),
+        ));
+
+        exec(&mut repl, ":undo");
+        assert_eq!(repl.mem()[..3], [1, 0, 0]);
+        assert_eq!(exec(&mut repl, ":patch"), "");
+    }
+
+    #[test]
+    fn run_program() {
+
+        let mut repl = Repl::new();
+
+        // Doubles each input until given 0
+        exec(&mut repl, ":set 0 3,20,1006,20,14,102,2,20,20,4,20,1105,1,0,99");
+
+        assert_eq!(exec(&mut repl, ":dis 0 3"), "\
+>    0  in [20]
+     2  jf [20], 14
+     5  mul 2, [20], [20]
+");
+
+        assert!(matches!(repl.exec(":run"), Err(ReplError::NoInput)));
+
+        exec(&mut repl, ":input 4 21");
+        assert!(exec(&mut repl, ":run").starts_with("out: 8\n"));
+        assert!(exec(&mut repl, ":run").starts_with("out: 42\n"));
+        assert_eq!(exec(&mut repl, ":run"), "ip=0 rb=0  next: in [20]\n");
+
+        exec(&mut repl, ":input 0");
+        assert_eq!(exec(&mut repl, ":run"), "halted\nip=14 rb=0 (halted)  next: hlt\n");
+
+        // Undoing a command issued after halting leaves the program halted
+        exec(&mut repl, ":set 30 1");
+        assert_eq!(exec(&mut repl, ":undo"), "ip=14 rb=0 (halted)  next: hlt\n");
+
+        exec(&mut repl, ":undo");
+        assert_eq!(exec(&mut repl, ":step 2"), "ip=14 rb=0  next: hlt\n");
+    }
+}
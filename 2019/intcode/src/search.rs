@@ -0,0 +1,337 @@
+//! Exploration of the inputs a program can be given
+//!
+//! Starting from a machine paused on an input instruction, each candidate move
+//! is tried on a fork of the machine. The output produced in response is
+//! judged by the caller, who decides whether the goal has been reached, the
+//! state is a dead end, or it should be explored further. States already seen
+//! (by a hash of memory and registers) are skipped.
+
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use crate::Error;
+use crate::machine::Machine;
+
+
+/// What a search should do with a newly reached state
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verdict {
+
+    /// State is what we were looking for
+    Goal,
+
+    /// State leads nowhere and is not explored further
+    Prune,
+
+    /// State should be explored, lower scores first when searching best-first
+    Explore(isize),
+}
+
+
+/// Order in which states are explored
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Order {
+
+    /// Fewest moves first, so the goal is reached by a shortest sequence
+    BreadthFirst,
+
+    /// Lowest score first, ties broken by discovery order
+    BestFirst,
+}
+
+
+/// Successful result of a search
+#[derive(Clone, Debug)]
+pub struct Found {
+
+    /// Indices of the candidate moves made, in order
+    pub moves: Vec<usize>,
+
+    /// Every value input to reach the goal, in order
+    pub inputs: Vec<isize>,
+
+    /// Machine as it was when the goal was reached
+    pub machine: Machine,
+
+    /// Number of distinct states reached
+    pub explored: usize,
+}
+
+
+/// States waiting to be expanded, in the order of the search
+enum Frontier {
+    Queue(VecDeque<(usize, Machine)>),
+    Heap(BinaryHeap<Reverse<(isize, usize)>>, Vec<Option<Machine>>),
+}
+
+impl Frontier {
+
+    fn push(&mut self, node: usize, score: isize, machine: Machine) {
+
+        match self {
+            Self::Queue(queue) => queue.push_back((node, machine)),
+            Self::Heap(heap, machines) => {
+                machines.resize(machines.len().max(node + 1), None);
+                machines[node] = Some(machine);
+                heap.push(Reverse((score, node)));
+            },
+        }
+    }
+
+    fn pop(&mut self) -> Option<(usize, Machine)> {
+
+        match self {
+            Self::Queue(queue) => queue.pop_front(),
+            Self::Heap(heap, machines) => heap.pop()
+                .map(|Reverse((_, node))| (node, machines[node].take().unwrap())),
+        }
+    }
+}
+
+
+/// Configuration of a search
+#[derive(Clone, Debug)]
+pub struct Search<'a> {
+
+    /// Moves tried from every state, each one or more input values
+    candidates: &'a [Vec<isize>],
+
+    order: Order,
+
+    /// Most distinct states to reach before giving up
+    limit: usize,
+}
+
+impl<'a> Search<'a> {
+
+    /// Prepares a breadth-first search trying `candidates` from every state
+    pub fn new(candidates: &'a [Vec<isize>]) -> Self {
+        Self {
+            candidates,
+            order: Order::BreadthFirst,
+            limit: usize::MAX,
+        }
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+
+        self.order = order;
+        self
+    }
+
+    /// Gives up once `limit` distinct states have been reached
+    pub fn limit(mut self, limit: usize) -> Self {
+
+        self.limit = limit;
+        self
+    }
+
+    /// Searches for a sequence of moves leading from `start` to the goal
+    ///
+    /// `start` is first run until it needs input; anything it outputs before
+    /// then is not judged. After each move, `judge` is given the resulting
+    /// machine and the values it output in response. Moves which make the
+    /// machine fail (e.g. on an invalid instruction) are pruned. Returns
+    /// `None` if every reachable state was explored (or the limit was hit)
+    /// without reaching the goal.
+    pub fn run<F>(&self, start: &Machine, mut judge: F) -> Result<Option<Found>, Error>
+    where F: FnMut(&Machine, &[isize]) -> Verdict
+    {
+        let mut start = start.clone();
+        start.feed(&[])?;
+
+        let mut seen = HashSet::new();
+        seen.insert(fingerprint(&start));
+
+        // Move which led to each node, and the node it was made from
+        let mut parents: Vec<Option<(usize, usize)>> = vec![None];
+
+        let mut frontier = match self.order {
+            Order::BreadthFirst => Frontier::Queue(VecDeque::new()),
+            Order::BestFirst => Frontier::Heap(BinaryHeap::new(), Vec::new()),
+        };
+        frontier.push(0, 0, start);
+
+        while let Some((node, machine)) = frontier.pop() {
+
+            if machine.is_halted() {
+                continue;
+            }
+
+            for (i, candidate) in self.candidates.iter().enumerate() {
+
+                let mut next = machine.clone();
+                let output = match next.feed(candidate) {
+                    Ok(output) => output,
+                    Err(_) => continue,
+                };
+
+                if !seen.insert(fingerprint(&next)) {
+                    continue;
+                }
+
+                let child = parents.len();
+                parents.push(Some((node, i)));
+
+                match judge(&next, &output) {
+                    Verdict::Goal => {
+                        return Ok(Some(self.found(&parents, child, next)));
+                    },
+                    Verdict::Prune => { },
+                    Verdict::Explore(score) => frontier.push(child, score, next),
+                }
+
+                if seen.len() >= self.limit {
+                    return Ok(None);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Retraces the moves which led to `node`
+    fn found(&self, parents: &[Option<(usize, usize)>], mut node: usize, machine: Machine) -> Found {
+
+        let mut moves = Vec::new();
+        while let Some((parent, i)) = parents[node] {
+            moves.push(i);
+            node = parent;
+        }
+        moves.reverse();
+
+        let inputs = moves.iter()
+            .flat_map(|&i| self.candidates[i].iter().cloned())
+            .collect();
+
+        Found { moves, inputs, machine, explored: parents.len() }
+    }
+}
+
+
+/// Hash of a machine's memory and registers
+///
+/// Only hashes are kept, so states that collide are treated as duplicates.
+fn fingerprint(machine: &Machine) -> u64 {
+
+    let mut hasher = DefaultHasher::new();
+    machine.hash(&mut hasher);
+    hasher.finish()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Reads digits, shifting each into an accumulator (`x = 2x + digit`) and
+    /// outputting the result
+    fn shift() -> Machine {
+
+        Machine::new(&[3,100,1002,101,2,101,1,101,100,101,4,101,1105,1,0])
+    }
+
+    fn target(val: isize) -> impl FnMut(&Machine, &[isize]) -> Verdict {
+
+        move |_, output| match output.last() {
+            Some(&x) if x == val => Verdict::Goal,
+            Some(&x) if x < val => Verdict::Explore(val - x),
+            _ => Verdict::Prune,
+        }
+    }
+
+    #[test]
+    fn breadth_first() {
+
+        let bits = [vec![0], vec![1]];
+        let found = Search::new(&bits)
+            .run(&shift(), target(11))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(found.inputs, vec![1, 0, 1, 1]);
+        assert_eq!(found.moves, vec![1, 0, 1, 1]);
+        assert_eq!(found.machine.mem()[101], 11);
+
+        // Inputting 0 at the start leaves the machine as it was
+        let none = Search::new(&[vec![0]])
+            .run(&shift(), target(11))
+            .unwrap();
+        assert!(none.is_none());
+    }
+
+    #[test]
+    fn best_first() {
+
+        let bits = [vec![0], vec![1]];
+
+        // Prefers accumulators whose bits start the same way as the target's
+        let prefix = |_: &Machine, output: &[isize]| {
+            let x = output[0];
+            let mut t = 1000;
+            while t > x {
+                t >>= 1;
+            }
+            match (x, t == x) {
+                (1000, _) => Verdict::Goal,
+                (_, true) => Verdict::Explore(0),
+                (_, false) => Verdict::Explore(1),
+            }
+        };
+
+        let bfs = Search::new(&bits)
+            .run(&shift(), prefix)
+            .unwrap()
+            .unwrap();
+        let best = Search::new(&bits)
+            .order(Order::BestFirst)
+            .run(&shift(), prefix)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(bfs.inputs, vec![1, 1, 1, 1, 1, 0, 1, 0, 0, 0]);
+        assert_eq!(best.inputs, bfs.inputs);
+        assert!(best.explored < bfs.explored / 10);
+    }
+
+    #[test]
+    fn limit() {
+
+        let found = Search::new(&[vec![0], vec![1]])
+            .limit(5)
+            .run(&shift(), target(1 << 20))
+            .unwrap();
+
+        assert!(found.is_none());
+    }
+
+    #[test]
+    fn multi_value_moves() {
+
+        let pairs = [vec![1, 1], vec![0, 1]];
+        let found = Search::new(&pairs)
+            .run(&shift(), target(13))
+            .unwrap()
+            .unwrap();
+
+        // 13 is 1101 in binary
+        assert_eq!(found.inputs, vec![1, 1, 0, 1]);
+        assert_eq!(found.moves, vec![0, 1]);
+    }
+
+    #[test]
+    fn failing_moves() {
+
+        // Outputs nonzero inputs, but runs into an invalid opcode on zero
+        let prog = [3,20,1005,20,6,42,4,20,1105,1,0];
+
+        let found = Search::new(&[vec![0], vec![1]])
+            .run(&Machine::new(&prog), target(1))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(found.inputs, vec![1]);
+    }
+}
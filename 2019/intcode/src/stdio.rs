@@ -0,0 +1,44 @@
+//! I/O over the standard streams
+
+use std::io;
+
+use crate::{Computer, Error, InputError, IoHandler};
+
+
+/// Default I/O handler
+///
+/// Reads input from stdin a line at a time, asking again if a line is not an
+/// integer. Input is unavailable once stdin is closed.
+pub struct DefaultIoHandler;
+
+impl IoHandler for DefaultIoHandler {
+
+    fn input(&mut self) -> Result<isize, InputError> {
+
+        loop {
+            let mut input = String::new();
+
+            match io::stdin().read_line(&mut input) {
+                Ok(0) | Err(_) => return Err(InputError::Unavailable),
+                Ok(_) => { },
+            }
+
+            match input.trim().parse() {
+                Ok(val) => return Ok(val),
+                Err(_) => eprintln!("expected an integer, got {:?}", input.trim()),
+            }
+        }
+    }
+
+    fn output(&mut self, val: isize) {
+
+        println!("{}", val);
+    }
+}
+
+
+pub fn eval(prog: &mut [isize]) -> Result<(), Error> {
+
+    Computer::new(DefaultIoHandler)
+        .eval(prog)
+}
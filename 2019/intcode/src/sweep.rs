@@ -0,0 +1,348 @@
+//! Parallel parameter sweeps over intcode programs
+//!
+//! Candidates are evaluated across all available CPU cores, but results are
+//! always reported in candidate order so a sweep gives the same answer as the
+//! equivalent sequential loop.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::{Computer, Error, InputError, IoHandler};
+use crate::isa::Revision;
+
+
+/// Memory patch and input sequence applied to a single run of a program
+#[derive(Clone, Debug, Default)]
+pub struct Candidate {
+
+    /// Values written to memory before the program starts, as `(addr, val)`
+    pub patch: Vec<(usize, isize)>,
+
+    /// Values supplied to the program's input instructions, in order
+    pub input: Vec<isize>,
+}
+
+impl Candidate {
+
+    /// Creates a candidate which only patches memory
+    pub fn patch(patch: Vec<(usize, isize)>) -> Self {
+        Self {
+            patch,
+            input: Vec::new(),
+        }
+    }
+
+    /// Creates a candidate which only supplies input
+    pub fn input(input: Vec<isize>) -> Self {
+        Self {
+            patch: Vec::new(),
+            input,
+        }
+    }
+}
+
+
+/// Final state of a program run for a single candidate
+#[derive(Clone, Debug)]
+pub struct Outcome {
+
+    /// Memory after the program halted
+    pub mem: Vec<isize>,
+
+    /// Values output by the program, in order
+    pub output: Vec<isize>,
+}
+
+
+/// I/O handler which feeds a fixed input sequence and records output
+struct SweepIo<'a> {
+    input: std::slice::Iter<'a, isize>,
+    output: &'a mut Vec<isize>,
+}
+
+impl<'a> IoHandler for SweepIo<'a> {
+
+    fn input(&mut self) -> Result<isize, InputError> {
+
+        self.input.next()
+            .cloned()
+            .ok_or(InputError::Unavailable)
+    }
+
+    fn output(&mut self, val: isize) {
+
+        self.output.push(val);
+    }
+}
+
+
+/// Runs a base program against many candidates
+pub struct Sweep<'a> {
+
+    /// Initial memory of the program
+    prog: &'a [isize],
+
+    /// Revision of the instruction set the program runs under
+    rev: Revision,
+}
+
+impl<'a> Sweep<'a> {
+
+    pub fn new(prog: &'a [isize]) -> Self {
+        Self {
+            prog,
+            rev: Revision::default(),
+        }
+    }
+
+    /// Runs the program under an earlier revision of the instruction set
+    pub fn revision(mut self, rev: Revision) -> Self {
+
+        self.rev = rev;
+        self
+    }
+
+    /// Runs the program once with the given candidate applied
+    pub fn run(&self, cand: &Candidate) -> Result<Outcome, Error> {
+
+        let mut mem = self.prog.to_vec();
+
+        for &(addr, val) in &cand.patch {
+            *mem.get_mut(addr).ok_or(Error::Address)? = val;
+        }
+
+        let mut output = Vec::new();
+        let io = SweepIo {
+            input: cand.input.iter(),
+            output: &mut output,
+        };
+
+        let mut computer = Computer::new(io);
+        computer.set_revision(self.rev);
+        computer.eval(&mut mem)?;
+
+        Ok(Outcome { mem, output })
+    }
+
+    /// Finds the first candidate whose outcome satisfies `pred`
+    pub fn first<P>(
+        &self,
+        cands: &[Candidate],
+        pred: P,
+    ) -> Result<Option<(usize, Outcome)>, Error>
+    where P: Fn(&Outcome) -> bool + Sync
+    {
+        first(cands, |cand| {
+            let outcome = self.run(cand)?;
+            Ok(if pred(&outcome) { Some(outcome) } else { None })
+        })
+    }
+
+    /// Finds every candidate whose outcome satisfies `pred`
+    pub fn all<P>(
+        &self,
+        cands: &[Candidate],
+        pred: P,
+    ) -> Result<Vec<(usize, Outcome)>, Error>
+    where P: Fn(&Outcome) -> bool + Sync
+    {
+        all(cands, |cand| {
+            let outcome = self.run(cand)?;
+            Ok(if pred(&outcome) { Some(outcome) } else { None })
+        })
+    }
+}
+
+
+/// Result of evaluating a single item, tagged with its index
+type Tagged<R> = (usize, Result<Option<R>, Error>);
+
+/// Evaluates `f` against `items` on all available cores
+///
+/// If `stop_early` is set, items after the first one which matched or failed
+/// may be skipped. Every item before it is always evaluated.
+fn par_eval<T, R, F>(items: &[T], stop_early: bool, f: F) -> Vec<Tagged<R>>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> Result<Option<R>, Error> + Sync,
+{
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(items.len())
+        .max(1);
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicUsize::new(usize::MAX);
+
+    let mut res: Vec<Tagged<R>> = thread::scope(|s| {
+
+        let workers: Vec<_> = (0..threads)
+            .map(|_| s.spawn(|| {
+
+                let mut res = Vec::new();
+
+                loop {
+
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    if i >= items.len() || i > stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    let r = f(&items[i]);
+                    if stop_early && !matches!(r, Ok(None)) {
+                        stop.fetch_min(i, Ordering::SeqCst);
+                    }
+
+                    res.push((i, r));
+                }
+
+                res
+            }))
+            .collect();
+
+        workers.into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect()
+    });
+
+    res.sort_by_key(|(i, _)| *i);
+    res
+}
+
+/// Finds the first item for which `f` returns `Some`, evaluating in parallel
+///
+/// An error from any item before the first match is returned instead.
+pub fn first<T, R, F>(items: &[T], f: F) -> Result<Option<(usize, R)>, Error>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> Result<Option<R>, Error> + Sync,
+{
+    for (i, r) in par_eval(items, true, f) {
+        if let Some(val) = r? {
+            return Ok(Some((i, val)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Finds every item for which `f` returns `Some`, evaluating in parallel
+///
+/// Matches are returned in item order. If any item fails, the error from the
+/// earliest such item is returned.
+pub fn all<T, R, F>(items: &[T], f: F) -> Result<Vec<(usize, R)>, Error>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> Result<Option<R>, Error> + Sync,
+{
+    let mut matches = Vec::new();
+
+    for (i, r) in par_eval(items, false, f) {
+        if let Some(val) = r? {
+            matches.push((i, val));
+        }
+    }
+
+    Ok(matches)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GRAV_PROG: &str = include_str!("grav-prog.txt");
+
+    #[test]
+    fn day2_part2_sln() {
+
+        let prog = crate::parse_prog(GRAV_PROG)
+            .unwrap();
+
+        let cands: Vec<_> = (0..100)
+            .flat_map(|i| (0..100).map(move |j| (i, j)))
+            .map(|(i, j)| Candidate::patch(vec![(1, i), (2, j)]))
+            .collect();
+
+        let (idx, outcome) = Sweep::new(&prog)
+            .first(&cands, |o| o.mem[0] == 19690720)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(idx, 2347);
+        assert_eq!(outcome.mem[1..3], [23, 47]);
+    }
+
+    #[test]
+    fn input_candidates() {
+
+        // Outputs 1 if input equals 8, otherwise 0
+        let prog = [
+            3,9,
+            8,9,10,9,
+            4,9,
+            99,
+            -1,8,
+        ];
+
+        let cands: Vec<_> = (0..20)
+            .map(|i| Candidate::input(vec![i]))
+            .collect();
+
+        let matches = Sweep::new(&prog)
+            .all(&cands, |o| o.output == [1])
+            .unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].0, 8);
+    }
+
+    #[test]
+    fn all_in_order() {
+
+        let items: Vec<isize> = (0..1000).collect();
+
+        let matches = all(&items, |&i| Ok(if i % 7 == 0 { Some(i) } else { None }))
+            .unwrap();
+
+        let expected: Vec<_> = (0..1000)
+            .filter(|i| i % 7 == 0)
+            .map(|i| (i as usize, i))
+            .collect();
+
+        assert_eq!(matches, expected);
+    }
+
+    #[test]
+    fn earliest_error() {
+
+        let items: Vec<isize> = (0..1000).collect();
+
+        let res = first(&items, |&i| {
+            if i == 500 {
+                Err(Error::State)
+            } else if i > 600 {
+                Ok(Some(i))
+            } else {
+                Ok(None)
+            }
+        });
+
+        assert!(matches!(res, Err(Error::State)));
+    }
+
+    #[test]
+    fn bad_patch() {
+
+        let prog = [99];
+
+        let res = Sweep::new(&prog)
+            .first(&[Candidate::patch(vec![(5, 0)])], |_| true);
+
+        assert!(matches!(res, Err(Error::Address)));
+    }
+}
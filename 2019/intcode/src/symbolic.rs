@@ -0,0 +1,762 @@
+//! Symbolic execution of intcode programs
+//!
+//! Selected memory cells are treated as unknowns, and the program is run with
+//! every cell holding an expression over those unknowns rather than a concrete
+//! value. This allows questions like "which noun and verb produce 19690720?" to
+//! be answered by solving an equation instead of re-running the program for
+//! every candidate.
+
+use std::convert::TryInto;
+use std::ops::RangeInclusive;
+
+use crate::{Computer, Error, InputError, IoHandler};
+use crate::isa;
+
+
+/// Upper bound on instructions executed during symbolic evaluation, and during
+/// each concrete run
+const MAX_CYCLES: usize = 1_000_000;
+
+/// Largest search space that will be enumerated, either against a non-linear
+/// expression or by running the program for every assignment
+const MAX_ENUM: usize = 1_000_000;
+
+
+/// Symbolic value of a memory cell
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+
+    /// Known constant
+    Const(isize),
+
+    /// Value of the nth unknown
+    Var(usize),
+
+    /// Sum of two expressions
+    Add(Box<Expr>, Box<Expr>),
+
+    /// Product of two expressions
+    Mul(Box<Expr>, Box<Expr>),
+
+    /// 1 if the first expression is less than the second, otherwise 0
+    Less(Box<Expr>, Box<Expr>),
+
+    /// 1 if both expressions are equal, otherwise 0
+    Equal(Box<Expr>, Box<Expr>),
+
+    /// Value which could not be tracked (e.g. loaded from a symbolic address)
+    Unknown,
+}
+
+impl Expr {
+
+    fn add(lhs: Expr, rhs: Expr) -> Expr {
+
+        match (lhs, rhs) {
+            (Expr::Const(l), Expr::Const(r)) => Expr::Const(l + r),
+            (Expr::Const(0), e) | (e, Expr::Const(0)) => e,
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (l, r) => Expr::Add(Box::new(l), Box::new(r)),
+        }
+    }
+
+    fn mul(lhs: Expr, rhs: Expr) -> Expr {
+
+        match (lhs, rhs) {
+            (Expr::Const(l), Expr::Const(r)) => Expr::Const(l * r),
+            (Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+            (Expr::Const(1), e) | (e, Expr::Const(1)) => e,
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (l, r) => Expr::Mul(Box::new(l), Box::new(r)),
+        }
+    }
+
+    fn less(lhs: Expr, rhs: Expr) -> Expr {
+
+        match (lhs, rhs) {
+            (Expr::Const(l), Expr::Const(r)) => Expr::Const((l < r) as isize),
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (l, r) => Expr::Less(Box::new(l), Box::new(r)),
+        }
+    }
+
+    fn equal(lhs: Expr, rhs: Expr) -> Expr {
+
+        match (lhs, rhs) {
+            (Expr::Const(l), Expr::Const(r)) => Expr::Const((l == r) as isize),
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (l, r) => Expr::Equal(Box::new(l), Box::new(r)),
+        }
+    }
+
+    /// Evaluates this expression with the given values for each unknown
+    ///
+    /// Returns `None` if the expression contains an untracked value.
+    pub fn eval(&self, vals: &[isize]) -> Option<isize> {
+
+        Some(match self {
+            Expr::Const(c) => *c,
+            Expr::Var(v) => vals[*v],
+            Expr::Add(l, r) => l.eval(vals)? + r.eval(vals)?,
+            Expr::Mul(l, r) => l.eval(vals)? * r.eval(vals)?,
+            Expr::Less(l, r) => (l.eval(vals)? < r.eval(vals)?) as isize,
+            Expr::Equal(l, r) => (l.eval(vals)? == r.eval(vals)?) as isize,
+            Expr::Unknown => return None,
+        })
+    }
+
+    /// Rewrites this expression as `coeffs[0] * x0 + coeffs[1] * x1 + ... + c`
+    ///
+    /// Returns `None` if the expression is not linear in its unknowns.
+    pub fn linear(&self, n_vars: usize) -> Option<(Vec<isize>, isize)> {
+
+        match self {
+
+            Expr::Const(c) => Some((vec![0; n_vars], *c)),
+
+            Expr::Var(v) => {
+                let mut coeffs = vec![0; n_vars];
+                coeffs[*v] = 1;
+                Some((coeffs, 0))
+            },
+
+            Expr::Add(l, r) => {
+                let (mut coeffs, lc) = l.linear(n_vars)?;
+                let (rcoeffs, rc) = r.linear(n_vars)?;
+                for (a, b) in coeffs.iter_mut().zip(rcoeffs) {
+                    *a += b;
+                }
+                Some((coeffs, lc + rc))
+            },
+
+            Expr::Mul(l, r) => {
+                let (lcoeffs, lc) = l.linear(n_vars)?;
+                let (rcoeffs, rc) = r.linear(n_vars)?;
+                let (coeffs, k, c) = if lcoeffs.iter().all(|&a| a == 0) {
+                    (rcoeffs, lc, rc)
+                } else if rcoeffs.iter().all(|&a| a == 0) {
+                    (lcoeffs, rc, lc)
+                } else {
+                    return None;
+                };
+                Some((coeffs.into_iter().map(|a| a * k).collect(), c * k))
+            },
+
+            Expr::Less(..) | Expr::Equal(..) | Expr::Unknown => None,
+        }
+    }
+}
+
+
+/// Reason why symbolic evaluation had to be abandoned
+#[derive(Debug)]
+enum Unsupported {
+
+    /// Control flow, addressing or decoding depended on an unknown
+    Symbolic,
+
+    /// The program requested input
+    Input,
+
+    /// The program did not halt within `MAX_CYCLES`
+    Runaway,
+
+    /// Concrete execution error
+    Error(Error),
+}
+
+
+/// Tracks state of a symbolically executing CPU
+struct SymCpu {
+
+    /// Instruction pointer
+    ip: usize,
+
+    /// Main memory
+    mem: Vec<Expr>,
+
+    /// Relative base
+    rb: isize,
+}
+
+impl SymCpu {
+
+    fn concrete(&self, addr: usize) -> Result<isize, Unsupported> {
+
+        match self.mem.get(addr) {
+            Some(Expr::Const(c)) => Ok(*c),
+            Some(_) => Err(Unsupported::Symbolic),
+            None => Err(Unsupported::Error(Error::Address)),
+        }
+    }
+
+    fn to_addr(val: isize) -> Result<usize, Unsupported> {
+
+        val.try_into()
+            .map_err(|_| Unsupported::Error(Error::Address))
+    }
+
+    /// Resolves the address referenced by a non-immediate parameter
+    ///
+    /// Returns `None` if the address depends on an unknown.
+    fn param_addr(&self, param_idx: usize) -> Result<Option<usize>, Unsupported> {
+
+        let op = self.concrete(self.ip)?;
+        let raw = match self.mem.get(self.ip + 1 + param_idx) {
+            Some(Expr::Const(c)) => *c,
+            Some(_) => return Ok(None),
+            None => return Err(Unsupported::Error(Error::Address)),
+        };
+
+        match isa::mode(op, param_idx) {
+            0 => Ok(Some(Self::to_addr(raw)?)),
+            2 => Ok(Some(Self::to_addr(self.rb + raw)?)),
+            _ => Err(Unsupported::Error(Error::Mode)),
+        }
+    }
+
+    fn load_param(&self, param_idx: usize) -> Result<Expr, Unsupported> {
+
+        let op = self.concrete(self.ip)?;
+
+        if isa::mode(op, param_idx) == 1 {
+            return self.mem.get(self.ip + 1 + param_idx)
+                .cloned()
+                .ok_or(Unsupported::Error(Error::Address));
+        }
+
+        match self.param_addr(param_idx)? {
+            Some(addr) => self.mem.get(addr)
+                .cloned()
+                .ok_or(Unsupported::Error(Error::Address)),
+            None => Ok(Expr::Unknown),
+        }
+    }
+
+    fn store_by_param(
+        &mut self,
+        param_idx: usize,
+        val: Expr,
+    ) -> Result<(), Unsupported> {
+
+        let op = self.concrete(self.ip)?;
+
+        if isa::mode(op, param_idx) == 1 {
+            return Err(Unsupported::Error(Error::Mode));
+        }
+
+        let addr = self.param_addr(param_idx)?
+            .ok_or(Unsupported::Symbolic)?;
+        let cell = self.mem.get_mut(addr)
+            .ok_or(Unsupported::Error(Error::Address))?;
+        *cell = val;
+
+        Ok(())
+    }
+
+    fn load_concrete(&self, param_idx: usize) -> Result<isize, Unsupported> {
+
+        match self.load_param(param_idx)? {
+            Expr::Const(c) => Ok(c),
+            _ => Err(Unsupported::Symbolic),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        f: fn(Expr, Expr) -> Expr,
+    ) -> Result<(), Unsupported> {
+
+        let lhs = self.load_param(0)?;
+        let rhs = self.load_param(1)?;
+
+        self.store_by_param(2, f(lhs, rhs))?;
+
+        self.ip += 4;
+
+        Ok(())
+    }
+
+    fn jump(&mut self, if_true: bool) -> Result<(), Unsupported> {
+
+        let val = self.load_concrete(0)?;
+
+        if (val != 0) == if_true {
+            self.ip = Self::to_addr(self.load_concrete(1)?)?;
+        } else {
+            self.ip += 3;
+        }
+
+        Ok(())
+    }
+
+    fn cycle(&mut self) -> Result<bool, Unsupported> {
+
+        let op = self.concrete(self.ip)?;
+
+        match op % 100 {
+            1  => self.binary(Expr::add)?,
+            2  => self.binary(Expr::mul)?,
+            3  => return Err(Unsupported::Input),
+            4  => {
+                // Output is not observable symbolically, but must still be
+                // well-formed
+                self.load_param(0)?;
+                self.ip += 2;
+            },
+            5  => self.jump(true)?,
+            6  => self.jump(false)?,
+            7  => self.binary(Expr::less)?,
+            8  => self.binary(Expr::equal)?,
+            9  => {
+                self.rb += self.load_concrete(0)?;
+                self.ip += 2;
+            },
+            99 => return Ok(false),
+            _  => return Err(Unsupported::Error(Error::Opcode)),
+        }
+
+        Ok(true)
+    }
+}
+
+
+/// I/O handler used while searching for a solution concretely
+///
+/// Input is never available, so programs which request it fail to solve.
+struct NoIo;
+
+impl IoHandler for NoIo {
+
+    fn input(&mut self) -> Result<isize, InputError> {
+        Err(InputError::Unavailable)
+    }
+
+    fn output(&mut self, _val: isize) { }
+}
+
+
+/// Solves for the initial values of selected memory cells
+///
+/// Programs are expected to run without input. Output is ignored.
+pub struct Solver<'a> {
+
+    /// Initial memory of the program
+    prog: &'a [isize],
+
+    /// Address and candidate values of each unknown
+    vars: Vec<(usize, RangeInclusive<isize>)>,
+}
+
+impl<'a> Solver<'a> {
+
+    pub fn new(prog: &'a [isize]) -> Self {
+        Self {
+            prog,
+            vars: Vec::new(),
+        }
+    }
+
+    /// Marks the cell at `addr` as unknown, taking a value from `range`
+    pub fn var(&mut self, addr: usize, range: RangeInclusive<isize>) -> &mut Self {
+
+        self.vars.push((addr, range));
+        self
+    }
+
+    /// Runs the program symbolically, returning the final contents of memory
+    ///
+    /// Returns `None` if the program cannot be evaluated symbolically, e.g.
+    /// because a jump or store address depends on an unknown.
+    pub fn eval_symbolic(&self) -> Option<Vec<Expr>> {
+
+        self.run_symbolic().ok()
+    }
+
+    fn run_symbolic(&self) -> Result<Vec<Expr>, Unsupported> {
+
+        let mut mem: Vec<Expr> = self.prog.iter()
+            .map(|&c| Expr::Const(c))
+            .collect();
+
+        for (i, (addr, _)) in self.vars.iter().enumerate() {
+            *mem.get_mut(*addr).ok_or(Unsupported::Error(Error::Address))? =
+                Expr::Var(i);
+        }
+
+        let mut cpu = SymCpu { ip: 0, mem, rb: 0 };
+
+        for _ in 0..MAX_CYCLES {
+            if !cpu.cycle()? {
+                return Ok(cpu.mem);
+            }
+        }
+
+        Err(Unsupported::Runaway)
+    }
+
+    /// Finds values for each unknown such that `target_addr` holds `target`
+    /// once the program halts
+    ///
+    /// Solutions are searched in lexicographic order of the unknowns (in the
+    /// order they were added), so the first match found by a nested loop is
+    /// also the one returned here. If the program cannot be solved
+    /// symbolically and there are more than `MAX_ENUM` assignments to try,
+    /// no solution is returned.
+    pub fn solve(
+        &self,
+        target_addr: usize,
+        target: isize,
+    ) -> Result<Option<Vec<isize>>, Error> {
+
+        let expr = match self.run_symbolic() {
+            Ok(mem) => mem.get(target_addr)
+                .cloned()
+                .ok_or(Error::Address)?,
+            Err(Unsupported::Error(err)) => return Err(err),
+            Err(_) => Expr::Unknown,
+        };
+
+        let solution = if let Some((coeffs, c)) = expr.linear(self.vars.len()) {
+            Some(self.solve_linear(&coeffs, target - c))
+        } else if expr != Expr::Unknown && self.space() <= MAX_ENUM {
+            Some(self.search(|vals| expr.eval(vals) == Some(target)))
+        } else {
+            None
+        };
+
+        // Untracked values can cancel out of an expression (e.g. when a load
+        // through an unknown address is multiplied by zero), so a solution is
+        // only trusted once it has been run for real
+        match solution {
+            Some(None) => Ok(None),
+            Some(Some(vals)) if self.check(&vals, target_addr, target) == Ok(true) => {
+                Ok(Some(vals))
+            },
+            _ if self.space() <= MAX_ENUM => self.solve_concrete(target_addr, target),
+            _ => Ok(None),
+        }
+    }
+
+    /// Number of possible assignments to the unknowns
+    fn space(&self) -> usize {
+
+        self.vars.iter()
+            .map(|(_, r)| r.clone().count())
+            .fold(1, usize::saturating_mul)
+    }
+
+    /// Visits assignments in lexicographic order until `pred` matches
+    fn search<F>(&self, mut pred: F) -> Option<Vec<isize>>
+    where F: FnMut(&[isize]) -> bool
+    {
+        let mut vals: Vec<isize> = self.vars.iter()
+            .map(|(_, r)| *r.start())
+            .collect();
+
+        if self.vars.iter().any(|(_, r)| r.is_empty()) {
+            return None;
+        }
+
+        loop {
+
+            if pred(&vals) {
+                return Some(vals);
+            }
+
+            // Advance to the next assignment, odometer-style
+            let mut i = vals.len();
+            loop {
+                if i == 0 {
+                    return None;
+                }
+                i -= 1;
+                let range = &self.vars[i].1;
+                if vals[i] < *range.end() {
+                    vals[i] += 1;
+                    break;
+                }
+                vals[i] = *range.start();
+            }
+        }
+    }
+
+    /// Solves `sum(coeffs[i] * x[i]) == rhs`
+    fn solve_linear(&self, coeffs: &[isize], rhs: isize) -> Option<Vec<isize>> {
+
+        // The last unknown with a non-zero coefficient is solved for directly,
+        // unknowns after it are free and those before it are enumerated
+        let pivot = match coeffs.iter().rposition(|&a| a != 0) {
+            Some(pivot) => pivot,
+            None => return if rhs == 0 { self.search(|_| true) } else { None },
+        };
+
+        if self.vars[pivot + 1..].iter().any(|(_, r)| r.is_empty()) {
+            return None;
+        }
+
+        let mut solution = None;
+
+        let outer = Solver {
+            prog: self.prog,
+            vars: self.vars[..pivot].to_vec(),
+        };
+
+        outer.search(|vals| {
+
+            let partial: isize = vals.iter()
+                .zip(coeffs)
+                .map(|(x, a)| x * a)
+                .sum();
+            let rem = rhs - partial;
+
+            if rem % coeffs[pivot] != 0 {
+                return false;
+            }
+
+            let x = rem / coeffs[pivot];
+            if !self.vars[pivot].1.contains(&x) {
+                return false;
+            }
+
+            let mut vals = vals.to_vec();
+            vals.push(x);
+            vals.extend(self.vars[pivot + 1..].iter().map(|(_, r)| *r.start()));
+            solution = Some(vals);
+
+            true
+        });
+
+        solution
+    }
+
+    /// Falls back to running the program for every assignment
+    ///
+    /// Assignments for which the program fails or does not halt are skipped.
+    /// If it fails for every one of them, the first error is returned.
+    fn solve_concrete(
+        &self,
+        target_addr: usize,
+        target: isize,
+    ) -> Result<Option<Vec<isize>>, Error> {
+
+        let mut err = None;
+        let mut ran = false;
+
+        let res = self.search(|vals| {
+            match self.check(vals, target_addr, target) {
+                Ok(found) => {
+                    ran = true;
+                    found
+                },
+                Err(e) => {
+                    err.get_or_insert(e);
+                    false
+                },
+            }
+        });
+
+        match err {
+            Some(err) if !ran => Err(err),
+            _ => Ok(res),
+        }
+    }
+
+    /// Runs the program with the given values of the unknowns, returning
+    /// whether `target_addr` holds `target` once it halts
+    ///
+    /// Returns `Ok(false)` if the program does not halt within `MAX_CYCLES`
+    /// instructions.
+    fn check(&self, vals: &[isize], target_addr: usize, target: isize) -> Result<bool, Error> {
+
+        let mut mem = self.prog.to_vec();
+        for ((addr, _), val) in self.vars.iter().zip(vals) {
+            mem[*addr] = *val;
+        }
+
+        let mut computer = Computer::new(NoIo);
+        let mut session = computer.attach(&mut mem);
+
+        for _ in 0..MAX_CYCLES {
+            if !session.step()? {
+                return Ok(session.read(target_addr) == Ok(target));
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GRAV_PROG: &str = include_str!("grav-prog.txt");
+
+    #[test]
+    fn grav_prog_is_linear() {
+
+        let prog = crate::parse_prog(GRAV_PROG)
+            .unwrap();
+
+        let mem = Solver::new(&prog)
+            .var(1, 0..=99)
+            .var(2, 0..=99)
+            .eval_symbolic()
+            .unwrap();
+
+        let (coeffs, _) = mem[0].linear(2)
+            .unwrap();
+
+        assert_eq!(coeffs[1], 1);
+    }
+
+    #[test]
+    fn day2_part2_sln() {
+
+        let prog = crate::parse_prog(GRAV_PROG)
+            .unwrap();
+
+        let res = Solver::new(&prog)
+            .var(1, 0..=99)
+            .var(2, 0..=99)
+            .solve(0, 19690720)
+            .unwrap();
+
+        assert_eq!(res, Some(vec![23, 47]));
+    }
+
+    #[test]
+    fn non_linear() {
+
+        // mem[0] = mem[5] * mem[6]
+        let prog = [
+            2,5,6,0,
+            99,
+            0,0,
+        ];
+
+        let res = Solver::new(&prog)
+            .var(5, 0..=20)
+            .var(6, 0..=20)
+            .solve(0, 91)
+            .unwrap();
+
+        assert_eq!(res, Some(vec![7, 13]));
+    }
+
+    #[test]
+    fn comparison() {
+
+        // mem[0] = (mem[5] == 3)
+        let prog = [
+            8,5,6,0,
+            99,
+            0,3,
+        ];
+
+        let res = Solver::new(&prog)
+            .var(5, 0..=9)
+            .solve(0, 1)
+            .unwrap();
+
+        assert_eq!(res, Some(vec![3]));
+    }
+
+    #[test]
+    fn concrete_fallback() {
+
+        // Jumps past the store unless mem[12] is non-zero, so control flow
+        // depends on the unknown
+        let prog = [
+            1005,12,7,
+            1101,5,5,0,
+            99,
+            0,0,0,0,
+            0,
+        ];
+
+        let mut solver = Solver::new(&prog);
+        solver.var(12, 0..=3);
+
+        assert!(solver.eval_symbolic().is_none());
+        assert_eq!(solver.solve(0, 10).unwrap(), Some(vec![0]));
+        assert_eq!(solver.solve(0, 1005).unwrap(), Some(vec![1]));
+    }
+
+    #[test]
+    fn failing_assignments() {
+
+        // mem[0] = mem[mem[1]] * 0, which fails for negative addresses even
+        // though the unknown seems to cancel out
+        let prog = [1002,0,0,0,99];
+
+        let res = Solver::new(&prog)
+            .var(1, -1..=4)
+            .solve(0, 0);
+        assert_eq!(res, Ok(Some(vec![0])));
+
+        let res = Solver::new(&prog)
+            .var(1, -3..=-1)
+            .solve(0, 0);
+        assert_eq!(res, Err(Error::Address));
+    }
+
+    #[test]
+    fn bad_modes() {
+
+        // Writing in immediate mode, and an unrecognized mode
+        for prog in &[[11101,1,1,0,99], [301,0,0,0,99]] {
+
+            let res = Solver::new(prog)
+                .solve(0, 2);
+            assert_eq!(res, Err(Error::Mode));
+            assert_eq!(crate::eval(&mut prog.clone()), Err(Error::Mode));
+        }
+    }
+
+    #[test]
+    fn runaway_assignments() {
+
+        // Loops forever unless mem[1] is zero
+        let prog = [1105,0,0,99,1101,3,4,0,99];
+
+        let res = Solver::new(&prog)
+            .var(1, 0..=1)
+            .solve(0, 7);
+        assert_eq!(res, Ok(None));
+
+        // Stores 7 in mem[0] unless mem[1] is non-zero
+        let prog = [1105,0,0,1101,3,4,0,99];
+
+        let res = Solver::new(&prog)
+            .var(1, -1..=0)
+            .solve(0, 7);
+        assert_eq!(res, Ok(Some(vec![0])));
+
+        // Too many assignments to try one by one
+        let res = Solver::new(&prog)
+            .var(1, isize::MIN..=0)
+            .solve(0, 7);
+        assert_eq!(res, Ok(None));
+    }
+
+    #[test]
+    fn no_solution() {
+
+        let prog = [
+            1,5,6,0,
+            99,
+            0,0,
+        ];
+
+        let res = Solver::new(&prog)
+            .var(5, 0..=9)
+            .var(6, 0..=9)
+            .solve(0, 100)
+            .unwrap();
+
+        assert_eq!(res, None);
+    }
+}
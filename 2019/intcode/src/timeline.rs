@@ -0,0 +1,502 @@
+//! Timelines of intcode execution in the trace-event format
+//!
+//! A `Timeline` collects events from any number of machines, one `Track` each,
+//! and serializes them as the JSON understood by trace viewers such as
+//! `chrome://tracing` and Perfetto. Wrapping a machine's I/O handler in
+//! `Traced` records a span for each wait on input and an instant event for
+//! each output; this works for both blocking and asynchronous handlers.
+//! Machines stepped with `run_traced` additionally get spans for recognized
+//! function calls, and `Track::patch` marks the cells changed by a patch.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::{Error, InputError, IoHandler, Session};
+use crate::aio::AsyncIoHandler;
+use crate::isa;
+use crate::patch::Patch;
+
+
+/// Kind of a single trace event
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Phase {
+    Begin,
+    End,
+    Instant,
+}
+
+
+/// Single recorded event
+#[derive(Clone, Debug)]
+struct Event {
+    phase: Phase,
+    name: String,
+    track: usize,
+
+    /// Microseconds since the timeline was created
+    ts: f64,
+
+    /// Value attached to the event, if any
+    value: Option<isize>,
+}
+
+
+#[derive(Default)]
+struct Inner {
+    tracks: Vec<String>,
+    events: Vec<Event>,
+}
+
+
+/// Events recorded from one or more machines
+///
+/// Clones share the same events, so a timeline can be handed to machines on
+/// other threads.
+#[derive(Clone)]
+pub struct Timeline {
+    start: Instant,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timeline {
+
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            inner: Arc::default(),
+        }
+    }
+
+    /// Adds a track, shown as a separate row in trace viewers
+    pub fn track(&self, name: &str) -> Track {
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.tracks.push(name.to_owned());
+
+        Track {
+            timeline: self.clone(),
+            id: inner.tracks.len(),
+        }
+    }
+
+    /// Number of events recorded so far
+    pub fn len(&self) -> usize {
+
+        self.inner.lock().unwrap().events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+
+        self.len() == 0
+    }
+
+    /// Serializes every track and event as trace-event JSON
+    pub fn to_json(&self) -> String {
+
+        let inner = self.inner.lock().unwrap();
+        let mut entries = Vec::new();
+
+        for (i, name) in inner.tracks.iter().enumerate() {
+            entries.push(format!(
+                r#"{{"ph":"M","name":"thread_name","pid":1,"tid":{},"args":{{"name":{}}}}}"#,
+                i + 1,
+                quote(name),
+            ));
+        }
+
+        for event in &inner.events {
+            let mut entry = format!(
+                r#"{{"ph":"{}","name":{},"pid":1,"tid":{},"ts":{:.3}"#,
+                match event.phase {
+                    Phase::Begin => "B",
+                    Phase::End => "E",
+                    Phase::Instant => "i",
+                },
+                quote(&event.name),
+                event.track,
+                event.ts,
+            );
+            if event.phase == Phase::Instant {
+                entry.push_str(r#","s":"t""#);
+            }
+            if let Some(value) = event.value {
+                write!(entry, r#","args":{{"value":{}}}"#, value).unwrap();
+            }
+            entry.push('}');
+            entries.push(entry);
+        }
+
+        format!("{{\"traceEvents\":[\n{}\n]}}\n", entries.join(",\n"))
+    }
+
+    /// Writes the timeline as trace-event JSON
+    pub fn save<P>(&self, path: P) -> io::Result<()>
+    where P: AsRef<Path>
+    {
+        fs::write(path, self.to_json())
+    }
+
+    fn record(&self, track: usize, phase: Phase, name: &str, value: Option<isize>) {
+
+        let ts = self.start.elapsed().as_secs_f64() * 1e6;
+
+        self.inner.lock()
+            .unwrap()
+            .events
+            .push(Event { phase, name: name.to_owned(), track, ts, value });
+    }
+}
+
+
+/// Row of a timeline belonging to a single machine
+#[derive(Clone)]
+pub struct Track {
+    timeline: Timeline,
+    id: usize,
+}
+
+impl Track {
+
+    /// Starts a span, which lasts until the matching `end`
+    pub fn begin(&self, name: &str) {
+
+        self.timeline.record(self.id, Phase::Begin, name, None);
+    }
+
+    /// Ends the most recently started span
+    pub fn end(&self, name: &str) {
+
+        self.timeline.record(self.id, Phase::End, name, None);
+    }
+
+    /// Marks a single point in time, optionally with a value
+    pub fn instant(&self, name: &str, value: Option<isize>) {
+
+        self.timeline.record(self.id, Phase::Instant, name, value);
+    }
+
+    /// Marks each cell changed by a patch, with its new value
+    pub fn patch(&self, patch: &Patch) {
+
+        for edit in patch.edits() {
+            let name = format!("patch {} [{}]", patch.name(), edit.addr);
+            self.instant(&name, Some(edit.new));
+        }
+    }
+}
+
+
+/// I/O handler which records its traffic on a track
+pub struct Traced<H> {
+    inner: H,
+    track: Track,
+}
+
+impl<H> Traced<H> {
+
+    pub fn new(inner: H, track: Track) -> Self {
+        Self {
+            inner,
+            track,
+        }
+    }
+
+    pub fn into_inner(self) -> H {
+
+        self.inner
+    }
+}
+
+impl<H> IoHandler for Traced<H>
+where H: IoHandler
+{
+
+    fn input(&mut self) -> Result<isize, InputError> {
+
+        self.track.begin("input");
+        let val = self.inner.input();
+        self.track.end("input");
+
+        val
+    }
+
+    fn output(&mut self, val: isize) {
+
+        self.track.instant("output", Some(val));
+        self.inner.output(val);
+    }
+}
+
+impl<H> AsyncIoHandler for Traced<H>
+where H: AsyncIoHandler
+{
+
+    async fn input(&mut self) -> Result<isize, InputError> {
+
+        self.track.begin("input");
+        let val = self.inner.input().await;
+        self.track.end("input");
+
+        val
+    }
+
+    async fn output(&mut self, val: isize) {
+
+        self.track.instant("output", Some(val));
+        self.inner.output(val).await;
+    }
+}
+
+
+/// Runs a session until it halts, recording spans for function calls
+///
+/// A taken jump counts as a call if the address just after it was written to
+/// memory since the previous taken jump (i.e. a return address was pushed).
+/// The call ends when execution reaches that return address. Calls are named
+/// after the address they jump to. Calls still in progress when the program
+/// halts or fails are ended then.
+pub fn run_traced<H>(session: &mut Session<H>, track: &Track) -> Result<(), Error>
+where H: IoHandler
+{
+    // Names and return addresses of calls in progress, innermost last
+    let mut calls: Vec<(String, usize)> = Vec::new();
+
+    // Values written since the last taken jump
+    let mut written: Vec<isize> = Vec::new();
+
+    let res = loop {
+        let ip = session.ip();
+        let mem = session.mem();
+        let op = mem.get(ip).cloned().unwrap_or(0);
+        let instr = isa::lookup(isa::opcode(op));
+
+        let targets = isa::write_targets(mem, ip, session.rb());
+
+        match session.step() {
+            Ok(true) => { },
+            Ok(false) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+
+        for addr in targets {
+            written.extend(session.read(addr).ok());
+        }
+
+        let next = ip + instr.map_or(0, |i| i.size());
+        let jumped = instr.is_some_and(|i| i.is_jump()) && session.ip() != next;
+
+        if jumped {
+            if written.contains(&(next as isize)) {
+                let name = format!("fn {}", session.ip());
+                track.begin(&name);
+                calls.push((name, next));
+            }
+            written.clear();
+        }
+
+        // Unwind every call returning here, so calls which never return
+        // explicitly are closed by an outer return
+        if let Some(depth) = calls.iter().rposition(|&(_, ret)| ret == session.ip()) {
+            for (name, _) in calls.drain(depth..).rev() {
+                track.end(&name);
+            }
+        }
+    };
+
+    for (name, _) in calls.drain(..).rev() {
+        track.end(&name);
+    }
+
+    res
+}
+
+
+/// Quotes a string as a JSON string literal
+fn quote(s: &str) -> String {
+
+    let mut quoted = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Computer;
+    use crate::aio::{self, ChannelIo, Executor};
+
+    /// Phases and names of the events in a timeline, in order
+    fn events(timeline: &Timeline) -> Vec<(Phase, String, usize, Option<isize>)> {
+
+        timeline.inner.lock()
+            .unwrap()
+            .events
+            .iter()
+            .map(|e| (e.phase, e.name.clone(), e.track, e.value))
+            .collect()
+    }
+
+    #[test]
+    fn async_ring() {
+
+        // Adds one to each input and outputs it, until it sees 5 or more
+        let prog = vec![3,20,1001,20,1,20,4,20,1007,20,5,21,1005,21,0,99];
+
+        let timeline = Timeline::new();
+        let (first_tx, mut rx) = aio::channel();
+        first_tx.send(0).unwrap();
+
+        let mut exec = Executor::new();
+        for i in 0..2 {
+            let (tx, next) = aio::channel();
+            let input = std::mem::replace(&mut rx, next);
+            let io = Traced::new(ChannelIo { input, output: tx }, timeline.track(&format!("m{}", i)));
+            let mut mem = prog.clone();
+            mem.resize(22, 0);
+            exec.spawn(async move {
+                Computer::new(io)
+                    .eval_async(&mut mem)
+                    .await
+                    .unwrap();
+            });
+        }
+        exec.spawn(async move {
+            while let Some(val) = rx.recv().await {
+                let _ = first_tx.send(val);
+            }
+        });
+        exec.run();
+
+        let outputs: Vec<_> = events(&timeline)
+            .into_iter()
+            .filter(|e| e.0 == Phase::Instant)
+            .map(|e| (e.2, e.3.unwrap()))
+            .collect();
+        assert_eq!(outputs, vec![(1, 1), (2, 2), (1, 3), (2, 4), (1, 5), (2, 6)]);
+
+        // Every wait on input is a closed span
+        let waits = events(&timeline)
+            .into_iter()
+            .filter(|e| e.1 == "input")
+            .count();
+        assert_eq!(waits, 12);
+
+        let json = timeline.to_json();
+        assert!(json.starts_with("{\"traceEvents\":[\n"));
+        assert!(json.contains(r#"{"ph":"M","name":"thread_name","pid":1,"tid":2,"args":{"name":"m1"}}"#));
+        assert!(json.contains(r#""s":"t","args":{"value":6}}"#));
+    }
+
+    #[test]
+    fn calls() {
+
+        struct NoIo;
+
+        impl IoHandler for NoIo {
+
+            fn input(&mut self) -> Result<isize, InputError> {
+                Err(InputError::Unavailable)
+            }
+
+            fn output(&mut self, _: isize) { }
+        }
+
+        // Calls a function at 20 twice, passing the return address in [100];
+        // the function calls another at 30 which returns via [101]
+        let mut mem = vec![0; 110];
+        mem[..14].copy_from_slice(&[1101,0,7,100, 1105,1,20, 1101,0,14,100, 1105,1,20]);
+        mem[14] = 99;
+        mem[20..27].copy_from_slice(&[1101,0,27,101, 1105,1,30]);
+        mem[27..30].copy_from_slice(&[106,0,100]);
+        mem[30..33].copy_from_slice(&[106,0,101]);
+
+        let timeline = Timeline::new();
+        let track = timeline.track("main");
+        let mut computer = Computer::new(NoIo);
+
+        run_traced(&mut computer.attach(&mut mem), &track)
+            .unwrap();
+        assert!(computer.is_halted());
+
+        let spans: Vec<_> = events(&timeline)
+            .into_iter()
+            .map(|(phase, name, _, _)| (phase, name))
+            .collect();
+        let span = |phase, name: &str| (phase, name.to_owned());
+
+        assert_eq!(spans, vec![
+            span(Phase::Begin, "fn 20"),
+            span(Phase::Begin, "fn 30"),
+            span(Phase::End, "fn 30"),
+            span(Phase::End, "fn 20"),
+            span(Phase::Begin, "fn 20"),
+            span(Phase::Begin, "fn 30"),
+            span(Phase::End, "fn 30"),
+            span(Phase::End, "fn 20"),
+        ]);
+
+        // Returning to an invalid instruction still ends the open call
+        mem[27] = 0;
+        let timeline = Timeline::new();
+        let track = timeline.track("main");
+        computer.reset();
+
+        let res = run_traced(&mut computer.attach(&mut mem), &track);
+        assert_eq!(res, Err(Error::Opcode));
+
+        let phases: Vec<_> = events(&timeline)
+            .into_iter()
+            .map(|(phase, _, _, _)| phase)
+            .collect();
+        assert_eq!(phases, vec![Phase::Begin, Phase::Begin, Phase::End, Phase::End]);
+    }
+
+    #[test]
+    fn patches() {
+
+        let mut mem = vec![1,0,0,0,99];
+        let patch = Patch::new("alarm")
+            .set(1, 0, 4)
+            .and_then(|p| p.set(2, 0, 4))
+            .unwrap();
+
+        let timeline = Timeline::new();
+        let track = timeline.track("main");
+
+        patch.apply(&mut mem)
+            .unwrap();
+        track.patch(&patch);
+
+        assert_eq!(events(&timeline), vec![
+            (Phase::Instant, "patch alarm [1]".to_owned(), 1, Some(4)),
+            (Phase::Instant, "patch alarm [2]".to_owned(), 1, Some(4)),
+        ]);
+    }
+
+    #[test]
+    fn quoting() {
+
+        assert_eq!(quote("amp \"A\"\\\n"), r#""amp \"A\"\\\u000a""#);
+    }
+}
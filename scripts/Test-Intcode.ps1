@@ -0,0 +1,27 @@
+#!/usr/bin/pwsh
+
+<#
+
+.SYNOPSIS
+Builds and tests the shared intcode crate, with and without std
+
+.DESCRIPTION
+The intcode interpreter can be built with only core and alloc by disabling its
+default std feature. Building the library that way checks nothing in the
+interpreter has come to depend on std; tests still link std for the harness,
+but only exercise the parts available without it.
+
+#>
+
+$ErrorActionPreference = "Stop"
+
+function invokeCargo {
+    cargo @args
+    if ($LASTEXITCODE -ne 0) {
+        throw "cargo $args failed"
+    }
+}
+
+invokeCargo test -p intcode
+invokeCargo build -p intcode --lib --no-default-features
+invokeCargo test -p intcode --lib --no-default-features